@@ -0,0 +1,412 @@
+//! Entropy coding backends for the compressed Adus.
+//!
+//! Both backends start from the same [`FenwickModel`] contexts. The arithmetic coder adapts the
+//! model with every symbol, while the rANS coder codes with quantized frequency tables that are
+//! only rebuilt periodically, trading a little compression for much cheaper symbol lookups.
+
+use crate::codec::compressed::fenwick::context_switching::FenwickModel;
+use crate::codec::CodecError;
+use arithmetic_coding_adder_dep::{Decoder, Encoder};
+use bitstream_io::{BigEndian, BitRead, BitReader, BitWrite, BitWriter};
+use std::io::Cursor;
+
+/// Codes symbols to a stream, according to an adaptive source model
+pub(crate) trait EntropyEncoder {
+    /// The estimated number of bits spent coding the symbols of the given context, if tracked
+    fn context_bits(&self, context: usize) -> Option<f64>;
+
+    /// Switch the context of the source model
+    fn set_context(&mut self, context: usize);
+
+    /// Code a symbol, or the EOF symbol if `None`
+    fn encode(
+        &mut self,
+        symbol: Option<&usize>,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    ) -> Result<(), CodecError>;
+
+    /// Write out any buffered state. Must be called after the last symbol is coded.
+    fn flush(&mut self, stream: &mut BitWriter<Vec<u8>, BigEndian>) -> Result<(), CodecError>;
+}
+
+/// Decodes symbols from a stream, according to an adaptive source model
+pub(crate) trait EntropyDecoder {
+    /// Switch the context of the source model
+    fn set_context(&mut self, context: usize);
+
+    /// Decode the next symbol. Returns `None` for the EOF symbol.
+    fn decode(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) -> Result<Option<usize>, CodecError>;
+}
+
+/// The arithmetic encoder, operating on an Adu's byte stream
+pub(crate) type ArithmeticEncoder = Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>;
+
+/// The arithmetic decoder, operating on an Adu's byte stream
+pub(crate) type ArithmeticDecoder = Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>;
+
+impl EntropyEncoder for ArithmeticEncoder {
+    fn context_bits(&self, context: usize) -> Option<f64> {
+        self.model.context_bits(context)
+    }
+
+    fn set_context(&mut self, context: usize) {
+        self.model.set_context(context);
+    }
+
+    fn encode(
+        &mut self,
+        symbol: Option<&usize>,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    ) -> Result<(), CodecError> {
+        Ok(Encoder::encode(self, symbol, stream)?)
+    }
+
+    fn flush(&mut self, stream: &mut BitWriter<Vec<u8>, BigEndian>) -> Result<(), CodecError> {
+        Ok(Encoder::flush(self, stream)?)
+    }
+}
+
+impl EntropyDecoder for ArithmeticDecoder {
+    fn set_context(&mut self, context: usize) {
+        self.model.set_context(context);
+    }
+
+    fn decode(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) -> Result<Option<usize>, CodecError> {
+        Ok(Decoder::decode(self, stream)?)
+    }
+}
+
+/// The lower bound of the normalized rANS state. The state always lies in
+/// `[RANS_L, RANS_L << 32)`, and is renormalized 32 bits at a time.
+const RANS_L: u64 = 1 << 31;
+
+/// The quantized frequencies of each context sum to `1 << RANS_SCALE_BITS`. This leaves room for
+/// every symbol of the largest context to keep a nonzero frequency.
+const RANS_SCALE_BITS: u32 = 20;
+
+/// A context's frequency table is first rebuilt after this many of its symbols are coded. The
+/// interval doubles after each rebuild, up to [`RANS_MAX_REBUILD_INTERVAL`].
+const RANS_MIN_REBUILD_INTERVAL: u32 = 16;
+
+/// The most symbols a context codes between rebuilds of its frequency table
+const RANS_MAX_REBUILD_INTERVAL: u32 = 1024;
+
+/// The symbol statistics of one context.
+///
+/// The counts adapt with every symbol, but the quantized table that the symbols are coded with
+/// is only rebuilt from them periodically. Coding a symbol is then a table lookup, rather than a
+/// walk of the Fenwick tree.
+#[derive(Debug, Clone)]
+struct RansContext {
+    /// The count of each symbol, with the EOF count first
+    counts: Vec<u64>,
+    total: u64,
+
+    /// The quantized cumulative frequencies. Symbol `i` spans `cumulative[i]..cumulative[i + 1]`.
+    cumulative: Vec<u32>,
+
+    until_rebuild: u32,
+    rebuild_interval: u32,
+}
+
+impl RansContext {
+    fn new(counts: Vec<u64>) -> Self {
+        debug_assert!(counts.len() <= 1 << RANS_SCALE_BITS);
+        let mut context = Self {
+            total: counts.iter().sum(),
+            counts,
+            cumulative: Vec::new(),
+            until_rebuild: 0,
+            rebuild_interval: RANS_MIN_REBUILD_INTERVAL,
+        };
+        context.rebuild();
+        context
+    }
+
+    /// Quantize the counts to a new frequency table. Every symbol gets a frequency of at least
+    /// one, and the rounding error goes to the most frequent symbol.
+    fn rebuild(&mut self) {
+        let scale = 1_u64 << RANS_SCALE_BITS;
+        let spare = scale - self.counts.len() as u64;
+        let mut freqs: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|count| 1 + count * spare / self.total)
+            .collect();
+        let most_frequent = (0..freqs.len())
+            .max_by_key(|&i| freqs[i])
+            .unwrap_or_default();
+        freqs[most_frequent] += scale - freqs.iter().sum::<u64>();
+
+        self.cumulative.clear();
+        self.cumulative.push(0);
+        let mut sum = 0;
+        for freq in freqs {
+            sum += freq as u32;
+            self.cumulative.push(sum);
+        }
+
+        self.until_rebuild = self.rebuild_interval;
+        self.rebuild_interval = (self.rebuild_interval * 2).min(RANS_MAX_REBUILD_INTERVAL);
+    }
+
+    /// The start and frequency of the symbol at `index` in the table
+    fn range(&self, index: usize) -> (u64, u64) {
+        let start = self.cumulative[index];
+        (
+            u64::from(start),
+            u64::from(self.cumulative[index + 1] - start),
+        )
+    }
+
+    /// The index of the symbol whose range contains `slot`
+    fn find(&self, slot: u32) -> usize {
+        self.cumulative.partition_point(|&start| start <= slot) - 1
+    }
+
+    fn update(&mut self, index: usize) {
+        self.counts[index] += 1;
+        self.total += 1;
+        self.until_rebuild -= 1;
+        if self.until_rebuild == 0 {
+            self.rebuild();
+        }
+    }
+}
+
+/// The source model of the rANS backend. Each context starts from the counts of the
+/// corresponding [`FenwickModel`] context, and its table is built the first time it's used.
+#[derive(Debug, Clone)]
+struct RansModel {
+    source: FenwickModel,
+    contexts: Vec<Option<RansContext>>,
+    current_context: usize,
+
+    /// The estimated number of bits spent coding the symbols of each context, if tracked
+    bits: Option<Vec<f64>>,
+}
+
+impl RansModel {
+    fn new(source: FenwickModel) -> Self {
+        let num_contexts = source.num_contexts();
+        Self {
+            bits: source.tracks_bits().then(|| vec![0.0; num_contexts]),
+            contexts: vec![None; num_contexts],
+            current_context: 0,
+            source,
+        }
+    }
+
+    fn set_context(&mut self, context: usize) {
+        self.current_context = context;
+    }
+
+    /// The current context, which is built from the source model if it hasn't been used yet
+    fn context(&mut self) -> &mut RansContext {
+        let (source, current_context) = (&self.source, self.current_context);
+        self.contexts[current_context]
+            .get_or_insert_with(|| RansContext::new(source.context_counts(current_context)))
+    }
+
+    /// The index of a symbol in the current context's table
+    fn index(&mut self, symbol: Option<&usize>) -> Result<usize, CodecError> {
+        let index = symbol.map_or(0, |symbol| symbol + 1);
+        if index < self.context().counts.len() {
+            Ok(index)
+        } else {
+            Err(arithmetic_coding_adder_dep::Error::ValueError.into())
+        }
+    }
+
+    /// Count an occurrence of the symbol at `index` in the current context
+    fn update(&mut self, index: usize) {
+        let current_context = self.current_context;
+        let (_, freq) = self.context().range(index);
+        if let Some(bits) = &mut self.bits {
+            bits[current_context] += f64::from(RANS_SCALE_BITS) - (freq as f64).log2();
+        }
+        self.context().update(index);
+    }
+
+    fn context_bits(&self, context: usize) -> Option<f64> {
+        self.bits
+            .as_ref()
+            .and_then(|bits| bits.get(context).copied())
+    }
+}
+
+/// An adaptive rANS encoder.
+///
+/// rANS codes symbols in the reverse order that they're decoded in, so the quantized symbol
+/// ranges are buffered, and only coded when the encoder is flushed.
+#[derive(Debug, Clone)]
+pub(crate) struct RansEncoder {
+    model: RansModel,
+    symbols: Vec<(u64, u64)>,
+}
+
+impl RansEncoder {
+    pub(crate) fn new(model: FenwickModel) -> Self {
+        Self {
+            model: RansModel::new(model),
+            symbols: Vec::new(),
+        }
+    }
+}
+
+impl EntropyEncoder for RansEncoder {
+    fn context_bits(&self, context: usize) -> Option<f64> {
+        self.model.context_bits(context)
+    }
+
+    fn set_context(&mut self, context: usize) {
+        self.model.set_context(context);
+    }
+
+    fn encode(
+        &mut self,
+        symbol: Option<&usize>,
+        _stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    ) -> Result<(), CodecError> {
+        let index = self.model.index(symbol)?;
+        self.symbols.push(self.model.context().range(index));
+        self.model.update(index);
+        Ok(())
+    }
+
+    fn flush(&mut self, stream: &mut BitWriter<Vec<u8>, BigEndian>) -> Result<(), CodecError> {
+        let mut state = RANS_L;
+        let mut words = Vec::new();
+        for &(start, freq) in self.symbols.iter().rev() {
+            if state >= ((RANS_L >> RANS_SCALE_BITS) << 32) * freq {
+                words.push(state as u32);
+                state >>= 32;
+            }
+            state = ((state / freq) << RANS_SCALE_BITS) + (state % freq) + start;
+        }
+        self.symbols.clear();
+
+        // The decoder reads the final state first, then the renormalization words in reverse
+        stream.write(32, (state >> 32) as u32)?;
+        stream.write(32, state as u32)?;
+        for word in words.iter().rev() {
+            stream.write(32, *word)?;
+        }
+        Ok(())
+    }
+}
+
+/// An adaptive rANS decoder
+#[derive(Debug, Clone)]
+pub(crate) struct RansDecoder {
+    model: RansModel,
+
+    /// The rANS state, read from the stream before the first symbol is decoded
+    state: Option<u64>,
+}
+
+impl RansDecoder {
+    pub(crate) fn new(model: FenwickModel) -> Self {
+        Self {
+            model: RansModel::new(model),
+            state: None,
+        }
+    }
+}
+
+impl EntropyDecoder for RansDecoder {
+    fn set_context(&mut self, context: usize) {
+        self.model.set_context(context);
+    }
+
+    fn decode(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) -> Result<Option<usize>, CodecError> {
+        let mut state = match self.state {
+            Some(state) => state,
+            None => {
+                let high: u32 = stream.read(32)?;
+                let low: u32 = stream.read(32)?;
+                (u64::from(high) << 32) | u64::from(low)
+            }
+        };
+
+        let slot = state & ((1 << RANS_SCALE_BITS) - 1);
+        let index = self.model.context().find(slot as u32);
+        let (start, freq) = self.model.context().range(index);
+        state = freq * (state >> RANS_SCALE_BITS) + slot - start;
+        if state < RANS_L {
+            let word: u32 = stream.read(32)?;
+            state = (state << 32) | u64::from(word);
+        }
+        self.state = Some(state);
+
+        self.model.update(index);
+        Ok(index.checked_sub(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::compressed::fenwick::Weights;
+
+    fn test_model() -> (FenwickModel, usize, usize) {
+        let mut model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+        let skewed =
+            model.push_context_with_weights(Weights::new_with_counts(4, &[1000, 10, 1, 1]));
+        let uniform = model.push_context_with_weights(Weights::new(256));
+        (model, skewed, uniform)
+    }
+
+    #[test]
+    fn rans_round_trip() -> Result<(), CodecError> {
+        let symbols: Vec<(bool, usize)> = (0..10_000)
+            .map(|i| (i % 3 == 0, (i * 7919) % 256))
+            .map(|(skewed, s)| (skewed, if skewed { s % 4 } else { s }))
+            .collect();
+
+        let (model, skewed, uniform) = test_model();
+        let mut encoder = RansEncoder::new(model);
+        let mut stream = BitWriter::endian(Vec::new(), BigEndian);
+        for (is_skewed, symbol) in &symbols {
+            encoder.set_context(if *is_skewed { skewed } else { uniform });
+            encoder.encode(Some(symbol), &mut stream)?;
+        }
+        encoder.encode(None, &mut stream)?;
+        encoder.flush(&mut stream)?;
+
+        let (model, skewed, uniform) = test_model();
+        let mut decoder = RansDecoder::new(model);
+        let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
+        for (is_skewed, symbol) in &symbols {
+            decoder.set_context(if *is_skewed { skewed } else { uniform });
+            assert_eq!(decoder.decode(&mut stream)?, Some(*symbol));
+        }
+        assert_eq!(decoder.decode(&mut stream)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rans_table_keeps_every_symbol() {
+        let mut context = RansContext::new(vec![1, 1_000_000, 0, 3]);
+        for _ in 0..3 {
+            assert_eq!(*context.cumulative.last().unwrap(), 1 << RANS_SCALE_BITS);
+            assert!(context.cumulative.windows(2).all(|pair| pair[1] > pair[0]));
+
+            // Code enough symbols to trigger the next rebuild
+            for _ in 0..context.until_rebuild {
+                context.update(2);
+            }
+        }
+        assert!(context.range(2).1 > context.range(3).1);
+    }
+}
@@ -11,6 +11,9 @@ pub struct FenwickModel {
     contexts: Vec<Weights>,
     current_context: usize,
     max_denominator: u64,
+
+    /// The estimated number of bits spent coding the symbols of each context, if tracked
+    bits: Option<Vec<f64>>,
 }
 
 impl FenwickModel {
@@ -28,6 +31,7 @@ impl FenwickModel {
             contexts,
             current_context: 0,
             max_denominator,
+            bits: None,
         }
     }
 
@@ -49,6 +53,33 @@ impl FenwickModel {
         self.current_context = context;
     }
 
+    /// The number of contexts on the stack
+    pub(crate) fn num_contexts(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// The current count of each symbol of the given context, with the EOF count first
+    pub(crate) fn context_counts(&self, context: usize) -> Vec<u64> {
+        self.contexts[context].counts()
+    }
+
+    /// Start estimating the number of bits spent coding the symbols of each context
+    pub fn track_bits(&mut self) {
+        self.bits = Some(vec![0.0; self.contexts.len()]);
+    }
+
+    /// Whether the number of bits spent coding each context is being estimated
+    pub(crate) fn tracks_bits(&self) -> bool {
+        self.bits.is_some()
+    }
+
+    /// The estimated number of bits spent coding the symbols of the given context, if tracked
+    pub fn context_bits(&self, context: usize) -> Option<f64> {
+        self.bits
+            .as_ref()
+            .and_then(|bits| bits.get(context).copied())
+    }
+
     fn context(&self) -> &Weights {
         &self.contexts[self.current_context]
     }
@@ -80,6 +111,15 @@ impl Model for FenwickModel {
     }
 
     fn update(&mut self, symbol: Option<&usize>) {
+        if let Some(bits) = &mut self.bits {
+            // The information content of the symbol, according to the model before it's updated
+            let weights = &self.contexts[self.current_context];
+            let range = weights.range(symbol.copied());
+            if let Some(context_bits) = bits.get_mut(self.current_context) {
+                *context_bits += (weights.total as f64 / (range.end - range.start) as f64).log2();
+            }
+        }
+
         debug_assert!(
             self.denominator() < self.max_denominator,
             "hit max denominator!"
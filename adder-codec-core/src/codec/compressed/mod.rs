@@ -1,7 +1,10 @@
+mod entropy;
 pub mod fenwick;
 mod source_model;
 /// Compressed codec
 pub mod stream;
+/// Round-trip verification of the compressed codec
+pub mod verify;
 
 pub const BLOCK_SIZE_BIG: usize = 64;
 
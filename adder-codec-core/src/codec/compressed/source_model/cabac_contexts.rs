@@ -1,7 +1,7 @@
+use crate::codec::compressed::entropy::EntropyEncoder;
 use crate::codec::compressed::fenwick::context_switching::FenwickModel;
 use crate::codec::compressed::fenwick::Weights;
 use crate::{AbsoluteT, DeltaT, EventCoordless, Intensity, D, D_SHIFT};
-use arithmetic_coding_adder_dep::Encoder;
 use bitstream_io::{BigEndian, BitWrite, BitWriter};
 
 pub struct Contexts {
@@ -226,12 +226,12 @@ pub fn d_residual_default_weights() -> Weights {
 
 pub fn eof_context(
     contexts: &Contexts,
-    encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+    encoder: &mut impl EntropyEncoder,
     stream: &mut BitWriter<Vec<u8>, BigEndian>,
 ) {
     // THIS IS CRUCIAL FOR TESTING
     let eof_context = contexts.eof_context;
-    encoder.model.set_context(eof_context);
+    encoder.set_context(eof_context);
     encoder.encode(None, stream).unwrap();
     encoder.flush(stream).unwrap();
     stream.byte_align().unwrap();
@@ -1,17 +1,24 @@
+use crate::codec::compressed::entropy::{
+    ArithmeticDecoder, ArithmeticEncoder, EntropyDecoder, EntropyEncoder, RansDecoder, RansEncoder,
+};
 use crate::codec::compressed::fenwick::context_switching::FenwickModel;
 use crate::codec::compressed::source_model::cabac_contexts::{eof_context, Contexts};
 use crate::codec::compressed::source_model::event_structure::event_cube::EventCube;
 use crate::codec::compressed::source_model::event_structure::BLOCK_SIZE;
 use crate::codec::compressed::source_model::{ComponentCompression, HandleEvent};
-use crate::codec::CodecError;
+use crate::codec::compressed::stream::AduStats;
+use crate::codec::{CodecError, EntropyCoder};
 use crate::{AbsoluteT, DeltaT, Event, PlaneSize};
-use arithmetic_coding_adder_dep::{Decoder, Encoder};
 use bitstream_io::{BigEndian, BitReader, BitWriter};
 use ndarray::Array2;
 use nestify::nest;
 use std::io::Cursor;
 use std::mem::size_of;
 
+/// The first codec version whose Adus carry the channel decorrelation symbol after their
+/// starting timestamp
+pub(crate) const CHANNEL_DECORRELATION_VERSION: u8 = 4;
+
 nest! {
     #[derive(Clone, Debug, Default)]
     pub struct EventAdu {
@@ -80,42 +87,130 @@ impl EventAdu {
         }
     }
 
+    /// Compress the Adu to the given stream.
+    ///
+    /// If `channel_decorrelation` is `Some(true)`, the events in color channels 1 and 2 are
+    /// predicted from those in channel 0 at the same pixel. This mode is signaled at the start of
+    /// the Adu. Pass `None` for streams older than codec version 4, which don't carry the signal.
+    ///
+    /// The symbols are entropy coded with the given `entropy_coder`, which the decoder must
+    /// match.
+    ///
+    /// If `track_stats` is set, returns the compression statistics of the Adu. The number of
+    /// bytes written is left for the caller to fill in.
     pub fn compress(
         &mut self,
         stream: &mut BitWriter<Vec<u8>, BigEndian>,
         c_thresh_max: u8,
-    ) -> Result<(), CodecError> {
+        channel_decorrelation: Option<bool>,
+        entropy_coder: EntropyCoder,
+        track_stats: bool,
+    ) -> Result<Option<AduStats>, CodecError> {
         // Create a new source model instance
         let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
         let contexts = Contexts::new(&mut source_model, self.dt_ref);
+        if track_stats {
+            source_model.track_bits();
+        }
 
-        let mut encoder = Encoder::new(source_model);
+        match entropy_coder {
+            EntropyCoder::Arithmetic => self.compress_with(
+                &mut ArithmeticEncoder::new(source_model),
+                &contexts,
+                stream,
+                c_thresh_max,
+                channel_decorrelation,
+                track_stats,
+            ),
+            EntropyCoder::Rans => self.compress_with(
+                &mut RansEncoder::new(source_model),
+                &contexts,
+                stream,
+                c_thresh_max,
+                channel_decorrelation,
+                track_stats,
+            ),
+        }
+    }
+
+    fn compress_with(
+        &mut self,
+        encoder: &mut impl EntropyEncoder,
+        contexts: &Contexts,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+        c_thresh_max: u8,
+        channel_decorrelation: Option<bool>,
+        track_stats: bool,
+    ) -> Result<Option<AduStats>, CodecError> {
+        let mut stats = track_stats.then(|| AduStats {
+            start_t: self.start_t,
+            cube_count: self.event_cubes.len(),
+            ..Default::default()
+        });
 
         // Write out the starting timestamp of the Adu
-        encoder.model.set_context(contexts.t_context);
+        encoder.set_context(contexts.t_context);
         for byte in self.start_t.to_be_bytes().iter() {
-            encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+            encoder.encode(Some(&(*byte as usize)), stream)?;
+        }
+
+        // Write out the channel prediction mode of the Adu, if the stream version carries it
+        if let Some(channel_decorrelation) = channel_decorrelation {
+            encoder.set_context(contexts.bitshift_context);
+            encoder.encode(Some(&(channel_decorrelation as usize)), stream)?;
         }
+        let channel_decorrelation = channel_decorrelation.unwrap_or(false);
 
         for cube in self.event_cubes.iter_mut() {
             debug_assert_eq!(cube.start_t, self.start_t);
-            cube.compress_intra(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
+            cube.channel_decorrelation = channel_decorrelation;
+            cube.compress_intra(encoder, contexts, stream, Some(c_thresh_max))?;
         }
 
         for cube in self.event_cubes.iter_mut() {
             debug_assert_eq!(cube.start_t, self.start_t);
-            cube.compress_inter(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
+            cube.compress_inter(encoder, contexts, stream, Some(c_thresh_max))?;
+
+            if let Some(stats) = &mut stats {
+                if cube.skip_cube {
+                    stats.skipped_cubes += 1;
+                } else {
+                    stats.event_count += cube
+                        .raw_event_lists
+                        .iter()
+                        .flatten()
+                        .flatten()
+                        .map(Vec::len)
+                        .sum::<usize>();
+                }
+                stats.t_residual_count += cube.t_residual_count;
+                stats.t_residual_full_count += cube.t_residual_full_count;
+            }
         }
 
         // Flush the encoder
-        eof_context(&contexts, &mut encoder, stream);
+        eof_context(contexts, encoder, stream);
+
+        if let Some(stats) = &mut stats {
+            let context_bits = |context| encoder.context_bits(context).unwrap_or_default();
+            stats.d_bits = context_bits(contexts.d_context);
+            stats.t_bits =
+                context_bits(contexts.t_context) + context_bits(contexts.bitshift_context);
+        }
 
         self.clear_compression();
 
-        Ok(())
+        Ok(stats)
     }
 
-    pub fn decompress(&mut self, stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>) {
+    /// Decompress the Adu from the given stream, which was written with codec version
+    /// `codec_version` and entropy coded with `entropy_coder`
+    pub fn decompress(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+        codec_version: u8,
+        entropy_coder: EntropyCoder,
+    ) -> Result<(), CodecError> {
         self.clear_decompression();
 
         // let mut adu = Self::new(plane, start_t, dt_ref, num_intervals);
@@ -123,21 +218,59 @@ impl EventAdu {
         // Create a new source model instance
         let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
         let contexts = Contexts::new(&mut source_model, self.dt_ref);
-        let mut decoder = Decoder::new(source_model);
 
+        match entropy_coder {
+            EntropyCoder::Arithmetic => self.decompress_with(
+                &mut ArithmeticDecoder::new(source_model),
+                &contexts,
+                stream,
+                codec_version,
+            ),
+            EntropyCoder::Rans => self.decompress_with(
+                &mut RansDecoder::new(source_model),
+                &contexts,
+                stream,
+                codec_version,
+            ),
+        }
+    }
+
+    fn decompress_with(
+        &mut self,
+        decoder: &mut impl EntropyDecoder,
+        contexts: &Contexts,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+        codec_version: u8,
+    ) -> Result<(), CodecError> {
         // Read the starting timestamp of the Adu
-        decoder.model.set_context(contexts.t_context);
+        decoder.set_context(contexts.t_context);
         let mut start_t = [0u8; size_of::<AbsoluteT>()];
 
         for byte in start_t.iter_mut() {
-            *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+            *byte = decoder.decode(stream)?.ok_or(CodecError::Deserialize)? as u8;
+        }
+
+        // Read the channel prediction mode of the Adu. Older streams don't carry it.
+        let channel_decorrelation = if codec_version >= CHANNEL_DECORRELATION_VERSION {
+            decoder.set_context(contexts.bitshift_context);
+            decoder.decode(stream)?.ok_or(CodecError::Deserialize)? == 1
+        } else {
+            false
+        };
+
+        // Take the start time from the Adu itself, rather than counting Adus from the start of
+        // the stream, so that a decoder can join the stream at any Adu
+        self.start_t = AbsoluteT::from_be_bytes(start_t);
+        for cube in self.event_cubes.iter_mut() {
+            cube.start_t = self.start_t;
+            cube.channel_decorrelation = channel_decorrelation;
         }
 
         for block_idx_y in 0..self.event_cubes.nrows() {
             for block_idx_x in 0..self.event_cubes.ncols() {
                 self.event_cubes[[block_idx_y, block_idx_x]].decompress_intra(
-                    &mut decoder,
-                    &contexts,
+                    decoder,
+                    contexts,
                     stream,
                     self.start_t,
                 );
@@ -150,11 +283,8 @@ impl EventAdu {
 
         for block_idx_y in 0..self.event_cubes.nrows() {
             for block_idx_x in 0..self.event_cubes.ncols() {
-                self.event_cubes[[block_idx_y, block_idx_x]].decompress_inter(
-                    &mut decoder,
-                    &contexts,
-                    stream,
-                );
+                self.event_cubes[[block_idx_y, block_idx_x]]
+                    .decompress_inter(decoder, contexts, stream);
                 debug_assert_eq!(
                     self.event_cubes[[block_idx_y, block_idx_x]].start_t,
                     self.start_t
@@ -163,11 +293,30 @@ impl EventAdu {
         }
         self.state = AduState::Decompressed;
         self.first_run = false;
+        Ok(())
     }
 
     pub fn decoder_is_empty(&self) -> bool {
         self.state == AduState::Empty
     }
+
+    /// Whether ingesting the event keeps it intact, rather than dropping it or coding it with a
+    /// residual error
+    pub(crate) fn holds_losslessly(&self, event: &Event) -> bool {
+        let idx_y = event.coord.y_usize() / BLOCK_SIZE;
+        let idx_x = event.coord.x_usize() / BLOCK_SIZE;
+        self.event_cubes[[idx_y, idx_x]].holds_losslessly(event)
+    }
+
+    /// The events ingested since the Adu was last compressed. Ingesting them, in order, into a
+    /// new Adu with the same start time restores this Adu's state for compression.
+    pub(crate) fn held_events(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        for cube in self.event_cubes.iter() {
+            cube.held_events(&mut events);
+        }
+        events
+    }
 }
 
 impl HandleEvent for EventAdu {
@@ -241,9 +390,11 @@ impl HandleEvent for EventAdu {
 mod tests {
     use crate::codec::compressed::fenwick::context_switching::FenwickModel;
     use crate::codec::compressed::source_model::cabac_contexts::{eof_context, Contexts};
-    use crate::codec::compressed::source_model::event_structure::event_adu::EventAdu;
+    use crate::codec::compressed::source_model::event_structure::event_adu::{
+        EventAdu, CHANNEL_DECORRELATION_VERSION,
+    };
     use crate::codec::compressed::source_model::{ComponentCompression, HandleEvent};
-    use crate::codec::CodecError;
+    use crate::codec::{CodecError, EntropyCoder, LATEST_CODEC_VERSION};
     use crate::{Coord, Event, PlaneSize};
     use arithmetic_coding_adder_dep::Encoder;
     use bitstream_io::{BigEndian, BitReader, BitWriter};
@@ -309,11 +460,12 @@ mod tests {
         let bufwriter = Vec::new();
         let mut stream = BitWriter::endian(bufwriter, BigEndian);
 
-        compress_test(&mut adu, &mut stream, 0)?;
+        // Write the Adu in the v3 layout, which predates the channel decorrelation symbol
+        compress_test(&mut adu, &mut stream, 0, 3)?;
 
         let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
         let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals);
-        adu2.decompress(&mut stream);
+        adu2.decompress(&mut stream, 3, EntropyCoder::Arithmetic)?;
 
         assert_eq!(adu.event_cubes.shape(), adu2.event_cubes.shape());
         for (cube1, cube2) in adu.event_cubes.iter().zip(adu2.event_cubes.iter()) {
@@ -344,6 +496,7 @@ mod tests {
         adu: &mut EventAdu,
         stream: &mut BitWriter<Vec<u8>, BigEndian>,
         c_thresh_max: u8,
+        codec_version: u8,
     ) -> Result<(), CodecError> {
         // Create a new source model instance
         let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
@@ -357,6 +510,12 @@ mod tests {
             encoder.encode(Some(&(*byte as usize)), stream).unwrap();
         }
 
+        // Write out the channel prediction mode of the Adu
+        if codec_version >= CHANNEL_DECORRELATION_VERSION {
+            encoder.model.set_context(contexts.bitshift_context);
+            encoder.encode(Some(&0), stream).unwrap();
+        }
+
         for cube in adu.event_cubes.iter_mut() {
             debug_assert_eq!(cube.start_t, adu.start_t);
             cube.compress_intra(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
@@ -409,12 +568,12 @@ mod tests {
         let bufwriter = Vec::new();
         let mut stream = BitWriter::endian(bufwriter, BigEndian);
 
-        compress_test(&mut adu, &mut stream, 0)?;
+        compress_test(&mut adu, &mut stream, 0, LATEST_CODEC_VERSION)?;
 
         let encoded_data = stream.into_writer();
         let mut stream = BitReader::endian(Cursor::new(encoded_data.clone()), BigEndian);
         let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals);
-        adu2.decompress(&mut stream);
+        adu2.decompress(&mut stream, LATEST_CODEC_VERSION, EntropyCoder::Arithmetic)?;
 
         assert_eq!(adu.event_cubes.shape(), adu2.event_cubes.shape());
         let mut pixel_count = 0;
@@ -446,4 +605,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn compress_tiny_adu_rans() -> Result<(), Box<dyn std::error::Error>> {
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let start_t = 0;
+        let dt_ref = 255;
+        let num_intervals = 10;
+
+        let mut adu = EventAdu::new(plane, start_t, dt_ref, num_intervals);
+
+        let mut counter = 0;
+        for y in 0..30 {
+            for x in 0..16 {
+                for _ in 0..3 {
+                    adu.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + counter,
+                        d: 7 + (x % 4) as u8,
+                    });
+                    counter += 1;
+                }
+            }
+        }
+
+        // Compressing clears out the Adu's events, so keep a copy to compare against
+        let expected = adu.clone();
+
+        let mut decoded = Vec::new();
+        for entropy_coder in [EntropyCoder::Arithmetic, EntropyCoder::Rans] {
+            let mut adu = expected.clone();
+            let mut stream = BitWriter::endian(Vec::new(), BigEndian);
+            let stats = adu.compress(&mut stream, 0, Some(false), entropy_coder, true)?;
+            assert!(stats.is_some_and(|stats| stats.event_count > 0));
+
+            let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
+            let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals);
+            adu2.decompress(&mut stream, LATEST_CODEC_VERSION, entropy_coder)?;
+            for (cube1, cube2) in expected.event_cubes.iter().zip(adu2.event_cubes.iter()) {
+                assert_eq!(cube1.raw_event_lists, cube2.raw_event_lists);
+            }
+
+            let mut events = Vec::new();
+            while let Ok(event) = adu2.digest_event() {
+                events.push(event);
+            }
+            decoded.push(events);
+        }
+
+        // Both entropy coders reconstruct exactly the same events
+        assert_eq!(decoded[0], decoded[1]);
+
+        // Statistics are only gathered when asked for
+        let mut stream = BitWriter::endian(Vec::new(), BigEndian);
+        let stats =
+            expected
+                .clone()
+                .compress(&mut stream, 0, Some(false), EntropyCoder::Rans, false)?;
+        assert!(stats.is_none());
+
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use crate::codec::compressed::fenwick::context_switching::FenwickModel;
+use crate::codec::compressed::entropy::{EntropyDecoder, EntropyEncoder};
 use crate::codec::compressed::source_model::cabac_contexts::{
     Contexts, BITSHIFT_ENCODE_FULL, D_RESIDUAL_OFFSET,
 };
@@ -7,7 +7,6 @@ use crate::codec::compressed::source_model::{ComponentCompression, HandleEvent};
 use crate::codec::compressed::{DResidual, TResidual, DRESIDUAL_NO_EVENT, DRESIDUAL_SKIP_CUBE};
 use crate::codec::CodecError;
 use crate::{AbsoluteT, Coord, DeltaT, Event, EventCoordless, PixelAddress, D, D_EMPTY};
-use arithmetic_coding_adder_dep::{Decoder, Encoder};
 use bitstream_io::{BigEndian, BitReader, BitWriter};
 use std::cmp::{max, min};
 use std::collections::VecDeque;
@@ -16,7 +15,9 @@ use std::mem::size_of;
 
 type Pixel = Vec<EventCoordless>;
 
-type EventLists = [[[Pixel; BLOCK_SIZE]; BLOCK_SIZE]; 3];
+type ChannelLists = [[Pixel; BLOCK_SIZE]; BLOCK_SIZE];
+
+type EventLists = [ChannelLists; 3];
 
 #[derive(PartialEq, Debug, Clone, Default)]
 pub struct EventCube {
@@ -43,7 +44,18 @@ pub struct EventCube {
 
     raw_event_memory: [[[EventCoordless; BLOCK_SIZE]; BLOCK_SIZE]; 3],
 
-    skip_cube: bool,
+    pub(crate) skip_cube: bool,
+
+    /// If true, channels 1 and 2 are predicted from channel 0 at the same pixel, rather than
+    /// being coded independently. Only has an effect for 3-channel cubes.
+    pub(crate) channel_decorrelation: bool,
+
+    /// The number of t residuals written in the last compression of the cube
+    pub(crate) t_residual_count: usize,
+
+    /// The number of t residuals written in full (with [`BITSHIFT_ENCODE_FULL`]) in the last
+    /// compression of the cube
+    pub(crate) t_residual_full_count: usize,
 
     decompressed_event_queue: VecDeque<Event>,
 }
@@ -73,9 +85,50 @@ impl EventCube {
             num_intervals,
             raw_event_memory: [[[EventCoordless::default(); BLOCK_SIZE]; BLOCK_SIZE]; 3],
             skip_cube: true,
+            channel_decorrelation: false,
+            t_residual_count: 0,
+            t_residual_full_count: 0,
             decompressed_event_queue: Default::default(),
         }
     }
+
+    /// Whether ingesting the event keeps it intact. Each pixel's events must have strictly
+    /// increasing timestamps, or the later ones can't be coded exactly.
+    pub(crate) fn holds_losslessly(&self, event: &Event) -> bool {
+        let (y, x) = (
+            (event.coord.y - self.start_y) as usize,
+            (event.coord.x - self.start_x) as usize,
+        );
+        self.raw_event_lists[event.coord.c_usize()][y][x]
+            .last()
+            .map_or(true, |last| event.t > last.t)
+    }
+
+    /// Push the events held in the cube to `events`, as absolute events. The events of each pixel
+    /// stay in the order they were ingested.
+    pub(crate) fn held_events(&self, events: &mut Vec<Event>) {
+        for c in 0..self.num_channels {
+            for y in 0..BLOCK_SIZE {
+                for x in 0..BLOCK_SIZE {
+                    for event in self.raw_event_lists[c][y][x].iter() {
+                        events.push(Event {
+                            coord: Coord {
+                                x: x as PixelAddress + self.start_x,
+                                y: y as PixelAddress + self.start_y,
+                                c: if self.num_channels == 1 {
+                                    None
+                                } else {
+                                    Some(c as u8)
+                                },
+                            },
+                            d: event.d,
+                            t: event.t,
+                        });
+                    }
+                }
+            }
+        }
+    }
 }
 
 fn generate_t_prediction(
@@ -162,30 +215,9 @@ impl HandleEvent for EventCube {
             // ));
         } else if self.decompressed_event_queue.is_empty() {
             // Then we need to convert all the cube events back into actual events and queue them up
-            for c in 0..self.num_channels {
-                for y in 0..BLOCK_SIZE {
-                    for x in 0..BLOCK_SIZE {
-                        if !self.raw_event_lists[c][y][x].is_empty() {
-                            for event in self.raw_event_lists[c][y][x].iter() {
-                                let event = Event {
-                                    coord: Coord {
-                                        x: x as PixelAddress + self.start_x,
-                                        y: y as PixelAddress + self.start_y,
-                                        c: if self.num_channels == 1 {
-                                            None
-                                        } else {
-                                            Some(c as u8)
-                                        },
-                                    },
-                                    d: event.d,
-                                    t: event.t,
-                                };
-                                self.decompressed_event_queue.push_back(event);
-                            }
-                        }
-                    }
-                }
-            }
+            let mut events = Vec::new();
+            self.held_events(&mut events);
+            self.decompressed_event_queue.extend(events);
         }
 
         if let Some(event) = self.decompressed_event_queue.pop_front() {
@@ -305,15 +337,34 @@ mod build_tests {
     }
 }
 
+/// Split the event lists into the lists for channel `c` and, if `decorrelate` is set, the lists
+/// for channel 0 which serve as the prediction anchor for the other channels.
+fn split_anchor(
+    lists: &mut EventLists,
+    c: usize,
+    decorrelate: bool,
+) -> (&mut ChannelLists, Option<&ChannelLists>) {
+    let (anchor, rest) = lists.split_at_mut(1);
+    if c == 0 {
+        (&mut anchor[0], None)
+    } else if decorrelate {
+        (&mut rest[c - 1], Some(&anchor[0]))
+    } else {
+        (&mut rest[c - 1], None)
+    }
+}
+
 impl ComponentCompression for EventCube {
     fn compress_intra(
         &mut self,
-        encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+        encoder: &mut impl EntropyEncoder,
         contexts: &Contexts,
         stream: &mut BitWriter<Vec<u8>, BigEndian>,
         _: Option<u8>,
     ) -> Result<(), CodecError> {
-        encoder.model.set_context(contexts.d_context);
+        self.t_residual_count = 0;
+        self.t_residual_full_count = 0;
+        encoder.set_context(contexts.d_context);
         if self.skip_cube {
             // If we're skipping this cube, just encode a NO_EVENT symbol
             let tmp = (DRESIDUAL_SKIP_CUBE + D_RESIDUAL_OFFSET) as usize;
@@ -325,83 +376,76 @@ impl ComponentCompression for EventCube {
         }
 
         let mut init_event: Option<EventCoordless> = None;
-        let mut d_residual = 0;
+        let decorrelate = self.channel_decorrelation && self.num_channels > 1;
+        let start_t = self.start_t;
+        let (mut t_residual_count, mut t_residual_full_count) = (0, 0);
 
         // Intra-code the first event (if present) for each pixel in row-major order
         for c in 0..self.num_channels {
-            self.raw_event_lists[c].iter_mut().for_each(|row| {
-                row.iter_mut().for_each(|pixel| {
-                    encoder.model.set_context(contexts.d_context);
+            let (lists, anchor) = split_anchor(&mut self.raw_event_lists, c, decorrelate);
+            lists.iter_mut().enumerate().for_each(|(y, row)| {
+                row.iter_mut().enumerate().for_each(|(x, pixel)| {
+                    encoder.set_context(contexts.d_context);
 
                     if !pixel.is_empty() {
                         let event = pixel.first_mut().unwrap();
 
-                        if let Some(init) = &mut init_event {
-                            d_residual = event.d as DResidual - init.d as DResidual;
-                            // Write the D residual (relative to the start_d for the first event)
+                        // If channel 0 has an event at this pixel, predict from its (already
+                        // reconstructed) first event. Otherwise, predict from the last event coded.
+                        // The very first event's D is written directly, relative to the cube's start_t.
+                        let reference = match anchor.and_then(|anchor| anchor[y][x].first()) {
+                            Some(anchor_event) => *anchor_event,
+                            None => init_event.unwrap_or(EventCoordless { d: 0, t: start_t }),
+                        };
 
-                            let tmp = (d_residual + D_RESIDUAL_OFFSET) as usize;
-                            encoder.encode(Some(&tmp), stream).unwrap();
-                            //     for byte in d_residual.to_be_bytes().iter() {
-                            //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                            // }
-                        } else {
-                            // Write the first event's D directly
-                            let tmp = (event.d as DResidual + D_RESIDUAL_OFFSET) as usize;
-                            encoder.encode(Some(&tmp), stream).unwrap();
-                            // for byte in (event.d as DResidual).to_be_bytes().iter() {
-                            //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                            // }
-
-                            // Create the init event with t being the start_t of the cube
-                            init_event = Some(EventCoordless {
-                                d: event.d,
-                                t: self.start_t,
-                            })
+                        // Write the D residual
+                        let d_residual = event.d as DResidual - reference.d as DResidual;
+                        let tmp = (d_residual + D_RESIDUAL_OFFSET) as usize;
+                        encoder.encode(Some(&tmp), stream).unwrap();
+                        //     for byte in d_residual.to_be_bytes().iter() {
+                        //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+                        // }
+
+                        // Don't do any special prediction here (yet). Just predict the same t as previously found.
+                        let t_residual_i64 = event.t as i64 - reference.t as i64;
+                        let (bitshift_amt, t_residual) =
+                            contexts.residual_to_bitshift(t_residual_i64);
+                        // contexts.residual_to_bitshift2(
+                        //     reference.t as i64,
+                        //     t_residual_i64,
+                        //     event,
+                        //     reference,
+                        //     self.dt_ref
+                        // );
+
+                        encoder.set_context(contexts.bitshift_context);
+                        for byte in bitshift_amt.to_be_bytes().iter() {
+                            encoder.encode(Some(&(*byte as usize)), stream).unwrap();
                         }
 
-                        if let Some(init) = &mut init_event {
-                            // Don't do any special prediction here (yet). Just predict the same t as previously found.
-                            let t_residual_i64 = event.t as i64 - init.t as i64;
-                            let (bitshift_amt, t_residual) =
-                                contexts.residual_to_bitshift(t_residual_i64);
-                            // contexts.residual_to_bitshift2(
-                            //     init.t as i64,
-                            //     t_residual_i64,
-                            //     event,
-                            //     init,
-                            //     self.dt_ref
-                            // );
+                        encoder.set_context(contexts.t_context);
 
-                            encoder.model.set_context(contexts.bitshift_context);
-                            for byte in bitshift_amt.to_be_bytes().iter() {
+                        t_residual_count += 1;
+                        if bitshift_amt == BITSHIFT_ENCODE_FULL {
+                            t_residual_full_count += 1;
+                            for byte in t_residual.to_be_bytes().iter() {
                                 encoder.encode(Some(&(*byte as usize)), stream).unwrap();
                             }
-
-                            encoder.model.set_context(contexts.t_context);
-
-                            if bitshift_amt == BITSHIFT_ENCODE_FULL {
-                                for byte in t_residual.to_be_bytes().iter() {
-                                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                                }
-                                event.t = (init.t as i64 + t_residual) as AbsoluteT;
-                            } else {
-                                let t_residual = t_residual as TResidual;
-                                for byte in t_residual.to_be_bytes().iter() {
-                                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                                }
-                                // Shift it back for the event, so we base our next prediction on the reconstructed value!
-                                // if bitshift_amt != 0 {
-                                event.t = (init.t as i64
-                                    + ((t_residual as i64) << bitshift_amt as i64))
-                                    as AbsoluteT;
-                            }
-                            debug_assert!(event.t < 2_u32.pow(31));
-
-                            *init = *event;
+                            event.t = (reference.t as i64 + t_residual) as AbsoluteT;
                         } else {
-                            panic!("No init event");
+                            let t_residual = t_residual as TResidual;
+                            for byte in t_residual.to_be_bytes().iter() {
+                                encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+                            }
+                            // Shift it back for the event, so we base our next prediction on the reconstructed value!
+                            // if bitshift_amt != 0 {
+                            event.t = (reference.t as i64
+                                + ((t_residual as i64) << bitshift_amt as i64))
+                                as AbsoluteT;
                         }
+                        debug_assert!(event.t < 2_u32.pow(31));
+
+                        init_event = Some(*event);
                     } else {
                         // Else there's no event for this pixel. Encode a NO_EVENT symbol.
                         let tmp = (DRESIDUAL_NO_EVENT + D_RESIDUAL_OFFSET) as usize;
@@ -413,12 +457,14 @@ impl ComponentCompression for EventCube {
                 })
             })
         }
+        self.t_residual_count += t_residual_count;
+        self.t_residual_full_count += t_residual_full_count;
         Ok(())
     }
 
     fn compress_inter(
         &mut self,
-        encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+        encoder: &mut impl EntropyEncoder,
         contexts: &Contexts,
         stream: &mut BitWriter<Vec<u8>, BigEndian>,
         c_thresh_max: Option<u8>,
@@ -427,56 +473,78 @@ impl ComponentCompression for EventCube {
             return Ok(());
         }
         let c_thresh_max = c_thresh_max.unwrap_or(7);
+        let decorrelate = self.channel_decorrelation && self.num_channels > 1;
+        let (num_intervals, dt_ref, start_t) = (self.num_intervals, self.dt_ref, self.start_t);
+        let (mut t_residual_count, mut t_residual_full_count) = (0, 0);
         for c in 0..self.num_channels {
-            self.raw_event_lists[c].iter_mut().for_each(|row| {
-                row.iter_mut().for_each(|pixel| {
+            let (lists, anchor) = split_anchor(&mut self.raw_event_lists, c, decorrelate);
+            lists.iter_mut().enumerate().for_each(|(y, row)| {
+                row.iter_mut().enumerate().for_each(|(x, pixel)| {
                     if !pixel.is_empty() {
+                        let anchor_pixel = anchor.map(|anchor| &anchor[y][x]);
                         let mut idx = 1;
                         let mut last_delta_t: DeltaT = 0;
                         loop {
-                            encoder.model.set_context(contexts.d_context);
+                            encoder.set_context(contexts.d_context);
 
                             if idx < pixel.len() {
                                 // TODO: don't copy the below event?
                                 let prev_event = pixel[idx - 1]; // We can assume for now that this is perfectly decoded, but later we'll corrupt it according to any loss we incur
                                 let event = &mut pixel[idx];
 
-                                // Get the D residual
-                                let d_residual = event.d as DResidual - prev_event.d as DResidual;
+                                let (d_residual, t_prediction) = match anchor_pixel
+                                    .and_then(|anchor_pixel| anchor_pixel.get(idx))
+                                {
+                                    // Predict D and t directly from channel 0's event with the same index
+                                    Some(anchor_event) => (
+                                        event.d as DResidual - anchor_event.d as DResidual,
+                                        max(prev_event.t, anchor_event.t),
+                                    ),
+                                    None => {
+                                        // Get the D residual
+                                        let d_residual =
+                                            event.d as DResidual - prev_event.d as DResidual;
+                                        (
+                                            d_residual,
+                                            generate_t_prediction(
+                                                idx,
+                                                d_residual,
+                                                last_delta_t,
+                                                &prev_event,
+                                                num_intervals,
+                                                dt_ref,
+                                                start_t,
+                                            ),
+                                        )
+                                    }
+                                };
+
                                 // Write the D residual (relative to the start_d for the first event)
                                 for byte in d_residual.to_be_bytes().iter() {
                                     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
                                 }
 
-                                let t_prediction = generate_t_prediction(
-                                    idx,
-                                    d_residual,
-                                    last_delta_t,
-                                    &prev_event,
-                                    self.num_intervals,
-                                    self.dt_ref,
-                                    self.start_t,
-                                );
-
-                                // encoder.model.set_context(contexts.dtref_context);
+                                // encoder.set_context(contexts.dtref_context);
                                 let t_residual_i64 = event.t as i64 - t_prediction as i64;
                                 let (bitshift_amt, t_residual) = contexts.residual_to_bitshift2(
                                     t_prediction as i64,
                                     t_residual_i64,
                                     event,
                                     &prev_event,
-                                    self.dt_ref,
+                                    dt_ref,
                                     c_thresh_max as f64,
                                 );
 
-                                encoder.model.set_context(contexts.bitshift_context);
+                                encoder.set_context(contexts.bitshift_context);
                                 for byte in bitshift_amt.to_be_bytes().iter() {
                                     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
                                 }
 
-                                encoder.model.set_context(contexts.t_context);
+                                encoder.set_context(contexts.t_context);
 
+                                t_residual_count += 1;
                                 if bitshift_amt == BITSHIFT_ENCODE_FULL {
+                                    t_residual_full_count += 1;
                                     for byte in t_residual.to_be_bytes().iter() {
                                         encoder.encode(Some(&(*byte as usize)), stream).unwrap();
                                     }
@@ -499,7 +567,7 @@ impl ComponentCompression for EventCube {
                                 debug_assert!(event.t >= prev_event.t);
                                 last_delta_t = (event.t - prev_event.t) as DeltaT;
                             } else {
-                                encoder.model.set_context(contexts.d_context);
+                                encoder.set_context(contexts.d_context);
                                 // Else there's no other event for this pixel. Encode a NO_EVENT symbol.
                                 for byte in (DRESIDUAL_NO_EVENT).to_be_bytes().iter() {
                                     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
@@ -513,12 +581,14 @@ impl ComponentCompression for EventCube {
                 })
             })
         }
+        self.t_residual_count += t_residual_count;
+        self.t_residual_full_count += t_residual_full_count;
         Ok(())
     }
 
     fn decompress_intra(
         &mut self,
-        decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+        decoder: &mut impl EntropyDecoder,
         contexts: &Contexts,
         stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
         start_t: AbsoluteT,
@@ -527,13 +597,15 @@ impl ComponentCompression for EventCube {
         let mut t_residual_buffer = [0u8; size_of::<TResidual>()];
         let mut t_residual_full_buffer = [0u8; size_of::<i64>()];
         let mut init_event: Option<EventCoordless> = None;
+        let decorrelate = self.channel_decorrelation && self.num_channels > 1;
 
         for c in 0..self.num_channels {
+            let (lists, anchor) = split_anchor(&mut self.raw_event_lists, c, decorrelate);
             for y in 0..BLOCK_SIZE {
                 for x in 0..BLOCK_SIZE {
-                    let pixel = &mut self.raw_event_lists[c][y][x];
+                    let pixel = &mut lists[y][x];
 
-                    decoder.model.set_context(contexts.d_context);
+                    decoder.set_context(contexts.d_context);
 
                     let tmp = decoder.decode(stream).unwrap().unwrap();
                     let d_residual = tmp as i16 - D_RESIDUAL_OFFSET;
@@ -545,53 +617,48 @@ impl ComponentCompression for EventCube {
                     } else if d_residual == DRESIDUAL_NO_EVENT {
                         pixel.clear(); // So we can skip it for intra-coding
                     } else {
-                        let d = if let Some(init) = &mut init_event {
-                            (init.d as DResidual + d_residual) as D
-                        } else {
-                            // There is no init event
-                            init_event = Some(EventCoordless { d: 0, t: start_t });
-                            self.skip_cube = false;
-                            d_residual as D
+                        // Mirror the encoder's choice of reference event
+                        let reference = match anchor.and_then(|anchor| anchor[y][x].first()) {
+                            Some(anchor_event) => *anchor_event,
+                            None => init_event.unwrap_or(EventCoordless { d: 0, t: start_t }),
                         };
+                        self.skip_cube = false;
 
-                        if let Some(init) = &mut init_event {
-                            // decoder.model.set_context(contexts.dtref_context);
-                            // for byte in dtref_residual_buffer.iter_mut() {
-                            //     *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                            // }
-                            // let dtref_residual = DResidual::from_be_bytes(dtref_residual_buffer);
+                        let d = (reference.d as DResidual + d_residual) as D;
 
-                            decoder.model.set_context(contexts.bitshift_context);
-                            for byte in bitshift_buffer.iter_mut() {
-                                *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                            }
-                            let bitshift_amt = bitshift_buffer[0];
+                        // decoder.set_context(contexts.dtref_context);
+                        // for byte in dtref_residual_buffer.iter_mut() {
+                        //     *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                        // }
+                        // let dtref_residual = DResidual::from_be_bytes(dtref_residual_buffer);
 
-                            let t_residual = if bitshift_amt == BITSHIFT_ENCODE_FULL {
-                                decoder.model.set_context(contexts.t_context);
-                                for byte in t_residual_full_buffer.iter_mut() {
-                                    *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                                }
-                                i64::from_be_bytes(t_residual_full_buffer)
-                            } else {
-                                decoder.model.set_context(contexts.t_context);
-                                for byte in t_residual_buffer.iter_mut() {
-                                    *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                                }
-                                let t_residual = TResidual::from_be_bytes(t_residual_buffer) as i64;
-                                (t_residual) << bitshift_amt as i64
-                            };
+                        decoder.set_context(contexts.bitshift_context);
+                        for byte in bitshift_buffer.iter_mut() {
+                            *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                        }
+                        let bitshift_amt = bitshift_buffer[0];
 
-                            init.d = (init.d as DResidual + d_residual) as D;
+                        let t_residual = if bitshift_amt == BITSHIFT_ENCODE_FULL {
+                            decoder.set_context(contexts.t_context);
+                            for byte in t_residual_full_buffer.iter_mut() {
+                                *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                            }
+                            i64::from_be_bytes(t_residual_full_buffer)
+                        } else {
+                            decoder.set_context(contexts.t_context);
+                            for byte in t_residual_buffer.iter_mut() {
+                                *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                            }
+                            let t_residual = TResidual::from_be_bytes(t_residual_buffer) as i64;
+                            (t_residual) << bitshift_amt as i64
+                        };
 
-                            debug_assert!(init.t as i64 + t_residual >= 0);
-                            init.t = (init.t as i64 + t_residual) as AbsoluteT;
+                        debug_assert!(reference.t as i64 + t_residual >= 0);
+                        let t = (reference.t as i64 + t_residual) as AbsoluteT;
 
-                            // debug_assert!(init.t < start_t + num_intervals as AbsoluteT * dt_ref);
-                            pixel.push(EventCoordless { d, t: init.t });
-                        } else {
-                            panic!("No init event");
-                        }
+                        // debug_assert!(t < start_t + num_intervals as AbsoluteT * dt_ref);
+                        pixel.push(EventCoordless { d, t });
+                        init_event = Some(EventCoordless { d, t });
                     }
                 }
             }
@@ -600,7 +667,7 @@ impl ComponentCompression for EventCube {
 
     fn decompress_inter(
         &mut self,
-        decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+        decoder: &mut impl EntropyDecoder,
         contexts: &Contexts,
         stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
     ) {
@@ -611,16 +678,20 @@ impl ComponentCompression for EventCube {
         let mut t_residual_buffer = [0u8; size_of::<TResidual>()];
         let mut t_residual_full_buffer = [0u8; size_of::<i64>()];
         let mut bitshift_buffer = [0u8; 1];
+        let decorrelate = self.channel_decorrelation && self.num_channels > 1;
+        let (num_intervals, dt_ref, start_t) = (self.num_intervals, self.dt_ref, self.start_t);
 
         for c in 0..self.num_channels {
-            self.raw_event_lists[c].iter_mut().for_each(|row| {
-                row.iter_mut().for_each(|pixel| {
+            let (lists, anchor) = split_anchor(&mut self.raw_event_lists, c, decorrelate);
+            lists.iter_mut().enumerate().for_each(|(y, row)| {
+                row.iter_mut().enumerate().for_each(|(x, pixel)| {
                     if !pixel.is_empty() {
+                        let anchor_pixel = anchor.map(|anchor| &anchor[y][x]);
                         // Then look for the next events for this pixel
                         let mut idx = 1;
                         let mut last_delta_t = 0;
                         loop {
-                            decoder.model.set_context(contexts.d_context);
+                            decoder.set_context(contexts.d_context);
 
                             for byte in d_residual_buffer.iter_mut() {
                                 *byte = decoder.decode(stream).unwrap().unwrap() as u8;
@@ -633,32 +704,40 @@ impl ComponentCompression for EventCube {
                             debug_assert!(idx - 1 < pixel.len());
                             let prev_event = pixel[idx - 1];
 
-                            let d = (prev_event.d as DResidual + d_residual) as D;
-
-                            let t_prediction = generate_t_prediction(
-                                idx,
-                                d_residual,
-                                last_delta_t,
-                                &prev_event,
-                                self.num_intervals,
-                                self.dt_ref,
-                                self.start_t,
-                            );
+                            let (d, t_prediction) =
+                                match anchor_pixel.and_then(|anchor_pixel| anchor_pixel.get(idx)) {
+                                    Some(anchor_event) => (
+                                        (anchor_event.d as DResidual + d_residual) as D,
+                                        max(prev_event.t, anchor_event.t),
+                                    ),
+                                    None => (
+                                        (prev_event.d as DResidual + d_residual) as D,
+                                        generate_t_prediction(
+                                            idx,
+                                            d_residual,
+                                            last_delta_t,
+                                            &prev_event,
+                                            num_intervals,
+                                            dt_ref,
+                                            start_t,
+                                        ),
+                                    ),
+                                };
 
-                            decoder.model.set_context(contexts.bitshift_context);
+                            decoder.set_context(contexts.bitshift_context);
                             for byte in bitshift_buffer.iter_mut() {
                                 *byte = decoder.decode(stream).unwrap().unwrap() as u8;
                             }
                             let bitshift_amt = bitshift_buffer[0];
 
                             let t_residual = if bitshift_amt == BITSHIFT_ENCODE_FULL {
-                                decoder.model.set_context(contexts.t_context);
+                                decoder.set_context(contexts.t_context);
                                 for byte in t_residual_full_buffer.iter_mut() {
                                     *byte = decoder.decode(stream).unwrap().unwrap() as u8;
                                 }
                                 i64::from_be_bytes(t_residual_full_buffer)
                             } else {
-                                decoder.model.set_context(contexts.t_context);
+                                decoder.set_context(contexts.t_context);
                                 for byte in t_residual_buffer.iter_mut() {
                                     *byte = decoder.decode(stream).unwrap().unwrap() as u8;
                                 }
@@ -1057,4 +1136,70 @@ mod compression_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn compress_and_decompress_decorrelated_channels() -> Result<(), Box<dyn Error>> {
+        let num_intervals = 10;
+        let mut cube = EventCube::new(0, 0, 3, 255, 255, num_intervals);
+        cube.channel_decorrelation = true;
+        let mut rng = StdRng::seed_from_u64(1234);
+
+        for y in 0..16 {
+            for x in 0..16 {
+                // Leave some pixels without an anchor event in channel 0
+                let anchored = rng.gen_bool(0.8);
+                let mut t = 255 + rng.gen_range(0..255);
+                for _ in 0..rng.gen_range(1..5) {
+                    let d = rng.gen_range(4..12);
+                    for c in 0..3 {
+                        if c == 0 && !anchored {
+                            continue;
+                        }
+                        cube.ingest_event(Event {
+                            coord: Coord { x, y, c: Some(c) },
+                            t: min(
+                                t + rng.gen_range(0..3),
+                                cube.start_t + (cube.num_intervals as u32 - 1) * cube.dt_ref,
+                            ),
+                            d: d + rng.gen_range(0..2),
+                        });
+                    }
+                    t += rng.gen_range(3..300);
+                }
+            }
+        }
+
+        let bufwriter = Vec::new();
+        let mut stream = BitWriter::endian(bufwriter, BigEndian);
+
+        let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+        let contexts = crate::codec::compressed::source_model::cabac_contexts::Contexts::new(
+            &mut source_model,
+            255,
+        );
+
+        let mut encoder = Encoder::new(source_model);
+
+        cube.compress_intra(&mut encoder, &contexts, &mut stream, Some(0))?;
+        cube.compress_inter(&mut encoder, &contexts, &mut stream, Some(0))?;
+
+        eof_context(&contexts, &mut encoder, &mut stream);
+
+        let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+        let contexts = crate::codec::compressed::source_model::cabac_contexts::Contexts::new(
+            &mut source_model,
+            255,
+        );
+        let mut decoder = arithmetic_coding_adder_dep::Decoder::new(source_model);
+        let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
+
+        let mut cube2 = EventCube::new(0, 0, 3, 255, 255, num_intervals);
+        cube2.channel_decorrelation = true;
+        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255);
+        cube2.decompress_inter(&mut decoder, &contexts, &mut stream);
+
+        assert_eq!(cube.raw_event_lists, cube2.raw_event_lists);
+
+        Ok(())
+    }
 }
@@ -6,11 +6,13 @@ use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::ops::{Add, AddAssign};
 use std::sync::{Arc, RwLock};
 
-use crate::codec::compressed::source_model::event_structure::event_adu::EventAdu;
+use crate::codec::compressed::source_model::event_structure::event_adu::{
+    EventAdu, CHANNEL_DECORRELATION_VERSION,
+};
 use crate::codec::compressed::source_model::HandleEvent;
 use crate::codec::header::{Magic, MAGIC_COMPRESSED};
 use crate::codec::rate_controller::CrfParameters;
-use crate::{DeltaT, Event};
+use crate::{AbsoluteT, DeltaT, Event};
 
 /// A message to send to the writer thread (that is, the main thread) to write out the compressed
 /// ADΔER data to the stream
@@ -19,6 +21,48 @@ pub(crate) struct BytesMessage {
     bytes: Vec<u8>,
 }
 
+/// Compression statistics for a single ADU
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AduStats {
+    /// The start time of the ADU
+    pub start_t: AbsoluteT,
+
+    /// The number of bytes written for the ADU, not including its 4-byte length prefix
+    pub bytes_written: usize,
+
+    /// The number of events coded in the ADU
+    pub event_count: usize,
+
+    /// The number of cubes in the ADU
+    pub cube_count: usize,
+
+    /// The number of cubes which had no events, and were coded with a single skip symbol
+    pub skipped_cubes: usize,
+
+    /// The estimated number of bits spent on D residuals
+    pub d_bits: f64,
+
+    /// The estimated number of bits spent on t residuals, including their bitshift amounts
+    pub t_bits: f64,
+
+    /// The number of t residuals coded
+    pub t_residual_count: usize,
+
+    /// The number of t residuals coded in full, rather than as a (bitshifted) 16-bit residual
+    pub t_residual_full_count: usize,
+}
+
+impl AduStats {
+    /// The share of t residuals coded in full, in [0, 1]
+    pub fn t_residual_full_share(&self) -> f64 {
+        if self.t_residual_count == 0 {
+            0.0
+        } else {
+            self.t_residual_full_count as f64 / self.t_residual_count as f64
+        }
+    }
+}
+
 /// Write compressed ADΔER data to a stream.
 pub struct CompressedOutput<W: Write> {
     pub(crate) meta: CodecMetadata,
@@ -34,6 +78,9 @@ pub struct CompressedOutput<W: Write> {
     /// The ID of the last message received in the writer thread and actually written out the stream
     pub(crate) last_message_written: Arc<RwLock<u32>>,
 
+    /// Where to send the statistics of each compressed ADU, if anywhere
+    pub(crate) stats_tx: Option<std::sync::mpsc::Sender<AduStats>>,
+
     pub(crate) _phantom: std::marker::PhantomData<W>,
 }
 
@@ -102,6 +149,19 @@ fn flush_bytes_queue_worker<W: Write>(
     eprintln!("Exiting writer thread...");
 }
 
+/// Send the statistics of a compressed ADU to the listener, if there is one
+fn send_stats(
+    stats_tx: Option<std::sync::mpsc::Sender<AduStats>>,
+    stats: Option<AduStats>,
+    bytes_written: usize,
+) {
+    if let (Some(stats_tx), Some(mut stats)) = (stats_tx, stats) {
+        stats.bytes_written = bytes_written;
+        // The receiver may have been dropped, in which case nobody's listening
+        stats_tx.send(stats).ok();
+    }
+}
+
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutput<W> {
     /// Create a new compressed output stream.
     pub fn new(meta: CodecMetadata, writer: W) -> Self {
@@ -136,6 +196,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutpu
             // bytes_writer_queue: PriorityQueue::new(),
             last_message_sent: 0,
             last_message_written,
+            stats_tx: None,
             _phantom: Default::default(),
         }
     }
@@ -145,6 +206,14 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutpu
         self.options = options;
     }
 
+    /// Get a receiver for the compression statistics of each ADU written from now on. Replaces
+    /// any previously created receiver.
+    pub fn stats_receiver(&mut self) -> std::sync::mpsc::Receiver<AduStats> {
+        let (stats_tx, stats_rx) = std::sync::mpsc::channel();
+        self.stats_tx = Some(stats_tx);
+        stats_rx
+    }
+
     /// Convenience function to get a mutable reference to the underlying stream.
     #[inline(always)]
     pub(crate) fn stream(&mut self) -> &mut Arc<RwLock<BitWriter<W, BigEndian>>> {
@@ -152,6 +221,50 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutpu
     }
 }
 
+impl<W: Write + Seek + std::marker::Send + std::marker::Sync + 'static> CompressedOutput<W> {
+    /// Wait for the compressed ADUs to be written out, then flush the stream. Returns the number
+    /// of bytes in the stream, and the start time and events of the partial ADU.
+    pub(crate) fn checkpoint(&mut self) -> Result<(u64, AbsoluteT, Vec<Event>), CodecError> {
+        while self.last_message_sent != *self.last_message_written.read().unwrap() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let output_len = {
+            let mut stream = self.stream().write().unwrap();
+            stream.flush()?;
+            match stream.writer() {
+                Some(writer) => writer.stream_position()?,
+                None => return Err(CodecError::MalformedEncoder),
+            }
+        };
+        Ok((output_len, self.adu.start_t, self.adu.held_events()))
+    }
+
+    /// Move to the end of the checkpointed stream, and restore the partial ADU
+    pub(crate) fn resume(
+        &mut self,
+        output_len: u64,
+        adu_start_t: AbsoluteT,
+        events: &[Event],
+    ) -> Result<(), CodecError> {
+        match self.stream().write().unwrap().writer() {
+            Some(writer) => writer.seek(SeekFrom::Start(output_len))?,
+            None => return Err(CodecError::MalformedEncoder),
+        };
+
+        self.adu = EventAdu::new(
+            self.meta.plane,
+            adu_start_t,
+            self.meta.ref_interval,
+            self.meta.adu_interval,
+        );
+        for event in events {
+            self.adu.ingest_event(*event);
+        }
+        Ok(())
+    }
+}
+
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'static>
     WriteCompression<W> for CompressedOutput<W>
 {
@@ -204,16 +317,35 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
             let mut temp_stream = BitWriter::endian(Vec::new(), BigEndian);
 
             let parameters = self.options.crf.get_parameters().clone();
+            let c_thresh_max = if self.meta.lossless {
+                0
+            } else {
+                parameters.c_thresh_max
+            };
+            let channel_decorrelation = (self.meta.codec_version >= CHANNEL_DECORRELATION_VERSION)
+                .then_some(self.options.channel_decorrelation);
+            let entropy_coder = self.meta.entropy_coder;
             let mut adu = self.adu.clone();
             let tx = self.written_bytes_tx.as_ref().unwrap().clone();
+            let stats_tx = self.stats_tx.clone();
             // Spawn a thread to compress the ADU and write out the data
 
             let message_id_to_send = self.last_message_sent + 1;
             self.last_message_sent += 1;
 
             std::thread::spawn(move || {
-                adu.compress(&mut temp_stream, parameters.c_thresh_max).ok();
+                let stats = adu
+                    .compress(
+                        &mut temp_stream,
+                        c_thresh_max,
+                        channel_decorrelation,
+                        entropy_coder,
+                        stats_tx.is_some(),
+                    )
+                    .ok()
+                    .flatten();
                 let written_data = temp_stream.into_writer();
+                send_stats(stats_tx, stats, written_data.len());
 
                 tx.send(BytesMessage {
                     message_id: message_id_to_send,
@@ -289,19 +421,39 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
                 let mut temp_stream = BitWriter::endian(Vec::new(), BigEndian);
 
                 let parameters = self.options.crf.get_parameters().clone();
+                let c_thresh_max = if self.meta.lossless {
+                    0
+                } else {
+                    parameters.c_thresh_max
+                };
+                let channel_decorrelation = (self.meta.codec_version
+                    >= CHANNEL_DECORRELATION_VERSION)
+                    .then_some(self.options.channel_decorrelation);
+                let entropy_coder = self.meta.entropy_coder;
 
                 // Compress the Adu. This also writes the EOF symbol and flushes the encoder
                 // First, clone the ADU
                 let mut adu = self.adu.clone();
                 let tx = self.written_bytes_tx.as_ref().unwrap().clone();
+                let stats_tx = self.stats_tx.clone();
                 // Spawn a thread to compress the ADU and write out the data
 
                 let message_id_to_send = self.last_message_sent + 1;
                 self.last_message_sent += 1;
 
                 std::thread::spawn(move || {
-                    adu.compress(&mut temp_stream, parameters.c_thresh_max).ok();
+                    let stats = adu
+                        .compress(
+                            &mut temp_stream,
+                            c_thresh_max,
+                            channel_decorrelation,
+                            entropy_coder,
+                            stats_tx.is_some(),
+                        )
+                        .ok()
+                        .flatten();
                     let written_data = temp_stream.into_writer();
+                    send_stats(stats_tx, stats, written_data.len());
 
                     tx.send(BytesMessage {
                         message_id: message_id_to_send,
@@ -314,6 +466,11 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
             }
         }
 
+        // A lossless stream can't hold an event that the Adu would drop
+        if self.meta.lossless && !self.adu.holds_losslessly(&event) {
+            return Err(CodecError::UnorderedEvent(event));
+        }
+
         // Ingest the event in the Adu
         let _ = self.adu.ingest_event(event);
 
@@ -347,6 +504,8 @@ impl<R: Read> CompressedInput<R> {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             adu: None,
             _phantom: std::marker::PhantomData,
@@ -405,7 +564,11 @@ impl<R: Read + Seek> ReadCompression<R> for CompressedInput<R> {
                 let mut adu_stream = BitReader::endian(Cursor::new(adu_bytes), BigEndian);
 
                 // Decompress the Adu
-                adu.decompress(&mut adu_stream);
+                adu.decompress(
+                    &mut adu_stream,
+                    self.meta.codec_version,
+                    self.meta.entropy_coder,
+                )?;
 
                 let duration = start.elapsed();
                 println!("Decompressed Adu in {:?} ns", duration.as_nanos());
@@ -482,6 +645,8 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             Cursor::new(Vec::new()),
         );
@@ -543,6 +708,8 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             Cursor::new(Vec::new()),
         );
@@ -646,6 +813,8 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             Cursor::new(Vec::new()),
         );
@@ -734,6 +903,8 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             Cursor::new(Vec::new()),
         );
@@ -852,6 +1023,8 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             Cursor::new(Vec::new()),
         );
@@ -949,4 +1122,80 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_adu_stats() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::{AduStats, CompressedOutput};
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 0,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane: PlaneSize {
+                    width: 16,
+                    height: 32,
+                    channels: 1,
+                },
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                lossless: false,
+                entropy_coder: Default::default(),
+            },
+            Cursor::new(Vec::new()),
+        );
+        let stats_rx = compressed_output.stats_receiver();
+
+        let mut counter = 0;
+        for _ in 0..10 {
+            // Only fill the top cube, so that the bottom cube gets skipped
+            for y in 0..16 {
+                for x in 0..16 {
+                    compressed_output.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + counter,
+                        d: 7,
+                    })?;
+                    counter += 1;
+                }
+            }
+        }
+
+        let output = compressed_output.into_writer().unwrap().into_inner();
+        let stats: Vec<AduStats> = stats_rx.try_iter().collect();
+        assert!(stats.len() > 1);
+
+        // Each ADU is prefixed with its 4-byte length
+        assert_eq!(
+            stats.iter().map(|s| s.bytes_written + 4).sum::<usize>(),
+            output.len()
+        );
+        assert_eq!(
+            stats.iter().map(|s| s.event_count).sum::<usize>(),
+            counter as usize
+        );
+
+        let mut stats = stats;
+        stats.sort_by_key(|s| s.start_t);
+        for adu_stats in stats.iter().filter(|s| s.event_count > 0) {
+            assert_eq!(adu_stats.cube_count, 2);
+            assert_eq!(adu_stats.skipped_cubes, 1);
+            assert!(adu_stats.d_bits > 0.0);
+            assert!(adu_stats.t_bits > 0.0);
+            assert_eq!(adu_stats.t_residual_count, adu_stats.event_count);
+            assert!(adu_stats.t_residual_full_share() <= 1.0);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,183 @@
+use crate::codec::compressed::stream::{CompressedInput, CompressedOutput};
+use crate::codec::decoder::Decoder;
+use crate::codec::encoder::Encoder;
+use crate::codec::{CodecError, CodecMetadata, EncoderOptions, LATEST_CODEC_VERSION};
+use crate::Event;
+use bitstream_io::{BigEndian, BitReader};
+use std::io;
+use std::io::Cursor;
+
+/// The first difference between a set of ingested events and the events decoded from them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch {
+    /// The index of the mismatch, with both sets of events sorted in a canonical order
+    pub index: usize,
+
+    /// The ingested event at this index, if there is one
+    pub expected: Option<Event>,
+
+    /// The decoded event at this index, if there is one
+    pub found: Option<Event>,
+}
+
+/// Compress the given events with the given metadata and options, decompress them, and compare
+/// the decoded events to the ingested events. The order of the events is ignored.
+///
+/// Returns the first mismatch, or `None` if the round trip is exact. Note that the events must
+/// have absolute timestamps, and each pixel's events must be in increasing order of `t`. With
+/// `options.lossless` set, an event that breaks this order is returned as
+/// [`CodecError::UnorderedEvent`].
+pub fn verify_round_trip(
+    meta: CodecMetadata,
+    options: EncoderOptions,
+    events: &[Event],
+) -> Result<Option<Mismatch>, CodecError> {
+    let meta = CodecMetadata {
+        codec_version: LATEST_CODEC_VERSION,
+        ..meta
+    };
+    let compression = CompressedOutput::new(meta, Vec::new());
+    let mut encoder = Encoder::new_compressed(compression, options);
+    encoder.ingest_events(events)?;
+    let written = encoder
+        .close_writer()?
+        .ok_or(CodecError::UnitializedStream)?;
+
+    let compression = CompressedInput::new(meta.delta_t_max, meta.ref_interval, meta.adu_interval);
+    let mut bitreader = BitReader::endian(Cursor::new(written), BigEndian);
+    let mut decoder = Decoder::new_compressed(compression, &mut bitreader)?;
+
+    let mut decoded = Vec::with_capacity(events.len());
+    loop {
+        match decoder.digest_event(&mut bitreader) {
+            Ok(event) => decoded.push(event),
+            Err(CodecError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(first_mismatch(events.to_vec(), decoded))
+}
+
+/// Find the first difference between two sets of events, ignoring their order
+fn first_mismatch(mut expected: Vec<Event>, mut found: Vec<Event>) -> Option<Mismatch> {
+    let key = |event: &Event| {
+        (
+            event.t,
+            event.coord.c_usize(),
+            event.coord.y,
+            event.coord.x,
+            event.d,
+        )
+    };
+    expected.sort_unstable_by_key(key);
+    found.sort_unstable_by_key(key);
+
+    (0..expected.len().max(found.len())).find_map(|index| {
+        let (expected, found) = (expected.get(index).copied(), found.get(index).copied());
+        if expected == found {
+            None
+        } else {
+            Some(Mismatch {
+                index,
+                expected,
+                found,
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Coord, PlaneSize, SourceCamera, TimeMode};
+
+    fn test_events() -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut t = 280;
+        for _ in 0..10 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t,
+                        d: 7 + (x % 3) as u8,
+                    });
+                    t += 1;
+                }
+            }
+        }
+        events
+    }
+
+    fn test_meta(plane: PlaneSize) -> CodecMetadata {
+        CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            header_size: 0,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 30,
+            event_size: 0,
+            source_camera: SourceCamera::FramedU8,
+            adu_interval: 30,
+            lossless: false,
+            entropy_coder: Default::default(),
+        }
+    }
+
+    #[test]
+    fn lossless_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let plane = PlaneSize::new(16, 16, 1)?;
+        let meta = test_meta(plane);
+
+        // Even at the lowest quality, the lossless option overrides any loss
+        let mut options = EncoderOptions::default(plane);
+        options.crf = crate::codec::rate_controller::Crf::new(Some(9), plane);
+        options.lossless = true;
+
+        assert_eq!(verify_round_trip(meta, options, &test_events())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn report_first_mismatch() {
+        let expected = test_events();
+        let mut found = expected.clone();
+        found.reverse();
+        assert_eq!(first_mismatch(expected.clone(), found.clone()), None);
+
+        found[10].d += 1;
+        let mismatch = first_mismatch(expected.clone(), found.clone()).unwrap();
+        assert_eq!(mismatch.expected, Some(expected[expected.len() - 11]));
+
+        // The earliest event is now missing, so everything after it is shifted
+        found.pop();
+        let mismatch = first_mismatch(expected.clone(), found).unwrap();
+        assert_eq!(mismatch.index, 0);
+        assert_eq!(mismatch.expected, Some(expected[0]));
+    }
+
+    #[test]
+    fn lossless_rejects_unordered_events() -> Result<(), Box<dyn std::error::Error>> {
+        let plane = PlaneSize::new(16, 16, 1)?;
+        let meta = test_meta(plane);
+        let mut options = EncoderOptions::default(plane);
+        options.lossless = true;
+
+        // Repeat a pixel's timestamp a few events in, which the Adu can't hold
+        let mut events = test_events();
+        let repeated = Event {
+            d: 3,
+            ..events[256 * 2]
+        };
+        events.insert(256 * 2 + 1, repeated);
+
+        match verify_round_trip(meta, options, &events) {
+            Err(CodecError::UnorderedEvent(event)) => assert_eq!(event, repeated),
+            other => panic!("expected the repeated event to be rejected, got {other:?}"),
+        }
+        Ok(())
+    }
+}
@@ -9,7 +9,8 @@ use crate::codec::compressed::stream::CompressedInput;
 
 use crate::codec::header::{
     EventStreamHeader, EventStreamHeaderExtensionV1, EventStreamHeaderExtensionV2,
-    EventStreamHeaderExtensionV3, MAGIC_COMPRESSED,
+    EventStreamHeaderExtensionV3, EventStreamHeaderExtensionV4, EventStreamHeaderExtensionV5,
+    MAGIC_COMPRESSED,
 };
 use crate::codec::raw::stream::RawInput;
 use crate::codec::CodecError::Deserialize;
@@ -69,6 +70,25 @@ impl<R: Read + Seek> Decoder<R> {
         Ok(decoder)
     }
 
+    /// Create a new decoder with a user-defined compression scheme
+    pub fn new_custom(
+        compression: Box<dyn ReadCompression<R> + Send + Sync>,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Self, CodecError>
+    where
+        Self: Sized,
+    {
+        let mut decoder = Self {
+            input: ReadCompressionEnum::CustomInput(compression),
+            bincode: DefaultOptions::new()
+                .with_fixint_encoding()
+                .with_big_endian(),
+            _phantom: std::marker::PhantomData,
+        };
+        decoder.decode_header(reader)?;
+        Ok(decoder)
+    }
+
     /// Returns a reference to the metadata of the underlying compression scheme
     #[inline]
     pub fn meta(&self) -> &CodecMetadata {
@@ -128,6 +148,8 @@ impl<R: Read + Seek> Decoder<R> {
                 event_size: header.event_size,
                 source_camera: Default::default(), // Gets filled by decoding the V2 header extension
                 adu_interval: Default::default(), // Gets filled by decoding the V3 header extension
+                lossless: Default::default(),     // Gets filled by decoding the V4 header extension
+                entropy_coder: Default::default(), // Gets filled by decoding the V5 header extension
             };
 
             // Manual fix for malformed files with old software
@@ -199,6 +221,40 @@ impl<R: Read + Seek> Decoder<R> {
             return Ok(());
         }
 
+        extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV4::default())?;
+        buffer = vec![0; extension_size as usize];
+        reader.read_bytes(&mut buffer)?;
+        let extension_v4 = match self
+            .bincode
+            .deserialize_from::<_, EventStreamHeaderExtensionV4>(&*buffer)
+        {
+            Ok(header) => header,
+            Err(_) => return Err(Deserialize),
+        };
+        self.input.meta_mut().lossless = extension_v4.lossless;
+        self.input.meta_mut().header_size += extension_size as usize;
+
+        if codec_version == 4 {
+            return Ok(());
+        }
+
+        extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV5::default())?;
+        buffer = vec![0; extension_size as usize];
+        reader.read_bytes(&mut buffer)?;
+        let extension_v5 = match self
+            .bincode
+            .deserialize_from::<_, EventStreamHeaderExtensionV5>(&*buffer)
+        {
+            Ok(header) => header,
+            Err(_) => return Err(Deserialize),
+        };
+        self.input.meta_mut().entropy_coder = extension_v5.entropy_coder;
+        self.input.meta_mut().header_size += extension_size as usize;
+
+        if codec_version == 5 {
+            return Ok(());
+        }
+
         Err(CodecError::UnsupportedVersion(codec_version))
     }
 
@@ -274,7 +330,7 @@ mod tests {
     use crate::codec::raw::stream::{RawInput, RawOutput};
 
     use crate::codec::rate_controller::Crf;
-    use crate::codec::{EncoderOptions, EventOrder};
+    use crate::codec::{EncoderOptions, EntropyCoder, EventOrder, DEFAULT_MAX_QUEUED_EVENTS};
     use crate::Coord;
     use std::io::{BufReader, BufWriter, Cursor, Write};
 
@@ -306,6 +362,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -343,6 +401,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -359,6 +419,10 @@ mod tests {
                         channels: 1,
                     },
                 ),
+                channel_decorrelation: false,
+                lossless: false,
+                entropy_coder: Default::default(),
+                max_queued_events: DEFAULT_MAX_QUEUED_EVENTS,
             },
         );
 
@@ -390,6 +454,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -449,6 +515,32 @@ mod tests {
         assert_eq!(reader.input.meta().header_size, 33);
     }
 
+    #[test]
+    fn header_v4_raw() {
+        let output = setup_encoded_raw(4);
+        let tmp = Cursor::new(&*output);
+        let bufreader = BufReader::new(tmp);
+        let compression = RawInput::new();
+
+        let mut bitreader = BitReader::endian(bufreader, BigEndian);
+        let reader = Decoder::new_raw(compression, &mut bitreader).unwrap();
+        assert_eq!(reader.input.meta().header_size, 38);
+        assert!(reader.input.meta().lossless);
+    }
+
+    #[test]
+    fn header_v5_raw() {
+        let output = setup_encoded_raw(5);
+        let tmp = Cursor::new(&*output);
+        let bufreader = BufReader::new(tmp);
+        let compression = RawInput::new();
+
+        let mut bitreader = BitReader::endian(bufreader, BigEndian);
+        let reader = Decoder::new_raw(compression, &mut bitreader).unwrap();
+        assert_eq!(reader.input.meta().header_size, 42);
+        assert_eq!(reader.input.meta().entropy_coder, EntropyCoder::Arithmetic);
+    }
+
     #[test]
     #[cfg(feature = "compression")]
     fn header_v0_compressed() {
@@ -500,4 +592,18 @@ mod tests {
         let event = reader.digest_event(&mut bitreader).unwrap();
         assert_eq!(event, stock_event());
     }
+
+    #[test]
+    fn digest_event_raw_interleaved() {
+        // The queued event only gets written out when the encoder is closed
+        let output = setup_encoded_raw_interleaved(2);
+        let tmp = Cursor::new(&*output);
+        let bufreader = BufReader::new(tmp);
+        let compression = RawInput::new();
+
+        let mut bitreader = BitReader::endian(bufreader, BigEndian);
+        let mut reader = Decoder::new_raw(compression, &mut bitreader).unwrap();
+        let event = reader.digest_event(&mut bitreader).unwrap();
+        assert_eq!(event, stock_event());
+    }
 }
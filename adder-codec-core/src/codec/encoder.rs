@@ -3,22 +3,26 @@ use crate::codec::{
     WriteCompressionEnum,
 };
 use crate::SourceType::*;
-use crate::{Event, EventSingle, SourceCamera, SourceType, EOF_EVENT};
+use crate::{AbsoluteT, DeltaT, Event, EventSingle, SourceCamera, SourceType, EOF_EVENT};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::BinaryHeap;
 
 use std::io;
-use std::io::{Sink, Write};
+use std::io::{Seek, Sink, Write};
 use std::time::Instant;
 
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
 #[cfg(feature = "compression")]
-use crate::codec::compressed::stream::CompressedOutput;
+use crate::codec::compressed::stream::{AduStats, CompressedOutput};
 
 use crate::codec::empty::stream::EmptyOutput;
 use crate::codec::header::{
     EventStreamHeader, EventStreamHeaderExtensionV0, EventStreamHeaderExtensionV1,
-    EventStreamHeaderExtensionV2, EventStreamHeaderExtensionV3,
+    EventStreamHeaderExtensionV2, EventStreamHeaderExtensionV3, EventStreamHeaderExtensionV4,
+    EventStreamHeaderExtensionV5,
 };
 
 use crate::codec::raw::stream::RawOutput;
@@ -34,12 +38,33 @@ pub struct Encoder<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     >,
     pub options: EncoderOptions,
     state: EncoderState,
+
+    /// Additional encoders that each ingested event is fanned out to
+    tees: Vec<Encoder<W>>,
 }
 
 struct EncoderState {
     current_event_rate: f64,
     last_event_ts: Instant,
     queue: BinaryHeap<Event>,
+
+    /// The timestamp of the last event measured for [`EventDrop::StreamTime`]
+    last_event_t: Option<AbsoluteT>,
+
+    /// The moving average of the number of ticks between events, for [`EventDrop::StreamTime`]
+    mean_event_interval: Option<f64>,
+
+    /// The seeded RNG for [`EventDrop::StreamTime`]
+    rng: Option<StdRng>,
+
+    /// The number of values drawn from `rng`, so that its state can be restored by replaying them
+    rng_draws: u64,
+
+    /// The latest timestamp written out from the [`EventOrder::Interleaved`] queue
+    last_released_t: Option<AbsoluteT>,
+
+    /// The number of events that arrived after a later event was already written out
+    late_event_count: u64,
 }
 
 impl Default for EncoderState {
@@ -48,6 +73,93 @@ impl Default for EncoderState {
             current_event_rate: 0.0,
             last_event_ts: Instant::now(),
             queue: BinaryHeap::new(),
+            last_event_t: None,
+            mean_event_interval: None,
+            rng: None,
+            rng_draws: 0,
+            last_released_t: None,
+            late_event_count: 0,
+        }
+    }
+}
+
+/// The state of an [`Encoder`] at a point in its stream, from which [`Encoder::resume`] continues
+/// the stream as if it had never stopped
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EncoderCheckpoint {
+    /// The number of bytes written to the output, including the header. A resumed encoder writes
+    /// from here on.
+    pub output_len: u64,
+
+    /// The size of the header, in bytes
+    pub header_size: usize,
+
+    /// The start time of the partial ADU of a compressed output
+    adu_start_t: AbsoluteT,
+
+    /// The events ingested by the output but not yet written, i.e., the partial ADU of a
+    /// compressed output
+    held_events: Vec<Event>,
+
+    /// The events held for reordering with [`EventOrder::Interleaved`]
+    queued_events: Vec<Event>,
+
+    last_event_t: Option<AbsoluteT>,
+    mean_event_interval: Option<f64>,
+    rng_draws: u64,
+    last_released_t: Option<AbsoluteT>,
+    late_event_count: u64,
+}
+
+impl EncoderState {
+    /// Decide whether to drop an event with timestamp `t`, according to the event rate measured
+    /// in stream time.
+    ///
+    /// Without a seed, the rate is measured over the kept events, and the event is dropped if
+    /// keeping it would exceed the target. With a seed, the rate is measured over all the ingested
+    /// events, and the event is dropped at random so that the expected rate of kept events
+    /// matches the target.
+    fn drop_by_stream_time(
+        &mut self,
+        t: AbsoluteT,
+        tps: DeltaT,
+        target_event_rate: f64,
+        alpha: f64,
+        seed: Option<u64>,
+    ) -> bool {
+        let interval = match self.last_event_t {
+            Some(last_event_t) => f64::from(t.saturating_sub(last_event_t)),
+            None => {
+                // Always keep the first event
+                self.last_event_t = Some(t);
+                return false;
+            }
+        };
+        let mean_event_interval = match self.mean_event_interval {
+            Some(mean) => alpha * mean + (1.0 - alpha) * interval,
+            None => interval,
+        };
+        let event_rate = f64::from(tps) / mean_event_interval;
+
+        match seed {
+            None => {
+                if event_rate > target_event_rate {
+                    return true;
+                }
+                self.last_event_t = Some(t);
+                self.mean_event_interval = Some(mean_event_interval);
+                false
+            }
+            Some(seed) => {
+                self.last_event_t = Some(t);
+                self.mean_event_interval = Some(mean_event_interval);
+                if event_rate <= target_event_rate {
+                    return false;
+                }
+                let rng = self.rng.get_or_insert_with(|| StdRng::seed_from_u64(seed));
+                self.rng_draws += 1;
+                rng.gen::<f64>() >= target_event_rate / event_rate
+            }
         }
     }
 }
@@ -66,6 +178,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
                 .with_big_endian(),
             options,
             state: EncoderState::default(),
+            tees: Vec::new(),
         };
         encoder.encode_header().unwrap();
         encoder
@@ -78,6 +191,8 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         Self: Sized,
     {
         compression.with_options(options);
+        compression.meta.lossless = options.lossless;
+        compression.meta.entropy_coder = options.entropy_coder;
         let mut encoder = Self {
             output: WriteCompressionEnum::CompressedOutput(compression),
             bincode: DefaultOptions::new()
@@ -85,6 +200,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
                 .with_big_endian(),
             options,
             state: Default::default(),
+            tees: Vec::new(),
         };
         encoder.encode_header().unwrap();
         encoder
@@ -102,6 +218,33 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
                 .with_big_endian(),
             options,
             state: Default::default(),
+            tees: Vec::new(),
+        };
+        // Raw events are always written exactly as they're ingested
+        encoder.output.meta_mut().lossless = true;
+        encoder.encode_header().unwrap();
+        encoder
+    }
+
+    /// Create a new [`Encoder`] with a user-defined compression scheme. Its [`Magic`] should be
+    /// registered with [`crate::codec::registry::register_magic`] wherever the stream is decoded.
+    ///
+    /// [`Magic`]: crate::codec::Magic
+    pub fn new_custom(
+        compression: Box<dyn WriteCompression<W> + Send + Sync>,
+        options: EncoderOptions,
+    ) -> Self
+    where
+        Self: Sized,
+    {
+        let mut encoder = Self {
+            output: WriteCompressionEnum::CustomOutput(compression),
+            bincode: DefaultOptions::new()
+                .with_fixint_encoding()
+                .with_big_endian(),
+            options,
+            state: Default::default(),
+            tees: Vec::new(),
         };
         encoder.encode_header().unwrap();
         encoder
@@ -143,13 +286,58 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         Ok(self.output.write_bytes(&buffer)?)
     }
 
-    /// Flush the `BitWriter`. Does not flush the internal `BufWriter`.
+    /// Write out any events held for reordering with [`EventOrder::Interleaved`], then flush the
+    /// `BitWriter`. Does not flush the internal `BufWriter`. Also flushes any tee encoders.
     pub fn flush_writer(&mut self) -> io::Result<()> {
+        for tee in &mut self.tees {
+            tee.flush_writer()?;
+        }
+        self.drain_queue().map_err(|e| match e {
+            CodecError::IoError(e) => e,
+            e => io::Error::new(io::ErrorKind::Other, e),
+        })?;
         self.output.flush_writer()
     }
 
-    /// Close the encoder's writer and return it, consuming the encoder in the process.
+    /// Like [`Encoder::flush_writer`], but returns the [`CodecError`] from writing out the queued
+    /// events as is.
+    pub fn flush(&mut self) -> Result<(), CodecError> {
+        for tee in &mut self.tees {
+            tee.flush()?;
+        }
+        self.drain_queue()?;
+        Ok(self.output.flush_writer()?)
+    }
+
+    /// Add an encoder, with its own output, options, and writer, that each event ingested from now
+    /// on is also fanned out to. Its options are applied independently of this encoder's.
+    pub fn tee(&mut self, encoder: Encoder<W>) {
+        self.tees.push(encoder);
+    }
+
+    /// Close the writers of this encoder and its tee encoders, and return them in the order the
+    /// encoders were added, starting with this encoder's.
+    pub fn close_writers(mut self) -> Result<Vec<Option<W>>, CodecError> {
+        let tees = std::mem::take(&mut self.tees);
+        let mut writers = vec![self.close_writer()?];
+        for tee in tees {
+            writers.append(&mut tee.close_writers()?);
+        }
+        Ok(writers)
+    }
+
+    /// Close the encoder's writer and return it, consuming the encoder in the process. Any events
+    /// held for reordering are written out first. The writers of any tee encoders are closed,
+    /// flushed, and dropped, returning the first error; use [`Encoder::close_writers`] to get
+    /// them back instead.
     pub fn close_writer(mut self) -> Result<Option<W>, CodecError> {
+        for tee in std::mem::take(&mut self.tees) {
+            // Flush explicitly, since errors are lost if the writer flushes itself on drop
+            for mut writer in tee.close_writers()?.into_iter().flatten() {
+                writer.flush()?;
+            }
+        }
+        self.drain_queue()?;
         // self.output.byte_align()?;
         // self.write_eof()?;
         // self.flush_writer()?;
@@ -225,12 +413,36 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         if meta.codec_version == 3 {
             return Ok(buffer);
         }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV4 {
+                lossless: meta.lossless,
+            },
+        )?;
+        if meta.codec_version == 4 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV5 {
+                entropy_coder: meta.entropy_coder,
+            },
+        )?;
+        if meta.codec_version == 5 {
+            return Ok(buffer);
+        }
         Err(CodecError::BadFile)
     }
 
     /// Ingest an event
     #[inline(always)]
     pub fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+        for tee in &mut self.tees {
+            tee.ingest_event(event)?;
+        }
+
         match self.options.event_drop {
             EventDrop::None => {}
             EventDrop::Manual {
@@ -247,6 +459,19 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
                 self.state.last_event_ts = now; // update time
                 self.state.current_event_rate = new_event_rate;
             }
+            EventDrop::StreamTime {
+                target_event_rate,
+                alpha,
+                seed,
+            } => {
+                let tps = self.meta().tps;
+                if self
+                    .state
+                    .drop_by_stream_time(event.t, tps, target_event_rate, alpha, seed)
+                {
+                    return Ok(()); // skip this event
+                }
+            }
             EventDrop::Auto => {
                 todo!()
             }
@@ -256,21 +481,54 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
             EventOrder::Unchanged => self.output.ingest_event(event),
             EventOrder::Interleaved => {
                 let dt = event.t;
+                if matches!(self.state.last_released_t, Some(t) if dt < t) {
+                    // A later event has already been written out, so this one can't be in order
+                    self.state.late_event_count += 1;
+                }
+
                 // First, push the event to the queue
                 self.state.queue.push(event);
 
-                let mut res = Ok(());
-                if let Some(first_item_addr) = self.state.queue.peek() {
-                    if first_item_addr.t < dt.saturating_sub(self.meta().delta_t_max) {
-                        if let Some(first_item) = self.state.queue.pop() {
-                            res = self.output.ingest_event(first_item);
-                        }
+                // Release the events that are too old to be preceded by any new event, and any
+                // that don't fit in the queue
+                let threshold = dt.saturating_sub(self.meta().delta_t_max);
+                while let Some(first_item) = self.state.queue.peek() {
+                    if first_item.t >= threshold
+                        && self.state.queue.len() <= self.options.max_queued_events
+                    {
+                        break;
+                    }
+                    if let Some(first_item) = self.state.queue.pop() {
+                        self.release_event(first_item)?;
                     }
                 }
-                res
+                Ok(())
             }
         }
     }
+
+    /// Write out an event from the [`EventOrder::Interleaved`] queue
+    fn release_event(&mut self, event: Event) -> Result<(), CodecError> {
+        let t = event.t;
+        if self.state.last_released_t.map_or(true, |last_t| t > last_t) {
+            self.state.last_released_t = Some(t);
+        }
+        self.output.ingest_event(event)
+    }
+
+    /// Write out all the events held for reordering, in order of their timestamps
+    fn drain_queue(&mut self) -> Result<(), CodecError> {
+        while let Some(event) = self.state.queue.pop() {
+            self.release_event(event)?;
+        }
+        Ok(())
+    }
+
+    /// The number of events that arrived with [`EventOrder::Interleaved`] after a later event had
+    /// already been written out, and so were written out of order
+    pub fn late_event_count(&self) -> u64 {
+        self.state.late_event_count
+    }
     // /// Ingest an event
     // #[cfg(feature = "compression")]
     // pub fn ingest_event_debug(&mut self, event: Event) -> Result<Option<Adu>, CodecError> {
@@ -299,6 +557,20 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         self.options
     }
 
+    /// Get a receiver for the compression statistics of each ADU written from now on. Returns
+    /// `None` if the output is not compressed.
+    #[cfg(feature = "compression")]
+    pub fn stats_receiver(&mut self) -> Option<std::sync::mpsc::Receiver<AduStats>> {
+        match &mut self.output {
+            WriteCompressionEnum::CompressedOutput(compressed_output) => {
+                Some(compressed_output.stats_receiver())
+            }
+            WriteCompressionEnum::RawOutput(_) => None,
+            WriteCompressionEnum::EmptyOutput(_) => None,
+            WriteCompressionEnum::CustomOutput(_) => None,
+        }
+    }
+
     /// Keeps the compressed output options in sync with the encoder options. This prevents us
     /// from constantly having to look up a reference-counted variable, which is costly at this scale.
     pub fn sync_crf(&mut self) {
@@ -309,10 +581,101 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
             }
             WriteCompressionEnum::RawOutput(_) => {}
             WriteCompressionEnum::EmptyOutput(_) => {}
+            WriteCompressionEnum::CustomOutput(_) => {}
         }
     }
 }
 
+impl<W: Write + Seek + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
+    /// Flush the output and capture the state of the encoder, so that a later encoder can
+    /// [`resume`](Encoder::resume) the stream from this point. The output must be kept up to
+    /// [`EncoderCheckpoint::output_len`] bytes.
+    ///
+    /// Encoders with tee outputs or a custom output can't be checkpointed.
+    pub fn checkpoint(&mut self) -> Result<EncoderCheckpoint, CodecError> {
+        if !self.tees.is_empty() {
+            return Err(CodecError::CheckpointUnsupported);
+        }
+
+        let (output_len, adu_start_t, held_events) = match &mut self.output {
+            #[cfg(feature = "compression")]
+            WriteCompressionEnum::CompressedOutput(compressed_output) => {
+                compressed_output.checkpoint()?
+            }
+            WriteCompressionEnum::RawOutput(raw_output) => {
+                raw_output.flush_writer()?;
+                (raw_output.stream_position()?, 0, Vec::new())
+            }
+            WriteCompressionEnum::EmptyOutput(_) => (0, 0, Vec::new()),
+            WriteCompressionEnum::CustomOutput(_) => return Err(CodecError::CheckpointUnsupported),
+        };
+
+        Ok(EncoderCheckpoint {
+            output_len,
+            header_size: self.meta().header_size,
+            adu_start_t,
+            held_events,
+            queued_events: self.state.queue.clone().into_sorted_vec(),
+            last_event_t: self.state.last_event_t,
+            mean_event_interval: self.state.mean_event_interval,
+            rng_draws: self.state.rng_draws,
+            last_released_t: self.state.last_released_t,
+            late_event_count: self.state.late_event_count,
+        })
+    }
+
+    /// Continue the stream of a checkpointed encoder. This encoder must have been created with
+    /// the same metadata and options, over the same output (opened without truncating it). The
+    /// header it wrote is identical to the one already there, and it now writes from the end of
+    /// the checkpointed stream.
+    pub fn resume(&mut self, checkpoint: &EncoderCheckpoint) -> Result<(), CodecError> {
+        if !self.tees.is_empty() {
+            return Err(CodecError::CheckpointUnsupported);
+        }
+        if self.meta().header_size != checkpoint.header_size {
+            return Err(CodecError::BadFile);
+        }
+
+        match &mut self.output {
+            #[cfg(feature = "compression")]
+            WriteCompressionEnum::CompressedOutput(compressed_output) => {
+                compressed_output.resume(
+                    checkpoint.output_len,
+                    checkpoint.adu_start_t,
+                    &checkpoint.held_events,
+                )?;
+            }
+            WriteCompressionEnum::RawOutput(raw_output) => {
+                raw_output.flush_writer()?;
+                raw_output.seek(checkpoint.output_len)?;
+            }
+            WriteCompressionEnum::EmptyOutput(_) => {}
+            WriteCompressionEnum::CustomOutput(_) => return Err(CodecError::CheckpointUnsupported),
+        }
+
+        self.state.queue = checkpoint.queued_events.iter().copied().collect();
+        self.state.last_event_t = checkpoint.last_event_t;
+        self.state.mean_event_interval = checkpoint.mean_event_interval;
+        self.state.last_released_t = checkpoint.last_released_t;
+        self.state.late_event_count = checkpoint.late_event_count;
+
+        // Replay the RNG draws, so that the same events are dropped from here on
+        self.state.rng = None;
+        self.state.rng_draws = 0;
+        if let EventDrop::StreamTime {
+            seed: Some(seed), ..
+        } = self.options.event_drop
+        {
+            let rng = self.state.rng.insert(StdRng::seed_from_u64(seed));
+            for _ in 0..checkpoint.rng_draws {
+                let _: f64 = rng.gen();
+            }
+            self.state.rng_draws = checkpoint.rng_draws;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,7 +683,7 @@ mod tests {
     use crate::codec::{CodecMetadata, LATEST_CODEC_VERSION};
     use crate::{Coord, PlaneSize};
     use bitstream_io::{BigEndian, BitWriter};
-    use std::io::BufWriter;
+    use std::io::{BufWriter, Cursor};
     use std::sync::{Arc, RwLock};
 
     #[test]
@@ -339,6 +702,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bincode: DefaultOptions::new()
                 .with_fixint_encoding()
@@ -356,6 +721,7 @@ mod tests {
                 channels: 1,
             }),
             state: EncoderState::default(),
+            tees: Vec::new(),
         };
         let mut writer = encoder.close_writer().unwrap().unwrap();
         writer.flush().unwrap();
@@ -378,6 +744,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -392,6 +760,7 @@ mod tests {
                 channels: 1,
             }),
             state: EncoderState::default(),
+            tees: Vec::new(),
         };
         let mut writer = encoder.close_writer().unwrap().unwrap();
         writer.flush().unwrap();
@@ -418,6 +787,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -444,7 +815,7 @@ mod tests {
         let mut writer = encoder.close_writer().unwrap().unwrap();
         writer.flush().unwrap();
         let output = writer.into_inner().unwrap();
-        assert_eq!(output.len(), 37 + 22); // 37 bytes for the header, 22 bytes for the 2 events
+        assert_eq!(output.len(), 42 + 22); // 42 bytes for the header, 22 bytes for the 2 events
     }
 
     #[test]
@@ -466,6 +837,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             // frame: Default::default(),
             // adu: Adu::new(),
@@ -478,6 +851,7 @@ mod tests {
             written_bytes_tx: Some(written_bytes_tx),
             last_message_sent: 0,
             last_message_written: Arc::new(RwLock::new(0)),
+            stats_tx: None,
             _phantom: Default::default(),
         };
         let _encoder = Encoder {
@@ -487,6 +861,7 @@ mod tests {
                 .with_big_endian(),
             options: EncoderOptions::default(PlaneSize::default()),
             state: Default::default(),
+            tees: Vec::new(),
         };
     }
 
@@ -507,6 +882,8 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: Default::default(),
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -517,6 +894,7 @@ mod tests {
                 .with_big_endian(),
             options: EncoderOptions::default(PlaneSize::default()),
             state: Default::default(),
+            tees: Vec::new(),
         };
     }
 
@@ -537,10 +915,269 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: Default::default(),
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
         let _encoder =
             Encoder::new_compressed(compression, EncoderOptions::default(PlaneSize::default()));
     }
+
+    /// Encode 10,000 single-channel events at 1,000 events per second of stream time, and return
+    /// the raw output without its header
+    fn encode_with_drop(event_drop: EventDrop) -> Vec<u8> {
+        let plane = PlaneSize {
+            width: 10,
+            height: 10,
+            channels: 1,
+        };
+        let compression = RawOutput::new(
+            CodecMetadata {
+                codec_version: LATEST_CODEC_VERSION,
+                plane,
+                tps: 1000,
+                ..Default::default()
+            },
+            BufWriter::new(Vec::new()),
+        );
+        let mut options = EncoderOptions::default(plane);
+        options.event_drop = event_drop;
+        let mut encoder: Encoder<BufWriter<Vec<u8>>> = Encoder::new_raw(compression, options);
+        let header_size = encoder.meta().header_size;
+
+        for t in 0..10_000 {
+            let event = Event {
+                coord: Coord {
+                    x: (t % 10) as u16,
+                    y: (t / 10 % 10) as u16,
+                    c: None,
+                },
+                d: 7,
+                t,
+            };
+            encoder.ingest_event(event).unwrap();
+        }
+        let mut writer = encoder.close_writer().unwrap().unwrap();
+        writer.flush().unwrap();
+        writer.into_inner().unwrap().split_off(header_size)
+    }
+
+    #[test]
+    fn drop_by_stream_time() {
+        let full = encode_with_drop(EventDrop::None);
+
+        for seed in [None, Some(42)] {
+            let event_drop = EventDrop::StreamTime {
+                target_event_rate: 100.0,
+                alpha: 0.9,
+                seed,
+            };
+            let dropped = encode_with_drop(event_drop);
+
+            // Roughly 1 in 10 events are kept
+            assert!(dropped.len() < full.len() / 5);
+            assert!(dropped.len() > full.len() / 20);
+
+            // The same input always gives the same output
+            assert_eq!(dropped, encode_with_drop(event_drop));
+        }
+    }
+
+    #[test]
+    fn interleaved_queue() {
+        use crate::codec::decoder::Decoder;
+        use crate::codec::raw::stream::RawInput;
+        use bitstream_io::BitReader;
+        use std::io::Cursor;
+
+        let plane = PlaneSize {
+            width: 10,
+            height: 10,
+            channels: 1,
+        };
+        let compression = RawOutput::new(
+            CodecMetadata {
+                codec_version: LATEST_CODEC_VERSION,
+                plane,
+                delta_t_max: 1000,
+                ..Default::default()
+            },
+            BufWriter::new(Vec::new()),
+        );
+        let mut options = EncoderOptions::default(plane);
+        options.event_order = EventOrder::Interleaved;
+        options.max_queued_events = 4;
+        let mut encoder: Encoder<BufWriter<Vec<u8>>> = Encoder::new_raw(compression, options);
+
+        for (x, t) in [50, 40, 30, 20, 10, 60, 5, 70].into_iter().enumerate() {
+            let event = Event {
+                coord: Coord {
+                    x: x as u16,
+                    y: 0,
+                    c: None,
+                },
+                d: 7,
+                t,
+            };
+            encoder.ingest_event(event).unwrap();
+        }
+
+        // The full queue released events 10 and 20 early, so event 5 arrived late
+        assert_eq!(encoder.late_event_count(), 1);
+
+        // Flushing writes out the rest of the queue, in order
+        encoder.flush_writer().unwrap();
+        assert!(encoder.state.queue.is_empty());
+
+        let mut writer = encoder.close_writer().unwrap().unwrap();
+        writer.flush().unwrap();
+        let output = writer.into_inner().unwrap();
+
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut decoder = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let mut decoded = Vec::new();
+        while let Ok(event) = decoder.digest_event(&mut bitreader) {
+            decoded.push(event.t);
+        }
+        assert_eq!(decoded, vec![10, 20, 5, 30, 40, 50, 60, 70]);
+    }
+
+    #[test]
+    fn tee_outputs() {
+        let plane = PlaneSize {
+            width: 10,
+            height: 10,
+            channels: 1,
+        };
+        let new_raw_encoder = |event_drop| -> Encoder<BufWriter<Vec<u8>>> {
+            let compression = RawOutput::new(
+                CodecMetadata {
+                    codec_version: LATEST_CODEC_VERSION,
+                    plane,
+                    tps: 1000,
+                    ..Default::default()
+                },
+                BufWriter::new(Vec::new()),
+            );
+            let mut options = EncoderOptions::default(plane);
+            options.event_drop = event_drop;
+            Encoder::new_raw(compression, options)
+        };
+
+        let event_drop = EventDrop::StreamTime {
+            target_event_rate: 100.0,
+            alpha: 0.9,
+            seed: None,
+        };
+        let mut encoder = new_raw_encoder(EventDrop::None);
+        encoder.tee(new_raw_encoder(event_drop));
+        encoder.tee(new_raw_encoder(EventDrop::None));
+        let header_size = encoder.meta().header_size;
+
+        for t in 0..10_000 {
+            let event = Event {
+                coord: Coord {
+                    x: (t % 10) as u16,
+                    y: (t / 10 % 10) as u16,
+                    c: None,
+                },
+                d: 7,
+                t,
+            };
+            encoder.ingest_event(event).unwrap();
+        }
+
+        let outputs: Vec<Vec<u8>> = encoder
+            .close_writers()
+            .unwrap()
+            .into_iter()
+            .map(|writer| writer.unwrap().into_inner().unwrap())
+            .collect();
+        assert_eq!(outputs.len(), 3);
+
+        // Each tee applies its own options
+        assert_eq!(outputs[0], outputs[2]);
+        assert_eq!(outputs[1][header_size..], encode_with_drop(event_drop));
+    }
+
+    /// Create an encoder for a 16x16 stream, writing to the given output
+    fn new_checkpoint_encoder(
+        compressed: bool,
+        output: Cursor<Vec<u8>>,
+    ) -> Encoder<Cursor<Vec<u8>>> {
+        let plane = PlaneSize {
+            width: 16,
+            height: 16,
+            channels: 1,
+        };
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        };
+        let mut options = EncoderOptions::default(plane);
+        if compressed {
+            #[cfg(feature = "compression")]
+            return Encoder::new_compressed(CompressedOutput::new(meta, output), options);
+        }
+        options.event_drop = EventDrop::StreamTime {
+            target_event_rate: 5000.0,
+            alpha: 0.9,
+            seed: Some(3),
+        };
+        options.event_order = EventOrder::Interleaved;
+        Encoder::new_raw(RawOutput::new(meta, output), options)
+    }
+
+    /// Encode the events with a checkpoint halfway through, then resume the stream as if the
+    /// encoder had crashed some time after the checkpoint
+    fn encode_with_checkpoint(compressed: bool, events: &[Event]) -> Vec<u8> {
+        let half = events.len() / 2;
+        let mut encoder = new_checkpoint_encoder(compressed, Cursor::new(Vec::new()));
+        encoder.ingest_events(&events[..half]).unwrap();
+        let checkpoint = encoder.checkpoint().unwrap();
+
+        // Some more progress is lost in the crash
+        encoder.ingest_events(&events[half..half + 100]).unwrap();
+        let mut output = encoder.close_writer().unwrap().unwrap().into_inner();
+        output.truncate(checkpoint.output_len as usize);
+
+        let mut encoder = new_checkpoint_encoder(compressed, Cursor::new(output));
+        encoder.resume(&checkpoint).unwrap();
+        encoder.ingest_events(&events[half..]).unwrap();
+        encoder.close_writer().unwrap().unwrap().into_inner()
+    }
+
+    #[test]
+    fn checkpoint_resume() {
+        let events: Vec<Event> = (0..4000)
+            .map(|t| Event {
+                coord: Coord {
+                    x: (t % 16) as u16,
+                    y: (t / 16 % 16) as u16,
+                    c: None,
+                },
+                d: 7,
+                t,
+            })
+            .collect();
+
+        let compressed_options: &[bool] = if cfg!(feature = "compression") {
+            &[false, true]
+        } else {
+            &[false]
+        };
+        for &compressed in compressed_options {
+            let mut encoder = new_checkpoint_encoder(compressed, Cursor::new(Vec::new()));
+            encoder.ingest_events(&events).unwrap();
+            let expected = encoder.close_writer().unwrap().unwrap().into_inner();
+
+            assert_eq!(encode_with_checkpoint(compressed, &events), expected);
+        }
+    }
 }
@@ -1,9 +1,15 @@
+use crate::codec::EntropyCoder;
 use crate::{PlaneSize, SourceCamera, TimeMode};
 use serde::{Deserialize, Serialize};
 
-pub(crate) type Magic = [u8; 5];
-pub(crate) const MAGIC_RAW: Magic = [97, 100, 100, 101, 114]; // 'adder' in ASCII
-pub(crate) const MAGIC_COMPRESSED: Magic = [97, 100, 100, 101, 99]; // 'addec' in ASCII
+/// The magic number at the start of an ADΔER stream, identifying its codec
+pub type Magic = [u8; 5];
+
+/// The magic number of a raw ADΔER stream
+pub const MAGIC_RAW: Magic = [97, 100, 100, 101, 114]; // 'adder' in ASCII
+
+/// The magic number of a compressed ADΔER stream
+pub const MAGIC_COMPRESSED: Magic = [97, 100, 100, 101, 99]; // 'addec' in ASCII
 
 /// ADΔER event stream header
 ///
@@ -45,8 +51,20 @@ pub(crate) struct EventStreamHeaderExtensionV3 {
     pub(crate) adu_interval: u32,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV4 {
+    pub(crate) lossless: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV5 {
+    pub(crate) entropy_coder: EntropyCoder,
+}
+
 impl HeaderExtension for EventStreamHeaderExtensionV2 {}
 impl HeaderExtension for EventStreamHeaderExtensionV3 {}
+impl HeaderExtension for EventStreamHeaderExtensionV4 {}
+impl HeaderExtension for EventStreamHeaderExtensionV5 {}
 
 impl EventStreamHeader {
     pub(crate) fn new(
@@ -61,7 +79,6 @@ impl EventStreamHeader {
         assert!(delta_t_max > 0);
         assert!(plane_size.width > 0);
         assert!(plane_size.height > 0);
-        assert!(magic == MAGIC_RAW || magic == MAGIC_COMPRESSED);
 
         EventStreamHeader {
             magic,
@@ -83,3 +100,15 @@ impl EventStreamHeader {
         }
     }
 }
+
+/// The total size in bytes of the header extensions written for the given codec version
+pub(crate) fn extension_size(codec_version: u8) -> bincode::Result<u64> {
+    let sizes = [
+        bincode::serialized_size(&EventStreamHeaderExtensionV1::default())?,
+        bincode::serialized_size(&EventStreamHeaderExtensionV2::default())?,
+        bincode::serialized_size(&EventStreamHeaderExtensionV3::default())?,
+        bincode::serialized_size(&EventStreamHeaderExtensionV4::default())?,
+        bincode::serialized_size(&EventStreamHeaderExtensionV5::default())?,
+    ];
+    Ok(sizes.iter().take(codec_version as usize).sum())
+}
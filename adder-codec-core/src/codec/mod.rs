@@ -1,9 +1,10 @@
 #![warn(missing_docs)]
 
-use crate::codec::header::Magic;
+pub use crate::codec::header::{Magic, MAGIC_COMPRESSED, MAGIC_RAW};
 use crate::{DeltaT, Event, PlaneSize, SourceCamera, TimeMode};
 use bitstream_io::{BigEndian, BitReader};
 use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
 use std::io;
 use std::io::{Read, Seek, Sink, Write};
 
@@ -19,6 +20,9 @@ pub enum WriteCompressionEnum<W: Write + std::marker::Send + std::marker::Sync +
 
     /// An empty output stream. Send all the data into the void.
     EmptyOutput(EmptyOutput<Sink>),
+
+    /// A user-defined output stream
+    CustomOutput(Box<dyn WriteCompression<W> + Send + Sync>),
 }
 
 /// The encoder type, along with any associated options
@@ -47,6 +51,7 @@ enum ReadCompressionEnum<R: Read + Seek> {
     #[cfg(feature = "compression")]
     CompressedInput(CompressedInput<R>),
     RawInput(RawInput<R>),
+    CustomInput(Box<dyn ReadCompression<R> + Send + Sync>),
 }
 
 /// Compressed codec utilities
@@ -63,15 +68,21 @@ pub mod empty;
 pub mod encoder;
 mod header;
 
+/// Stream ADΔER data over the network
+pub mod net;
+
 /// Control the quality of ADDER transcoding and compression in a predictable manner
 pub mod rate_controller;
 /// Raw codec utilities
 pub mod raw;
 
+/// Registry of user-defined input codecs
+pub mod registry;
+
 /// Current latest version of the codec.
 ///
 /// This is the version which will be written to the header.
-pub const LATEST_CODEC_VERSION: u8 = 3;
+pub const LATEST_CODEC_VERSION: u8 = 5;
 
 /// The metadata which stays the same over the course of an ADΔER stream
 #[allow(missing_docs)]
@@ -87,6 +98,13 @@ pub struct CodecMetadata {
     pub event_size: u8,
     pub source_camera: SourceCamera,
     pub adu_interval: usize, // TODO: Allow the adu_interval to be non-constant. Each ADU will encode its own size at its beginning
+
+    /// Whether the decoded events are guaranteed to equal the encoded events (modulo reordering).
+    /// Set by the [`encoder::Encoder`] when the stream is created.
+    pub lossless: bool,
+
+    /// The entropy coder used for the Adus of a compressed stream
+    pub entropy_coder: EntropyCoder,
 }
 
 impl Default for CodecMetadata {
@@ -102,6 +120,8 @@ impl Default for CodecMetadata {
             event_size: 9,
             source_camera: Default::default(),
             adu_interval: 1,
+            lossless: false,
+            entropy_coder: Default::default(),
         }
     }
 }
@@ -194,6 +214,78 @@ pub trait ReadCompression<R: Read> {
     // fn decompress(&self, data: &[u8]) -> Vec<u8>;
 }
 
+impl<
+        W: Write + std::marker::Send + std::marker::Sync + 'static,
+        T: WriteCompression<W> + ?Sized,
+    > WriteCompression<W> for Box<T>
+{
+    fn magic(&self) -> Magic {
+        (**self).magic()
+    }
+
+    fn meta(&self) -> &CodecMetadata {
+        (**self).meta()
+    }
+
+    fn meta_mut(&mut self) -> &mut CodecMetadata {
+        (**self).meta_mut()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn byte_align(&mut self) -> io::Result<()> {
+        (**self).byte_align()
+    }
+
+    fn into_writer(&mut self) -> Option<W> {
+        (**self).into_writer()
+    }
+
+    fn flush_writer(&mut self) -> io::Result<()> {
+        (**self).flush_writer()
+    }
+
+    fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+        (**self).ingest_event(event)
+    }
+}
+
+impl<R: Read, T: ReadCompression<R> + ?Sized> ReadCompression<R> for Box<T> {
+    fn magic(&self) -> Magic {
+        (**self).magic()
+    }
+
+    fn meta(&self) -> &CodecMetadata {
+        (**self).meta()
+    }
+
+    fn meta_mut(&mut self) -> &mut CodecMetadata {
+        (**self).meta_mut()
+    }
+
+    fn read_bytes(
+        &mut self,
+        bytes: &mut [u8],
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> io::Result<()> {
+        (**self).read_bytes(bytes, reader)
+    }
+
+    fn digest_event(&mut self, reader: &mut BitReader<R, BigEndian>) -> Result<Event, CodecError> {
+        (**self).digest_event(reader)
+    }
+
+    fn set_input_stream_position(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        position: u64,
+    ) -> Result<(), CodecError> {
+        (**self).set_input_stream_position(reader, position)
+    }
+}
+
 // unsafe impl<R: Read> Send for ReadCompression {}
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
@@ -253,6 +345,15 @@ pub enum CodecError {
 
     #[error("No more events to read")]
     NoMoreEvents,
+
+    #[error("Magic {0:?} is already registered")]
+    MagicAlreadyRegistered(Magic),
+
+    #[error("Checkpoints are not supported for this encoder")]
+    CheckpointUnsupported,
+
+    #[error("Event {0:?} is not later than its pixel's previous event, so it can't be encoded losslessly")]
+    UnorderedEvent(Event),
 }
 
 /*
@@ -270,6 +371,26 @@ pub struct EncoderOptions {
     pub event_order: EventOrder,
 
     pub crf: Crf,
+
+    /// Predict the events of color channels 1 and 2 from channel 0 at the same pixel, rather
+    /// than coding each channel independently. Only affects compressed, 3-channel streams.
+    pub channel_decorrelation: bool,
+
+    /// Guarantee that the decoded events equal the ingested events (modulo reordering), regardless
+    /// of the [`Crf`] parameters. Recorded in the stream header, so it can't be changed once the
+    /// [`encoder::Encoder`] is created.
+    ///
+    /// Each pixel's events must have strictly increasing timestamps within a compressed Adu. An
+    /// event that doesn't is rejected with [`CodecError::UnorderedEvent`], rather than dropped.
+    pub lossless: bool,
+
+    /// The entropy coder to use for compressed streams. Recorded in the stream header, so it
+    /// can't be changed once the [`encoder::Encoder`] is created.
+    pub entropy_coder: EntropyCoder,
+
+    /// With [`EventOrder::Interleaved`], the maximum number of events held for reordering. When
+    /// the queue is full, its earliest events are written out early.
+    pub max_queued_events: usize,
 }
 
 impl EncoderOptions {
@@ -278,10 +399,30 @@ impl EncoderOptions {
             event_drop: Default::default(),
             event_order: Default::default(),
             crf: Crf::new(None, plane),
+            channel_decorrelation: false,
+            lossless: false,
+            entropy_coder: Default::default(),
+            max_queued_events: DEFAULT_MAX_QUEUED_EVENTS,
         }
     }
 }
 
+/// The default maximum number of events held for reordering with [`EventOrder::Interleaved`]
+pub const DEFAULT_MAX_QUEUED_EVENTS: usize = 1 << 20;
+
+/// The entropy coder used for the Adus of a compressed stream
+#[derive(Default, Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EntropyCoder {
+    /// Adaptive arithmetic coding
+    #[default]
+    Arithmetic,
+
+    /// Range asymmetric numeral system (rANS) coding, with frequency tables that adapt
+    /// periodically rather than after every symbol. Decodes much faster than arithmetic coding,
+    /// at a small cost in compression ratio.
+    Rans,
+}
+
 /// Allow the encoder to randomly drop events before compressing, if the event rate is too high
 #[derive(Default, Copy, Clone, PartialEq, Debug)]
 pub enum EventDrop {
@@ -297,6 +438,22 @@ pub enum EventDrop {
         alpha: f64,
     },
 
+    /// Drop events according to this user-provided event rate, measured in stream time (the
+    /// events' timestamps against the stream's `tps`) rather than wall-clock time. The same input
+    /// therefore always produces the same output.
+    StreamTime {
+        /// The target number of events per second of stream time
+        target_event_rate: f64,
+
+        /// The decay rate in [0., 1.]
+        alpha: f64,
+
+        /// If set, each event is dropped at random, with a probability that brings the measured
+        /// event rate down to the target, using an RNG seeded with this value. Otherwise, an
+        /// event is dropped whenever keeping it would exceed the target event rate.
+        seed: Option<u64>,
+    },
+
     /// TODO: Implement this. Query the actual network bandwidth accoring to some stream handle
     /// and drop events accordingly.
     Auto,
@@ -309,6 +466,8 @@ pub enum EventOrder {
     #[default]
     Unchanged,
 
-    /// Reorder the events according to their firing times
+    /// Reorder the events according to their firing times. Events are held in a queue until a
+    /// later event arrives more than `delta_t_max` ticks ahead of them, the queue is full, or the
+    /// [`encoder::Encoder`] is flushed.
     Interleaved,
 }
@@ -0,0 +1,698 @@
+//! Stream ADΔER data over TCP or UDP.
+//!
+//! A [`NetworkSink`] is a writer for an [`Encoder`](crate::codec::encoder::Encoder), and a
+//! [`NetworkReceiver`] is a reader for a [`Decoder`]. The sink splits the encoded byte stream
+//! into its header and a series of sync units, each of which can be decoded on its own given the
+//! header:
+//! - For a compressed stream, a sync unit is a single Adu, along with its 4-byte length prefix.
+//! - For a raw stream, a sync unit is a run of whole events.
+//!
+//! Each piece is sent as a message, framed as a 1-byte kind (`H` for the header, `D` for a sync
+//! unit, or `E` for the end of the stream), a 4-byte big-endian payload length, and the payload.
+//! Over TCP, the messages are sent back-to-back. Over UDP, each message is a single datagram.
+//!
+//! A receiver can join at any time. Over TCP, each new client is sent the header when it's
+//! accepted, then receives every sync unit from the next one on. Over UDP, the header is re-sent
+//! periodically, and the receiver discards any sync units that arrive before it.
+
+use crate::codec::decoder::Decoder;
+use crate::codec::header::{extension_size, EventStreamHeader, Magic, MAGIC_RAW};
+use crate::codec::raw::stream::RawInput;
+use crate::codec::CodecError;
+use bincode::{DefaultOptions, Options};
+use bitstream_io::{BigEndian, BitReader};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+
+#[cfg(feature = "compression")]
+use crate::codec::compressed::stream::CompressedInput;
+
+const KIND_HEADER: u8 = b'H';
+const KIND_DATA: u8 = b'D';
+const KIND_END: u8 = b'E';
+
+/// The size of the kind and length fields that precede each message payload
+const FRAME_SIZE: usize = 5;
+
+/// The largest payload that fits in a single UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// The number of raw events gathered into each sync unit
+const RAW_UNIT_EVENTS: usize = 1024;
+
+/// Where a [`NetworkSink`] sends its messages
+enum SinkTransport {
+    Tcp {
+        listener: TcpListener,
+        clients: Vec<TcpStream>,
+    },
+    Udp {
+        socket: UdpSocket,
+        peer: SocketAddr,
+
+        /// Re-send the header before every `header_interval` sync units
+        header_interval: usize,
+        units_since_header: usize,
+    },
+}
+
+/// The parsed layout of the encoded stream
+#[derive(Clone, Copy)]
+enum Layout {
+    Compressed,
+    Raw { event_size: usize },
+}
+
+/// Write an ADΔER stream to network receivers, over TCP or UDP
+pub struct NetworkSink {
+    transport: SinkTransport,
+
+    /// Bytes written by the encoder which don't yet form a complete header or sync unit
+    pending: Vec<u8>,
+    header: Option<Vec<u8>>,
+    layout: Option<Layout>,
+
+    /// The number of sync units too large to send in a single UDP datagram
+    dropped_units: u64,
+    finished: bool,
+}
+
+impl NetworkSink {
+    /// Listen for TCP receivers at the given address. Receivers may connect at any time.
+    pub fn tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(SinkTransport::Tcp {
+            listener,
+            clients: Vec::new(),
+        }))
+    }
+
+    /// Send datagrams to a UDP receiver at `peer`, from a socket bound to `addr`. The header is
+    /// re-sent before every `header_interval` sync units, so that the receiver can join late.
+    pub fn udp(
+        addr: impl ToSocketAddrs,
+        peer: impl ToSocketAddrs,
+        header_interval: usize,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        let peer = peer
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no peer address"))?;
+        Ok(Self::new(SinkTransport::Udp {
+            socket,
+            peer,
+            header_interval: header_interval.max(1),
+            units_since_header: 0,
+        }))
+    }
+
+    fn new(transport: SinkTransport) -> Self {
+        Self {
+            transport,
+            pending: Vec::new(),
+            header: None,
+            layout: None,
+            dropped_units: 0,
+            finished: false,
+        }
+    }
+
+    /// The local address of the TCP listener or UDP socket
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.transport {
+            SinkTransport::Tcp { listener, .. } => listener.local_addr(),
+            SinkTransport::Udp { socket, .. } => socket.local_addr(),
+        }
+    }
+
+    /// The number of sync units that were dropped because they were too large for a UDP datagram.
+    /// Use a smaller `adu_interval` to avoid this.
+    pub fn dropped_units(&self) -> u64 {
+        self.dropped_units
+    }
+
+    /// Send any complete events still pending, then tell the receivers that the stream has ended.
+    /// Called automatically when the sink is dropped.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.flush()?;
+        self.accept_clients();
+        self.finished = true;
+        self.send(KIND_END, &[]);
+        Ok(())
+    }
+
+    /// Split the pending bytes into the header and any complete sync units
+    fn process(&mut self, flush: bool) -> io::Result<()> {
+        if self.header.is_none() && !self.parse_header()? {
+            return Ok(());
+        }
+
+        match self.layout {
+            Some(Layout::Compressed) => loop {
+                if self.pending.len() < 4 {
+                    break;
+                }
+                let adu_size =
+                    u32::from_be_bytes(self.pending[..4].try_into().unwrap()) as usize + 4;
+                if self.pending.len() < adu_size {
+                    break;
+                }
+                let unit: Vec<u8> = self.pending.drain(..adu_size).collect();
+                self.send_unit(&unit);
+            },
+            Some(Layout::Raw { event_size }) => {
+                let unit_size = RAW_UNIT_EVENTS * event_size;
+                while self.pending.len() >= unit_size {
+                    let unit: Vec<u8> = self.pending.drain(..unit_size).collect();
+                    self.send_unit(&unit);
+                }
+                let whole_events = self.pending.len() - self.pending.len() % event_size;
+                if flush && whole_events > 0 {
+                    let unit: Vec<u8> = self.pending.drain(..whole_events).collect();
+                    self.send_unit(&unit);
+                }
+            }
+            None => unreachable!("Layout is set with the header"),
+        }
+        Ok(())
+    }
+
+    /// Take the header from the pending bytes, if they hold all of it. Returns whether the header
+    /// is now known.
+    fn parse_header(&mut self) -> io::Result<bool> {
+        let base_size =
+            bincode::serialized_size(&EventStreamHeader::default()).map_err(invalid_data)? as usize;
+        if self.pending.len() < base_size {
+            return Ok(false);
+        }
+
+        let header: EventStreamHeader = DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_big_endian()
+            .deserialize(&self.pending[..base_size])
+            .map_err(invalid_data)?;
+        let header_size =
+            base_size + extension_size(header.version).map_err(invalid_data)? as usize;
+        if self.pending.len() < header_size {
+            return Ok(false);
+        }
+
+        self.layout = Some(if header.magic == MAGIC_RAW {
+            Layout::Raw {
+                event_size: match header.event_size {
+                    10 => 11, // Manual fix for malformed headers, as in the decoder
+                    size => size as usize,
+                },
+            }
+        } else {
+            Layout::Compressed
+        });
+        self.header = Some(self.pending.drain(..header_size).collect());
+        Ok(true)
+    }
+
+    /// Send a sync unit to every receiver, first sending the header to any receivers that need it
+    fn send_unit(&mut self, unit: &[u8]) {
+        self.accept_clients();
+        if let SinkTransport::Udp {
+            socket,
+            peer,
+            header_interval,
+            units_since_header,
+        } = &mut self.transport
+        {
+            if *units_since_header % *header_interval == 0 {
+                let header = self.header.as_deref().unwrap_or_default();
+                // Datagrams may be lost, so there's no point reporting errors
+                send_datagram(socket, *peer, KIND_HEADER, header).ok();
+            }
+            *units_since_header += 1;
+        }
+        self.send(KIND_DATA, unit);
+    }
+
+    /// Accept any new TCP receivers, and send them the header. Does nothing until the header is
+    /// known.
+    fn accept_clients(&mut self) {
+        let (Some(header), SinkTransport::Tcp { listener, clients }) =
+            (&self.header, &mut self.transport)
+        else {
+            return;
+        };
+        while let Ok((mut client, _)) = listener.accept() {
+            if client.set_nonblocking(false).is_ok()
+                && write_message(&mut client, KIND_HEADER, header).is_ok()
+            {
+                clients.push(client);
+            }
+        }
+    }
+
+    /// Send a message to every receiver. TCP receivers which can't be written to are dropped.
+    fn send(&mut self, kind: u8, payload: &[u8]) {
+        match &mut self.transport {
+            SinkTransport::Tcp { clients, .. } => {
+                clients.retain_mut(|client| write_message(client, kind, payload).is_ok());
+            }
+            SinkTransport::Udp { socket, peer, .. } => {
+                if FRAME_SIZE + payload.len() > MAX_DATAGRAM_SIZE {
+                    self.dropped_units += 1;
+                } else {
+                    send_datagram(socket, *peer, kind, payload).ok();
+                }
+            }
+        }
+    }
+}
+
+impl Write for NetworkSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.process(false)?;
+        Ok(buf.len())
+    }
+
+    /// Send any complete raw events still pending. Compressed Adus are always sent as soon as
+    /// they're written.
+    fn flush(&mut self) -> io::Result<()> {
+        self.process(true)?;
+        if let SinkTransport::Tcp { clients, .. } = &mut self.transport {
+            clients.retain_mut(|client| client.flush().is_ok());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NetworkSink {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+fn invalid_data(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn frame(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(FRAME_SIZE + payload.len());
+    message.push(kind);
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+fn write_message(stream: &mut TcpStream, kind: u8, payload: &[u8]) -> io::Result<()> {
+    stream.write_all(&frame(kind, payload))
+}
+
+fn send_datagram(socket: &UdpSocket, peer: SocketAddr, kind: u8, payload: &[u8]) -> io::Result<()> {
+    socket.send_to(&frame(kind, payload), peer).map(|_| ())
+}
+
+/// Where a [`NetworkReceiver`] receives its messages from
+enum ReceiverTransport {
+    Tcp(TcpStream),
+    Udp { socket: UdpSocket, buffer: Vec<u8> },
+}
+
+/// Receive an ADΔER stream from a [`NetworkSink`], as a reader for a [`Decoder`].
+///
+/// Reads return the stream header, followed by each sync unit as it arrives. The reader can't
+/// seek, except to query its current position.
+pub struct NetworkReceiver {
+    transport: ReceiverTransport,
+
+    /// The received bytes not yet read out
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    header: Option<Vec<u8>>,
+
+    /// The number of bytes read out so far
+    position: u64,
+    ended: bool,
+}
+
+impl NetworkReceiver {
+    /// Connect to a [`NetworkSink`] listening for TCP receivers at the given address
+    pub fn tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self::new(ReceiverTransport::Tcp(TcpStream::connect(addr)?)))
+    }
+
+    /// Receive datagrams from a [`NetworkSink`] on a UDP socket bound to the given address
+    pub fn udp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self::new(ReceiverTransport::Udp {
+            socket: UdpSocket::bind(addr)?,
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+        }))
+    }
+
+    fn new(transport: ReceiverTransport) -> Self {
+        Self {
+            transport,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            header: None,
+            position: 0,
+            ended: false,
+        }
+    }
+
+    /// The local address of the TCP stream or UDP socket
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.transport {
+            ReceiverTransport::Tcp(stream) => stream.local_addr(),
+            ReceiverTransport::Udp { socket, .. } => socket.local_addr(),
+        }
+    }
+
+    /// Block until the stream header is received, and return its [`Magic`]
+    fn wait_for_header(&mut self) -> io::Result<Magic> {
+        while self.header.is_none() {
+            if !self.fill_buffer()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        let header = self.header.as_ref().unwrap();
+        header
+            .get(..5)
+            .and_then(|magic| magic.try_into().ok())
+            .ok_or_else(|| io::ErrorKind::InvalidData.into())
+    }
+
+    /// Receive the next message
+    fn receive(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        match &mut self.transport {
+            ReceiverTransport::Tcp(stream) => {
+                let mut frame = [0; FRAME_SIZE];
+                match stream.read_exact(&mut frame) {
+                    // The sink closed the connection without sending an end message
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        return Ok((KIND_END, Vec::new()))
+                    }
+                    result => result?,
+                }
+                let len = u32::from_be_bytes(frame[1..].try_into().unwrap()) as usize;
+                let mut payload = vec![0; len];
+                stream.read_exact(&mut payload)?;
+                Ok((frame[0], payload))
+            }
+            ReceiverTransport::Udp { socket, buffer } => loop {
+                let len = socket.recv(buffer)?;
+                if len < FRAME_SIZE {
+                    continue;
+                }
+                let payload_len = u32::from_be_bytes(buffer[1..FRAME_SIZE].try_into().unwrap());
+                if payload_len as usize != len - FRAME_SIZE {
+                    // Truncated or malformed datagram
+                    continue;
+                }
+                return Ok((buffer[0], buffer[FRAME_SIZE..len].to_vec()));
+            },
+        }
+    }
+
+    /// Receive messages until there are more bytes to read out. Returns `false` at the end of the
+    /// stream.
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        while !self.ended {
+            let (kind, payload) = self.receive()?;
+            match kind {
+                KIND_HEADER if self.header.is_none() => {
+                    self.header = Some(payload.clone());
+                    self.buffer = payload;
+                    self.buffer_pos = 0;
+                    return Ok(true);
+                }
+                // Sync units are useless before the header
+                KIND_DATA if self.header.is_some() => {
+                    self.buffer = payload;
+                    self.buffer_pos = 0;
+                    return Ok(true);
+                }
+                KIND_END => self.ended = true,
+                _ => {}
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Read for NetworkReceiver {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.buffer_pos == self.buffer.len() {
+            if !self.fill_buffer()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.buffer.len() - self.buffer_pos);
+        buf[..len].copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + len]);
+        self.buffer_pos += len;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for NetworkReceiver {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.position),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "can't seek in a network stream",
+            )),
+        }
+    }
+}
+
+/// Wait for the header of a network stream, and open a raw or compressed decoder for it, as
+/// appropriate.
+pub fn open_network_decoder(
+    mut receiver: NetworkReceiver,
+) -> Result<
+    (
+        Decoder<NetworkReceiver>,
+        BitReader<NetworkReceiver, BigEndian>,
+    ),
+    CodecError,
+> {
+    let magic = receiver.wait_for_header()?;
+    let mut bitreader = BitReader::endian(receiver, BigEndian);
+    let decoder = if magic == MAGIC_RAW {
+        Decoder::new_raw(RawInput::new(), &mut bitreader)?
+    } else {
+        #[cfg(feature = "compression")]
+        {
+            let compression = CompressedInput::new(0, 0, 0); // The header fills these in
+            Decoder::new_compressed(compression, &mut bitreader)?
+        }
+
+        #[cfg(not(feature = "compression"))]
+        return Err(CodecError::WrongMagic);
+    };
+    Ok((decoder, bitreader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::encoder::Encoder;
+    use crate::codec::raw::stream::RawOutput;
+    use crate::codec::{CodecMetadata, EncoderOptions, LATEST_CODEC_VERSION};
+    use crate::{Coord, Event, PlaneSize, SourceCamera, TimeMode};
+
+    fn test_meta(channels: u8) -> CodecMetadata {
+        CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            header_size: 0,
+            time_mode: TimeMode::AbsoluteT,
+            plane: PlaneSize::new(16, 16, channels).unwrap(),
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 30,
+            event_size: 0,
+            source_camera: SourceCamera::FramedU8,
+            adu_interval: 30,
+            lossless: true,
+            entropy_coder: Default::default(),
+        }
+    }
+
+    /// Events spanning several Adus, with `start_t` as the first timestamp
+    fn test_events(start_t: u32, repeats: u16) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut t = start_t;
+        for _ in 0..repeats {
+            for y in 0..16 {
+                for x in 0..16 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t,
+                        d: 7 + (x % 3) as u8,
+                    });
+                    t += 1;
+                }
+            }
+        }
+        events
+    }
+
+    /// Decode events until the end of the stream
+    fn decode_all(receiver: NetworkReceiver) -> Result<Vec<Event>, CodecError> {
+        decode_all_with(receiver, |_| {})
+    }
+
+    /// Decode events until the end of the stream, calling `on_event` with the events decoded so
+    /// far after each one
+    fn decode_all_with(
+        receiver: NetworkReceiver,
+        mut on_event: impl FnMut(&[Event]),
+    ) -> Result<Vec<Event>, CodecError> {
+        let (mut decoder, mut bitreader) = open_network_decoder(receiver)?;
+        let mut events = Vec::new();
+        loop {
+            match decoder.digest_event(&mut bitreader) {
+                Ok(event) => {
+                    events.push(event);
+                    on_event(&events);
+                }
+                Err(CodecError::Eof) => break,
+                Err(CodecError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(events)
+    }
+
+    fn sorted(mut events: Vec<Event>) -> Vec<Event> {
+        events.sort_unstable_by_key(|event| (event.t, event.coord.y, event.coord.x, event.d));
+        events
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn tcp_compressed() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+
+        let meta = test_meta(1);
+        let sink = NetworkSink::tcp("127.0.0.1:0")?;
+        let receiver = NetworkReceiver::tcp(sink.local_addr()?)?;
+        let handle = std::thread::spawn(move || decode_all(receiver));
+
+        let mut options = EncoderOptions::default(meta.plane);
+        options.lossless = true;
+        let mut encoder = Encoder::new_compressed(CompressedOutput::new(meta, sink), options);
+        let events = test_events(280, 10);
+        encoder.ingest_events(&events)?;
+        drop(encoder.close_writer()?);
+
+        assert_eq!(sorted(handle.join().unwrap()?), sorted(events));
+        Ok(())
+    }
+
+    #[test]
+    fn tcp_join_mid_stream() -> Result<(), Box<dyn std::error::Error>> {
+        let meta = test_meta(1);
+        let sink = NetworkSink::tcp("127.0.0.1:0")?;
+        let addr = sink.local_addr()?;
+        let first = NetworkReceiver::tcp(addr)?;
+        let first_handle = std::thread::spawn(move || decode_all(first));
+
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, sink),
+            EncoderOptions::default(meta.plane),
+        );
+        let early_events = test_events(280, 10);
+        encoder.ingest_events(&early_events)?;
+        encoder.flush_writer()?;
+
+        // The second receiver only gets the header and the events written after it joins
+        let second = NetworkReceiver::tcp(addr)?;
+        let second_handle = std::thread::spawn(move || decode_all(second));
+        let late_events = test_events(280 + 2560, 10);
+        encoder.ingest_events(&late_events)?;
+        drop(encoder.close_writer()?);
+
+        let all_events = [early_events, late_events.clone()].concat();
+        assert_eq!(first_handle.join().unwrap()?, all_events);
+        assert_eq!(second_handle.join().unwrap()?, late_events);
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn tcp_join_mid_stream_compressed() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use std::sync::mpsc::channel;
+
+        let meta = test_meta(1);
+        let adu_span = meta.ref_interval * meta.adu_interval as u32;
+        let sink = NetworkSink::tcp("127.0.0.1:0")?;
+        let addr = sink.local_addr()?;
+
+        // The early events fill the first Adu, and the late events the second
+        let early_events = test_events(280, 10);
+        let late_events = test_events(280 + adu_span, 10);
+        let early_len = early_events.len();
+
+        let first = NetworkReceiver::tcp(addr)?;
+        let (early_tx, early_rx) = channel();
+        let first_handle = std::thread::spawn(move || {
+            decode_all_with(first, |events| {
+                if events.len() == early_len {
+                    early_tx.send(()).ok();
+                }
+            })
+        });
+
+        let mut options = EncoderOptions::default(meta.plane);
+        options.lossless = true;
+        let mut encoder = Encoder::new_compressed(CompressedOutput::new(meta, sink), options);
+        encoder.ingest_events(&early_events)?;
+        encoder.ingest_events(&late_events)?;
+
+        // Wait until the first Adu has been sent, then join. The second Adu is only written when
+        // the stream is closed.
+        early_rx.recv()?;
+        let second = NetworkReceiver::tcp(addr)?;
+        let second_handle = std::thread::spawn(move || decode_all(second));
+        drop(encoder.close_writer()?);
+
+        let all_events = [early_events, late_events.clone()].concat();
+        assert_eq!(sorted(first_handle.join().unwrap()?), sorted(all_events));
+        assert_eq!(sorted(second_handle.join().unwrap()?), sorted(late_events));
+        Ok(())
+    }
+
+    #[test]
+    fn udp_raw() -> Result<(), Box<dyn std::error::Error>> {
+        let meta = test_meta(3);
+        let receiver = NetworkReceiver::udp("127.0.0.1:0")?;
+        let sink = NetworkSink::udp("127.0.0.1:0", receiver.local_addr()?, 4)?;
+        let handle = std::thread::spawn(move || decode_all(receiver));
+
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, sink),
+            EncoderOptions::default(meta.plane),
+        );
+        let events: Vec<Event> = test_events(280, 10)
+            .into_iter()
+            .map(|mut event| {
+                event.coord.c = Some((event.t % 3) as u8);
+                event
+            })
+            .collect();
+        encoder.ingest_events(&events)?;
+        let sink = encoder.close_writer()?.unwrap();
+        assert_eq!(sink.dropped_units(), 0);
+        drop(sink);
+
+        assert_eq!(handle.join().unwrap()?, events);
+        Ok(())
+    }
+}
@@ -50,6 +50,18 @@ impl<W: Write> RawOutput<W> {
     }
 }
 
+impl<W: Write + Seek> RawOutput<W> {
+    /// The number of bytes in the stream
+    pub(crate) fn stream_position(&mut self) -> std::io::Result<u64> {
+        self.stream().stream_position()
+    }
+
+    /// Move to the given byte position of the stream, to write from there on
+    pub(crate) fn seek(&mut self, position: u64) -> std::io::Result<()> {
+        self.stream().seek(SeekFrom::Start(position)).map(|_| ())
+    }
+}
+
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static> WriteCompression<W>
     for RawOutput<W>
 {
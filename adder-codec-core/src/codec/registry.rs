@@ -0,0 +1,203 @@
+//! The [`Magic`] numbers of user-defined input codecs, and how to construct their inputs.
+//!
+//! A user-defined codec implements [`WriteCompression`](crate::codec::WriteCompression) and/or
+//! [`ReadCompression`], and is used with [`Encoder::new_custom`] and [`Decoder::new_custom`].
+//! Registering its magic lets [`open_file_decoder`] open its files as well.
+//!
+//! [`Encoder::new_custom`]: crate::codec::encoder::Encoder::new_custom
+//! [`Decoder::new_custom`]: crate::codec::decoder::Decoder::new_custom
+//! [`open_file_decoder`]: crate::open_file_decoder
+
+use crate::codec::{CodecError, Magic, ReadCompression, MAGIC_COMPRESSED, MAGIC_RAW};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::RwLock;
+
+/// Constructs the input of a user-defined codec, for reading a file
+pub type InputConstructor = fn() -> Box<dyn ReadCompression<BufReader<File>> + Send + Sync>;
+
+static REGISTRY: RwLock<Vec<(Magic, InputConstructor)>> = RwLock::new(Vec::new());
+
+/// Register the magic number of a user-defined codec, along with a constructor for its input.
+///
+/// Returns an error if the magic is already registered, or belongs to a built-in codec.
+pub fn register_magic(magic: Magic, constructor: InputConstructor) -> Result<(), CodecError> {
+    let mut registry = REGISTRY.write().unwrap();
+    if magic == MAGIC_RAW
+        || magic == MAGIC_COMPRESSED
+        || registry.iter().any(|(registered, _)| *registered == magic)
+    {
+        return Err(CodecError::MagicAlreadyRegistered(magic));
+    }
+    registry.push((magic, constructor));
+    Ok(())
+}
+
+/// All the known magic numbers: those of the built-in codecs, followed by the registered ones in
+/// the order they were registered
+pub fn registered_magics() -> Vec<Magic> {
+    let mut magics = vec![MAGIC_RAW, MAGIC_COMPRESSED];
+    magics.extend(REGISTRY.read().unwrap().iter().map(|(magic, _)| *magic));
+    magics
+}
+
+/// Get the input constructor registered for the given magic number, if there is one
+pub fn input_constructor(magic: Magic) -> Option<InputConstructor> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(registered, _)| *registered == magic)
+        .map(|(_, constructor)| *constructor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::encoder::Encoder;
+    use crate::codec::raw::stream::{RawInput, RawOutput};
+    use crate::codec::{CodecMetadata, EncoderOptions, WriteCompression, LATEST_CODEC_VERSION};
+    use crate::{open_file_decoder, Coord, Event, PlaneSize};
+    use bitstream_io::{BigEndian, BitReader};
+    use std::io::{BufWriter, Write};
+
+    const TAGGED_MAGIC: Magic = *b"adtag";
+
+    /// A raw stream with its own magic number
+    struct TaggedOutput<W>(RawOutput<W>);
+
+    impl<W: Write + Send + Sync + 'static> WriteCompression<W> for TaggedOutput<W> {
+        fn magic(&self) -> Magic {
+            TAGGED_MAGIC
+        }
+
+        fn meta(&self) -> &CodecMetadata {
+            self.0.meta()
+        }
+
+        fn meta_mut(&mut self) -> &mut CodecMetadata {
+            self.0.meta_mut()
+        }
+
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+            self.0.write_bytes(bytes)
+        }
+
+        fn byte_align(&mut self) -> std::io::Result<()> {
+            self.0.byte_align()
+        }
+
+        fn into_writer(&mut self) -> Option<W> {
+            self.0.into_writer()
+        }
+
+        fn flush_writer(&mut self) -> std::io::Result<()> {
+            self.0.flush_writer()
+        }
+
+        fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+            self.0.ingest_event(event)
+        }
+    }
+
+    struct TaggedInput(RawInput<BufReader<File>>);
+
+    impl ReadCompression<BufReader<File>> for TaggedInput {
+        fn magic(&self) -> Magic {
+            TAGGED_MAGIC
+        }
+
+        fn meta(&self) -> &CodecMetadata {
+            self.0.meta()
+        }
+
+        fn meta_mut(&mut self) -> &mut CodecMetadata {
+            self.0.meta_mut()
+        }
+
+        fn read_bytes(
+            &mut self,
+            bytes: &mut [u8],
+            reader: &mut BitReader<BufReader<File>, BigEndian>,
+        ) -> std::io::Result<()> {
+            self.0.read_bytes(bytes, reader)
+        }
+
+        fn digest_event(
+            &mut self,
+            reader: &mut BitReader<BufReader<File>, BigEndian>,
+        ) -> Result<Event, CodecError> {
+            self.0.digest_event(reader)
+        }
+
+        fn set_input_stream_position(
+            &mut self,
+            reader: &mut BitReader<BufReader<File>, BigEndian>,
+            position: u64,
+        ) -> Result<(), CodecError> {
+            self.0.set_input_stream_position(reader, position)
+        }
+    }
+
+    fn tagged_input() -> Box<dyn ReadCompression<BufReader<File>> + Send + Sync> {
+        Box::new(TaggedInput(RawInput::new()))
+    }
+
+    #[test]
+    fn custom_codec() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!("adder_tagged_{}.adder", std::process::id()));
+        let plane = PlaneSize::new(8, 8, 1)?;
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            plane,
+            ..Default::default()
+        };
+        let events: Vec<Event> = (0..64)
+            .map(|i| Event {
+                coord: Coord {
+                    x: i % 8,
+                    y: i / 8,
+                    c: None,
+                },
+                d: 7,
+                t: u32::from(i) * 10,
+            })
+            .collect();
+
+        let output = RawOutput::new(meta, BufWriter::new(File::create(&path)?));
+        let mut encoder = Encoder::new_custom(
+            Box::new(TaggedOutput(output)),
+            EncoderOptions::default(plane),
+        );
+        encoder.ingest_events(&events)?;
+        encoder.close_writer()?.unwrap().flush()?;
+
+        // The file can't be opened until its magic is registered
+        let path_str = path.to_str().unwrap();
+        assert!(matches!(
+            open_file_decoder(path_str),
+            Err(CodecError::WrongMagic)
+        ));
+        register_magic(TAGGED_MAGIC, tagged_input)?;
+        assert!(matches!(
+            register_magic(TAGGED_MAGIC, tagged_input),
+            Err(CodecError::MagicAlreadyRegistered(TAGGED_MAGIC))
+        ));
+        assert!(register_magic(MAGIC_RAW, tagged_input).is_err());
+        assert!(registered_magics().contains(&TAGGED_MAGIC));
+
+        let (mut decoder, mut bitreader) = open_file_decoder(path_str)?;
+        assert_eq!(decoder.meta().plane, plane);
+        let mut decoded = Vec::new();
+        loop {
+            match decoder.digest_event(&mut bitreader) {
+                Ok(event) => decoded.push(event),
+                Err(CodecError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        std::fs::remove_file(&path)?;
+        assert_eq!(decoded, events);
+        Ok(())
+    }
+}
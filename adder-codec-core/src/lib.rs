@@ -11,7 +11,7 @@ pub use bitstream_io;
 use bitstream_io::{BigEndian, BitReader};
 use std::cmp::Ordering;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::ops::Add;
 
 use thiserror::Error;
@@ -83,7 +83,7 @@ pub enum TimeMode {
 }
 
 /// The size of the image plane in pixels
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PlaneSize {
     width: u16,
     height: u16,
@@ -457,7 +457,9 @@ const EOF_EVENT: Event = Event {
     t: 0,
 };
 
-/// Helper function for opening a file as a raw or compressed input ADΔER stream
+/// Helper function for opening a file as a raw or compressed input ADΔER stream, or as the input
+/// stream of a user-defined codec whose [`Magic`](codec::Magic) is registered with
+/// [`codec::registry::register_magic`]
 pub fn open_file_decoder(
     file_path: &str,
 ) -> Result<
@@ -468,28 +470,22 @@ pub fn open_file_decoder(
     CodecError,
 > {
     let mut bufreader = BufReader::new(File::open(file_path)?);
-    let compression = RawInput::new();
+    let mut magic = [0; 5];
+    bufreader.read_exact(&mut magic)?;
+    bufreader.seek(SeekFrom::Start(0))?;
     let mut bitreader = BitReader::endian(bufreader, BigEndian);
 
-    // First try opening the file as a raw file, then try as a compressed file
-    let stream = match Decoder::new_raw(compression, &mut bitreader) {
-        Ok(reader) => reader,
-        Err(CodecError::WrongMagic) => {
-            #[cfg(feature = "compression")]
-            {
-                dbg!("Opening as compressed");
-                bufreader = BufReader::new(File::open(file_path)?);
-                let compression = CompressedInput::new(0, 0, 0); // TODO: temporary args. Need to refactor.
-                bitreader = BitReader::endian(bufreader, BigEndian);
-                Decoder::new_compressed(compression, &mut bitreader)?
-            }
-
-            #[cfg(not(feature = "compression"))]
-            return Err(CodecError::WrongMagic);
-        }
-        Err(e) => {
-            return Err(e);
+    let stream = match magic {
+        codec::MAGIC_RAW => Decoder::new_raw(RawInput::new(), &mut bitreader)?,
+        #[cfg(feature = "compression")]
+        codec::MAGIC_COMPRESSED => {
+            let compression = CompressedInput::new(0, 0, 0); // TODO: temporary args. Need to refactor.
+            Decoder::new_compressed(compression, &mut bitreader)?
         }
+        _ => match codec::registry::input_constructor(magic) {
+            Some(constructor) => Decoder::new_custom(constructor(), &mut bitreader)?,
+            None => return Err(CodecError::WrongMagic),
+        },
     };
     Ok((stream, bitreader))
 }
@@ -0,0 +1,124 @@
+use adder_codec_core::{open_file_decoder, Event, TimeMode};
+use adder_codec_rs::utils::dvs_sim::{AdderDvsEvents, DvsSimParams};
+use adder_codec_rs::utils::npy::{write_events_npz, NpyEvent, NpyEventWriter};
+use clap::Parser;
+use ndarray::Array3;
+use std::error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Export an ADΔER stream as a NumPy `.npy` array or `.npz` archive of events
+#[derive(Parser, Debug, Default)]
+#[clap(author, version, about, long_about = None)]
+pub struct MyArgs {
+    /// Input ADΔER video path
+    #[clap(short, long)]
+    pub input: String,
+
+    /// Output path. A `.npz` extension writes a compressed archive; anything else writes a
+    /// `.npy` array.
+    #[clap(short, long)]
+    pub output: String,
+
+    /// The events to export: "adder" for the ADΔER events (x, y, c, d, t), with absolute
+    /// timestamps in ticks, or "dvs" for DVS events (t, x, y, p) simulated from the stream,
+    /// with timestamps in microseconds
+    #[clap(short, long, default_value = "adder")]
+    pub events: String,
+
+    /// DVS contrast threshold, as a change in log intensity
+    #[clap(long, default_value_t = 0.2)]
+    pub theta: f64,
+
+    /// DVS refractory period, in microseconds
+    #[clap(long, default_value_t = 0)]
+    pub refractory_period: u32,
+
+    /// Rate of DVS noise events, per pixel per second
+    #[clap(long, default_value_t = 0.0)]
+    pub noise_rate: f64,
+
+    /// Seed of the DVS noise generator
+    #[clap(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let args: MyArgs = MyArgs::parse();
+
+    let count = match args.events.to_lowercase().as_str() {
+        "adder" => {
+            let (mut stream, mut bitreader) = open_file_decoder(&args.input)?;
+            let meta = *stream.meta();
+            let absolute_t = meta.codec_version >= 2 && meta.time_mode == TimeMode::AbsoluteT;
+            let mut last_timestamps: Array3<u32> = Array3::zeros((
+                meta.plane.h_usize(),
+                meta.plane.w_usize(),
+                meta.plane.c_usize(),
+            ));
+            println!("Exporting ADΔER events with {} ticks per second", meta.tps);
+
+            let events = std::iter::from_fn(|| stream.digest_event(&mut bitreader).ok()).map(
+                |mut event: Event| {
+                    if !absolute_t {
+                        if let Some(last_t) = last_timestamps.get_mut([
+                            event.coord.y_usize(),
+                            event.coord.x_usize(),
+                            event.coord.c_usize(),
+                        ]) {
+                            *last_t = last_t.saturating_add(event.t);
+                            event.t = *last_t;
+                        }
+                    }
+                    event
+                },
+            );
+            write_events(&args.output, events, meta.plane.c() > 1)?
+        }
+        "dvs" => {
+            let params = DvsSimParams::default()
+                .theta(args.theta)
+                .refractory_period(args.refractory_period)
+                .noise_rate(args.noise_rate)
+                .seed(args.seed);
+            let mut error = None;
+            let events = AdderDvsEvents::new(&args.input, params)?
+                .map_while(|event| event.map_err(|e| error = Some(e)).ok());
+            let count = write_events(&args.output, events, false)?;
+            if let Some(e) = error {
+                return Err(e.into());
+            }
+            count
+        }
+        _ => panic!("Invalid event type. Use \"adder\" or \"dvs\""),
+    };
+
+    println!("Wrote {count} events to {}", args.output);
+    Ok(())
+}
+
+/// Write the events to a `.npz` archive or `.npy` array, depending on the output extension
+fn write_events<E: NpyEvent>(
+    output: &str,
+    events: impl Iterator<Item = E>,
+    color: bool,
+) -> Result<u64, Box<dyn error::Error>> {
+    let writer = BufWriter::new(File::create(output)?);
+    if Path::new(output)
+        .extension()
+        .is_some_and(|extension| extension == "npz")
+    {
+        let mut count = 0;
+        write_events_npz(writer, "events", events.inspect(|_| count += 1), color)?;
+        Ok(count)
+    } else {
+        let mut npy = NpyEventWriter::new(writer, color)?;
+        for event in events {
+            npy.write_event(&event)?;
+        }
+        let count = npy.len();
+        npy.finish()?;
+        Ok(count)
+    }
+}
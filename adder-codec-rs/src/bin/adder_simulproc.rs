@@ -12,7 +12,7 @@ use std::fs::File;
 use adder_codec_core::codec::{EncoderOptions, EncoderType};
 use adder_codec_core::SourceCamera::FramedU8;
 use adder_codec_core::{PixelMultiMode, TimeMode};
-use adder_codec_rs::transcoder::source::framed::Framed;
+use adder_codec_rs::transcoder::source::framed::{Framed, Rect};
 use std::io::{BufWriter, Cursor};
 use std::path::Path;
 use std::process::Command;
@@ -69,11 +69,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
     //////////////////////////////////////////////////////
 
     let mut source: Framed<BufWriter<File>> =
-        Framed::new(args.input_filename.into(), args.color_input, args.scale)?
-            // .chunk_rows(64)
-            .frame_start(args.frame_idx_start)?
-            .crf(args.crf)
-            .auto_time_parameters(args.ref_time, args.delta_t_max, None)?;
+        Framed::new(args.input_filename.into(), args.color_input, args.scale)?;
+    if !args.crop.is_empty() {
+        let regions = args
+            .crop
+            .split(';')
+            .map(str::parse)
+            .collect::<Result<Vec<Rect>, _>>()?;
+        source = source.regions(&regions)?;
+    }
+    source = source
+        // .chunk_rows(64)
+        .frame_start(args.frame_idx_start)?
+        .use_timestamps(args.use_timestamps)
+        .crf(args.crf)
+        .auto_time_parameters(args.ref_time, args.delta_t_max, None)?;
 
     if !args.output_events_filename.is_empty() {
         let path = Path::new(&args.output_events_filename);
@@ -150,7 +160,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 #[cfg(test)]
 mod tests {
     use adder_codec_core::codec::rate_controller::Crf;
-    use adder_codec_core::codec::{EncoderOptions, EncoderType};
+    use adder_codec_core::codec::{EncoderOptions, EncoderType, DEFAULT_MAX_QUEUED_EVENTS};
     use adder_codec_core::SourceCamera::FramedU8;
     use adder_codec_core::TimeMode;
     use adder_codec_core::{DeltaT, PixelMultiMode};
@@ -189,6 +199,8 @@ mod tests {
             time_mode: "delta_t".to_string(),
             crf: 0,
             integration_mode: "".to_string(),
+            use_timestamps: false,
+            crop: String::new(),
         };
         let mut source = Framed::new(args.input_filename.into(), args.color_input, args.scale)?
             // .chunk_rows(64)
@@ -216,6 +228,10 @@ mod tests {
                     event_drop: Default::default(),
                     event_order: Default::default(),
                     crf: Crf::new(Some(0), plane),
+                    channel_decorrelation: false,
+                    lossless: false,
+                    entropy_coder: Default::default(),
+                    max_queued_events: DEFAULT_MAX_QUEUED_EVENTS,
                 },
                 writer,
             )?;
@@ -1,5 +1,14 @@
+use adder_codec_core::bitstream_io::{BigEndian, BitReader};
+use adder_codec_core::codec::compressed::stream::{CompressedInput, CompressedOutput};
+use adder_codec_core::codec::decoder::Decoder;
+use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::codec::{
+    CodecError, CodecMetadata, EncoderOptions, EntropyCoder, LATEST_CODEC_VERSION,
+};
 use adder_codec_core::*;
 use clap::Parser;
+use std::io::Cursor;
+use std::time::Instant;
 use std::{error, io};
 
 /// Command line argument parser
@@ -9,6 +18,10 @@ pub struct MyArgs {
     /// Input ADΔER video path
     #[clap(short, long)]
     pub(crate) input: String,
+
+    /// Also compress the events with each entropy coder, and compare their decode throughput
+    #[clap(long, action)]
+    pub(crate) compare_entropy_coders: bool,
 }
 
 fn main() -> Result<(), Box<dyn error::Error>> {
@@ -30,5 +43,62 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     let duration = start_time.elapsed();
     println!("Time to digest all events: {:?}", duration);
 
+    if args.compare_entropy_coders {
+        stream.set_input_stream_position(&mut bitreader, first_event_position)?;
+        let mut events = Vec::new();
+        while let Ok(event) = stream.digest_event(&mut bitreader) {
+            events.push(event);
+        }
+
+        let mut meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            ..meta
+        };
+        if stream.meta().codec_version < 3 {
+            // Older streams don't record the Adu interval
+            meta.adu_interval = (meta.delta_t_max / meta.ref_interval) as usize;
+        }
+        for entropy_coder in [EntropyCoder::Arithmetic, EntropyCoder::Rans] {
+            time_entropy_coder(meta, &events, entropy_coder)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compress the events with the given entropy coder, then time how long they take to decode
+fn time_entropy_coder(
+    meta: CodecMetadata,
+    events: &[Event],
+    entropy_coder: EntropyCoder,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut options = EncoderOptions::default(meta.plane);
+    options.entropy_coder = entropy_coder;
+    let mut encoder = Encoder::new_compressed(CompressedOutput::new(meta, Vec::new()), options);
+    encoder.ingest_events(events)?;
+    let written = encoder
+        .close_writer()?
+        .ok_or(CodecError::UnitializedStream)?;
+    let compressed_len = written.len();
+
+    let compression = CompressedInput::new(meta.delta_t_max, meta.ref_interval, meta.adu_interval);
+    let mut bitreader = BitReader::endian(Cursor::new(written), BigEndian);
+    let mut decoder = Decoder::new_compressed(compression, &mut bitreader)?;
+
+    let start_time = Instant::now();
+    let mut event_count = 0;
+    while decoder.digest_event(&mut bitreader).is_ok() {
+        event_count += 1;
+    }
+    let duration = start_time.elapsed();
+    println!(
+        "{:?}: {} bytes, digested {} events in {:?} ({:.0} events/s)",
+        entropy_coder,
+        compressed_len,
+        event_count,
+        duration,
+        event_count as f64 / duration.as_secs_f64()
+    );
+
     Ok(())
 }
@@ -1,8 +1,8 @@
 use adder_codec_core::codec::rate_controller::{Crf, DEFAULT_CRF_QUALITY};
-use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::codec::{EncoderOptions, EncoderType, DEFAULT_MAX_QUEUED_EVENTS};
 use adder_codec_core::SourceCamera::Dvs;
 use adder_codec_core::{PixelMultiMode, PlaneSize, TimeMode};
-use adder_codec_rs::transcoder::source::prophesee::Prophesee;
+use adder_codec_rs::transcoder::source::prophesee::{Prophesee, PropheseeParams};
 use adder_codec_rs::transcoder::source::video::{Source, SourceError, VideoBuilder};
 use adder_codec_rs::utils::simulproc::SimulProcArgs;
 use adder_codec_rs::utils::viz::ShowFeatureMode;
@@ -18,7 +18,7 @@ pub struct MyArgs {
     #[clap(short, long, default_value_t = 1)]
     pub ref_time: u32,
 
-    /// Max number of ticks for first event at a new intensity
+    /// Max number of ticks for first event at a new intensity, as a multiple of `ref_time`
     #[clap(short, long, default_value_t = 2)]
     pub delta_t_max: u32,
 
@@ -40,14 +40,22 @@ pub struct MyArgs {
 
     #[clap(short, long, action)]
     pub features: bool,
+
+    /// Source timestamp (in microseconds) to integrate the final pixel intensities up to
+    #[clap(long)]
+    pub end_t: Option<u32>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut args: MyArgs = MyArgs::parse();
 
+    let mut params = PropheseeParams::default().delta_t_max_multiplier(args.delta_t_max);
+    if let Some(end_t) = args.end_t {
+        params = params.end_t(end_t);
+    }
     let mut prophesee_source: Prophesee<BufWriter<File>> =
-        Prophesee::new(args.ref_time, args.input)?.crf(args.crf);
+        Prophesee::new_with_params(args.ref_time, args.input, params)?.crf(args.crf);
     let adu_interval =
         (prophesee_source.get_video_ref().state.tps as f32 / args.ref_time as f32) as usize;
     let plane = prophesee_source.get_video_ref().state.plane;
@@ -63,6 +71,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             event_drop: Default::default(),
             event_order: Default::default(),
             crf: Crf::new(Some(args.crf), plane),
+            channel_decorrelation: false,
+            lossless: false,
+            entropy_coder: Default::default(),
+            max_queued_events: DEFAULT_MAX_QUEUED_EVENTS,
         },
         writer,
     )?;
@@ -88,6 +100,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         match prophesee_source.consume() {
             Ok(_) => {}
             Err(SourceError::Open) => return Ok(()),
+            Err(SourceError::EndOfStream) => {
+                prophesee_source.get_video_mut().end_write_stream()?;
+                return Ok(());
+            }
             Err(e) => {
                 eprintln!("Consume Error: {:?}", e);
                 prophesee_source.get_video_mut().end_write_stream()?;
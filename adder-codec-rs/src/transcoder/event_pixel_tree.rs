@@ -3,6 +3,7 @@ use adder_codec_core::{
     AbsoluteT, Coord, DeltaT, Event, Mode, PixelMultiMode, TimeMode, D, D_SHIFT_F32,
 };
 use adder_codec_core::{UDshift, D_EMPTY, D_MAX, D_SHIFT, D_ZERO_INTEGRATION};
+use serde::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
 use std::cmp::min;
 
@@ -13,7 +14,7 @@ pub type Intensity32 = f32;
 // pub type PixelAddress = u16;
 
 #[repr(packed)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Event32 {
     pub coord: Coord,
     pub d: D,
@@ -31,7 +32,7 @@ impl From<Event32> for Event {
 }
 
 #[repr(packed)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct PixelState {
     d: D,
     integration: Intensity32,
@@ -39,7 +40,7 @@ pub(crate) struct PixelState {
 }
 
 #[repr(packed)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct PixelNode {
     /// Specifies if the next pixel in the arena vec exists
     alt: Option<()>,
@@ -50,19 +51,26 @@ pub struct PixelNode {
 
 // Each PixelNode is ~20 bytes. Each PixelArena is at least 20 + (6*20) 140 bytes, but takes at
 // least 144 bytes of space, I think?
+#[derive(Serialize, Deserialize)]
 pub struct PixelArena {
     pub coord: Coord,
     time_mode: TimeMode,
     pub last_fired_t: f32,
     pub(crate) running_t: f32,
     length: usize,
-    pub base_val: u8,
+
+    /// The intensity the pixel last fired its events at, in the units of the source's intensities
+    pub(crate) base_val: f32,
     pub need_to_pop_top: bool,
     pub arena: SmallVec<[PixelNode; 6]>,
     pub(crate) c_thresh: u8,
     pub(crate) c_increase_counter: u8,
     dtm_reached: bool,
     popped_dtm: bool,
+
+    /// Whether the pixel is in a region of interest. Pixels outside every region are never
+    /// integrated, so they never fire events.
+    pub(crate) in_roi: bool,
 }
 
 impl PixelArena {
@@ -76,13 +84,14 @@ impl PixelArena {
             time_mode: TimeMode::default(),
             last_fired_t: 0.0,
             running_t: 0.0,
-            base_val: 0,
+            base_val: 0.0,
             need_to_pop_top: false,
             arena,
             c_thresh: 10,
             c_increase_counter: 1,
             dtm_reached: false,
             popped_dtm: false,
+            in_roi: true,
         }
     }
 
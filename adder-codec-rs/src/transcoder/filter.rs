@@ -0,0 +1,373 @@
+use adder_codec_core::{AbsoluteT, DeltaT, Event, PlaneSize};
+
+/// A stage which decides whether each generated event should be passed on to the encoder.
+///
+/// Events are expected to arrive in (roughly) increasing order of their absolute timestamps.
+pub trait EventFilter: Send {
+    /// Returns `true` if the event should be kept
+    fn keep(&mut self, event: &Event) -> bool;
+
+    /// Drop the events which shouldn't be kept from a batch of events, which is divided into
+    /// chunks. Filters which judge an event by the other events around it override this, so that
+    /// their decisions don't depend on the order of the events within the batch.
+    fn retain(&mut self, batch: &mut [Vec<Event>]) {
+        for events in batch {
+            events.retain(|event| self.keep(event));
+        }
+    }
+}
+
+/// The index of the event's pixel channel in a flat array of the plane
+fn px_index(plane: &PlaneSize, event: &Event) -> usize {
+    (event.coord.y_usize() * plane.w_usize() + event.coord.x_usize()) * plane.c_usize()
+        + event.coord.c_usize()
+}
+
+/// A sequence of filters, applied in order. An event is kept only if every filter keeps it, and
+/// an event dropped by one filter is not seen by the filters after it.
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn EventFilter>>,
+
+    /// The number of events dropped so far
+    dropped: u64,
+}
+
+impl FilterChain {
+    /// Create an empty filter chain, which keeps every event
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a filter to the end of the chain
+    #[must_use]
+    pub fn with(mut self, filter: impl EventFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Whether the chain has no filters
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// The number of events dropped by the chain so far
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl EventFilter for FilterChain {
+    fn keep(&mut self, event: &Event) -> bool {
+        let keep = self.filters.iter_mut().all(|filter| filter.keep(event));
+        if !keep {
+            self.dropped += 1;
+        }
+        keep
+    }
+
+    fn retain(&mut self, batch: &mut [Vec<Event>]) {
+        let batch_len = batch.iter().map(Vec::len).sum::<usize>();
+        for filter in &mut self.filters {
+            filter.retain(batch);
+        }
+        self.dropped += (batch_len - batch.iter().map(Vec::len).sum::<usize>()) as u64;
+    }
+}
+
+/// Drop any event which fires within `period` ticks of the last kept event of the same pixel
+/// channel
+pub struct RefractoryFilter {
+    plane: PlaneSize,
+    period: DeltaT,
+    last_t: Vec<Option<AbsoluteT>>,
+}
+
+impl RefractoryFilter {
+    /// Create a refractory filter for the given plane
+    pub fn new(plane: PlaneSize, period: DeltaT) -> Self {
+        Self {
+            plane,
+            period,
+            last_t: vec![None; plane.volume()],
+        }
+    }
+}
+
+impl EventFilter for RefractoryFilter {
+    fn keep(&mut self, event: &Event) -> bool {
+        let last_t = &mut self.last_t[px_index(&self.plane, event)];
+        match *last_t {
+            Some(t) if event.t.saturating_sub(t) < self.period => false,
+            _ => {
+                *last_t = Some(event.t);
+                true
+            }
+        }
+    }
+}
+
+/// Suppress the events of hot pixels: those which fire far more often than the other pixels.
+///
+/// Time is divided into windows of `window` ticks. A pixel channel is hot for a window if, in the
+/// window before it, it fired more than `ratio` times as many events as the average active pixel
+/// channel did.
+///
+/// Each event is counted in the window its timestamp falls in, rather than the latest window
+/// seen, and [`retain`](EventFilter::retain) counts a whole batch before judging any of it. The
+/// events kept from a batch therefore don't depend on the order of the events within it.
+pub struct HotPixelFilter {
+    plane: PlaneSize,
+    window: DeltaT,
+    ratio: f64,
+
+    /// The counts of the latest windows, where window `i` is kept at index `i % HOT_WINDOWS`
+    windows: Vec<WindowCounts>,
+
+    /// The index of the latest window seen
+    latest_window: AbsoluteT,
+}
+
+/// How many windows of counts the [`HotPixelFilter`] keeps: enough to judge events which arrive
+/// up to one window late
+const HOT_WINDOWS: usize = 3;
+
+/// The number of events of each pixel channel in one window
+struct WindowCounts {
+    window_idx: AbsoluteT,
+    counts: Vec<u32>,
+    total: u64,
+    active: u64,
+}
+
+impl HotPixelFilter {
+    /// Create a hot pixel filter for the given plane
+    pub fn new(plane: PlaneSize, window: DeltaT, ratio: f64) -> Self {
+        Self {
+            plane,
+            window: window.max(1),
+            ratio,
+            windows: (0..HOT_WINDOWS)
+                .map(|_| WindowCounts {
+                    window_idx: 0,
+                    counts: vec![0; plane.volume()],
+                    total: 0,
+                    active: 0,
+                })
+                .collect(),
+            latest_window: 0,
+        }
+    }
+
+    /// Whether the pixel channel at the given index is currently considered hot
+    pub fn is_hot(&self, idx: usize) -> bool {
+        self.is_hot_in(idx, self.latest_window)
+    }
+
+    /// Whether the pixel channel at the given index is hot in the given window. If the window
+    /// before it had no events, or is too old to still be counted, nothing is hot.
+    fn is_hot_in(&self, idx: usize, window_idx: AbsoluteT) -> bool {
+        let Some(prev_idx) = window_idx.checked_sub(1) else {
+            return false;
+        };
+        let prev = &self.windows[prev_idx as usize % HOT_WINDOWS];
+        prev.window_idx == prev_idx
+            && prev.active > 0
+            && f64::from(prev.counts[idx]) > self.ratio * prev.total as f64 / prev.active as f64
+    }
+
+    /// Count the event in the window its timestamp falls in
+    fn count(&mut self, event: &Event) {
+        let window_idx = event.t / self.window;
+        let idx = px_index(&self.plane, event);
+        let window = &mut self.windows[window_idx as usize % HOT_WINDOWS];
+        if window.window_idx < window_idx {
+            // Reuse the counts of a window which is too old to matter anymore
+            window.window_idx = window_idx;
+            window.counts.fill(0);
+            window.total = 0;
+            window.active = 0;
+        } else if window.window_idx > window_idx {
+            // The event's window is already too old to be counted
+            return;
+        }
+        if window.counts[idx] == 0 {
+            window.active += 1;
+        }
+        window.counts[idx] += 1;
+        window.total += 1;
+        self.latest_window = self.latest_window.max(window_idx);
+    }
+}
+
+impl EventFilter for HotPixelFilter {
+    fn keep(&mut self, event: &Event) -> bool {
+        self.count(event);
+        !self.is_hot_in(px_index(&self.plane, event), event.t / self.window)
+    }
+
+    fn retain(&mut self, batch: &mut [Vec<Event>]) {
+        for event in batch.iter().flatten() {
+            self.count(event);
+        }
+        for events in batch {
+            events.retain(|event| {
+                !self.is_hot_in(px_index(&self.plane, event), event.t / self.window)
+            });
+        }
+    }
+}
+
+/// Drop isolated events: those with no event at a spatially neighboring pixel (in any channel)
+/// within the preceding `window` ticks
+pub struct NeighborhoodFilter {
+    plane: PlaneSize,
+    radius: u16,
+    window: DeltaT,
+
+    /// The timestamp of the latest event at each (y, x) pixel, kept or not
+    last_t: Vec<Option<AbsoluteT>>,
+}
+
+impl NeighborhoodFilter {
+    /// Create a neighborhood filter for the given plane, considering the pixels within `radius` of
+    /// each event
+    pub fn new(plane: PlaneSize, radius: u16, window: DeltaT) -> Self {
+        Self {
+            plane,
+            radius: radius.max(1),
+            window,
+            last_t: vec![None; plane.area_wh()],
+        }
+    }
+}
+
+impl EventFilter for NeighborhoodFilter {
+    fn keep(&mut self, event: &Event) -> bool {
+        let (x, y) = (event.coord.x, event.coord.y);
+        let x_range =
+            x.saturating_sub(self.radius)..=x.saturating_add(self.radius).min(self.plane.w() - 1);
+        let mut y_range =
+            y.saturating_sub(self.radius)..=y.saturating_add(self.radius).min(self.plane.h() - 1);
+        let width = self.plane.w_usize();
+
+        let keep = y_range.any(|ny| {
+            x_range.clone().any(|nx| {
+                (nx, ny) != (x, y)
+                    && matches!(
+                        self.last_t[ny as usize * width + nx as usize],
+                        Some(t) if event.t.saturating_sub(t) <= self.window
+                    )
+            })
+        });
+        self.last_t[y as usize * width + x as usize] = Some(event.t);
+        keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adder_codec_core::Coord;
+
+    fn event(x: u16, y: u16, t: AbsoluteT) -> Event {
+        Event {
+            coord: Coord { x, y, c: None },
+            d: 7,
+            t,
+        }
+    }
+
+    fn plane() -> PlaneSize {
+        PlaneSize::new(8, 8, 1).unwrap()
+    }
+
+    #[test]
+    fn refractory() {
+        let mut filter = RefractoryFilter::new(plane(), 100);
+        assert!(filter.keep(&event(1, 1, 0)));
+        assert!(!filter.keep(&event(1, 1, 50)));
+        assert!(filter.keep(&event(2, 1, 50)));
+        assert!(filter.keep(&event(1, 1, 100)));
+        assert!(!filter.keep(&event(1, 1, 199)));
+    }
+
+    #[test]
+    fn hot_pixel() {
+        let mut filter = HotPixelFilter::new(plane(), 1000, 3.0);
+
+        // In the first window, pixel (0, 0) fires 50 times and four other pixels fire once each
+        for t in 0..50 {
+            assert!(filter.keep(&event(0, 0, t * 10)));
+        }
+        for x in 1..5 {
+            assert!(filter.keep(&event(x, 3, 600)));
+        }
+
+        // In the next window, only the hot pixel is suppressed
+        assert!(!filter.keep(&event(0, 0, 1000)));
+        assert!(filter.keep(&event(1, 3, 1000)));
+        assert!(filter.is_hot(0));
+
+        // After an empty window, nothing is hot
+        assert!(filter.keep(&event(0, 0, 3000)));
+    }
+
+    #[test]
+    fn hot_pixel_shuffled() {
+        // The hot pixel's events in the second window come first, and are still suppressed
+        let mut events = Vec::new();
+        for t in 0..50 {
+            events.push(event(0, 0, t * 10));
+            events.push(event(0, 0, 1000 + t * 10));
+        }
+        for x in 1..5 {
+            events.push(event(x, 3, 600));
+            events.push(event(x, 3, 1600));
+        }
+        let mut shuffled = events.clone();
+        shuffled.reverse();
+        shuffled.rotate_left(37);
+        let shuffled_chunks = vec![shuffled.split_off(60), shuffled];
+
+        let mut kept = Vec::new();
+        for mut batch in [vec![events], shuffled_chunks] {
+            HotPixelFilter::new(plane(), 1000, 3.0).retain(&mut batch);
+            let mut events = batch.concat();
+            events.sort_unstable_by_key(|event| (event.t, event.coord.y, event.coord.x));
+            kept.push(events);
+        }
+        assert_eq!(kept[0], kept[1]);
+        assert_eq!(kept[0].len(), 50 + 8);
+        assert!(kept[0]
+            .iter()
+            .all(|event| event.t < 1000 || event.coord.x != 0));
+    }
+
+    #[test]
+    fn neighborhood() {
+        let mut filter = NeighborhoodFilter::new(plane(), 1, 100);
+        assert!(!filter.keep(&event(4, 4, 0)));
+        assert!(filter.keep(&event(5, 5, 50)));
+        assert!(!filter.keep(&event(7, 7, 60)));
+        assert!(!filter.keep(&event(4, 5, 500)));
+
+        // Edge pixels have fewer neighbors, but still work
+        assert!(!filter.keep(&event(0, 0, 1000)));
+        assert!(filter.keep(&event(1, 0, 1001)));
+    }
+
+    #[test]
+    fn chain() {
+        let mut chain = FilterChain::new()
+            .with(RefractoryFilter::new(plane(), 100))
+            .with(NeighborhoodFilter::new(plane(), 1, 100));
+        assert!(!chain.keep(&event(4, 4, 0)));
+        assert!(chain.keep(&event(4, 5, 10)));
+
+        // Dropped by the refractory filter, so the neighborhood filter never sees it
+        assert!(!chain.keep(&event(4, 5, 20)));
+        assert!(!chain.keep(&event(6, 6, 30)));
+        assert_eq!(chain.dropped_count(), 3);
+    }
+}
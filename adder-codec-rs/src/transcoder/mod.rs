@@ -1,5 +1,8 @@
 mod d_controller;
 pub(crate) mod event_pixel_tree;
 
+/// Filters for removing noisy events before they're encoded
+pub mod filter;
+
 /// The tools for casting various source videos to ADΔER
 pub mod source;
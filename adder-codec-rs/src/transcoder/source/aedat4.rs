@@ -0,0 +1,740 @@
+use crate::transcoder::source::dvs::{
+    dvs_video, ingest_integrated_events, DvsEvent, DvsIntegrator, DvsParams, START_T,
+};
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::PathBuf;
+use video_rs_adder_dep::Frame;
+
+/// The version string at the start of every AEDAT4 file
+const AEDAT4_VERSION: &[u8; 14] = b"#!AER-DAT4.0\r\n";
+
+/// The type identifier of a stream of DVS events
+const EVENTS_TYPE_IDENTIFIER: &str = "EVTS";
+
+/// The size of each event in an event packet: an i64 timestamp, i16 x and y coordinates, and a
+/// bool polarity, padded to the alignment of the timestamp
+const EVENT_SIZE: usize = 16;
+
+/// The compression applied to each packet of an AEDAT4 file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aedat4Compression {
+    /// Uncompressed
+    None,
+
+    /// LZ4 frames
+    Lz4,
+
+    /// LZ4 frames, at a high compression level
+    Lz4High,
+
+    /// Zstandard frames
+    Zstd,
+
+    /// Zstandard frames, at a high compression level
+    ZstdHigh,
+}
+
+impl TryFrom<i32> for Aedat4Compression {
+    type Error = io::Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Aedat4Compression::None),
+            1 => Ok(Aedat4Compression::Lz4),
+            2 => Ok(Aedat4Compression::Lz4High),
+            3 => Ok(Aedat4Compression::Zstd),
+            4 => Ok(Aedat4Compression::ZstdHigh),
+            _ => Err(invalid_data("Unknown AEDAT4 compression type")),
+        }
+    }
+}
+
+/// A stream of packets declared in the header of an AEDAT4 file
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Aedat4Stream {
+    /// The id of the stream, which each of its packets is tagged with
+    pub id: i32,
+
+    /// The type of the stream's packets, such as `EVTS` for DVS events or `FRME` for frames
+    pub type_identifier: String,
+
+    /// The (width, height) of the sensor which produced the stream, if known
+    pub size: Option<(u16, u16)>,
+}
+
+/// A DVS event, as stored in an AEDAT4 file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aedat4Event {
+    /// The timestamp of the event, in microseconds since the Unix epoch
+    pub t: i64,
+
+    /// The x-coordinate of the pixel
+    pub x: u16,
+
+    /// The y-coordinate of the pixel
+    pub y: u16,
+
+    /// Whether the intensity increased
+    pub on: bool,
+}
+
+/// Reads the DVS events of an AEDAT4 file, skipping the packets of every other stream
+pub struct Aedat4Reader<R: Read> {
+    reader: R,
+    compression: Aedat4Compression,
+    streams: Vec<Aedat4Stream>,
+
+    /// The id of the stream of DVS events
+    events_stream_id: i32,
+
+    /// The position of the file's data table, which follows the last packet
+    data_table_position: Option<u64>,
+    position: u64,
+    pending: VecDeque<Aedat4Event>,
+}
+
+impl<R: Read> Aedat4Reader<R> {
+    /// Parse the header of an AEDAT4 file, and leave the reader at the start of its first packet
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut version = [0; AEDAT4_VERSION.len()];
+        reader.read_exact(&mut version)?;
+        if version != *AEDAT4_VERSION {
+            return Err(invalid_data("Not an AEDAT4 file"));
+        }
+
+        let mut size = [0; 4];
+        reader.read_exact(&mut size)?;
+        let size = i32::from_le_bytes(size);
+        let mut header = vec![0; usize::try_from(size).map_err(|_| invalid_data("Bad header"))?];
+        reader.read_exact(&mut header)?;
+
+        let table = FlatTable::root(&header)?;
+        let compression = Aedat4Compression::try_from(table.i32(0, 0)?)?;
+        let data_table_position = u64::try_from(table.i64(1, -1)?).ok();
+        let streams = parse_info_node(table.string(2)?.unwrap_or_default());
+        let events_stream_id = streams
+            .iter()
+            .find(|stream| stream.type_identifier == EVENTS_TYPE_IDENTIFIER)
+            .ok_or_else(|| invalid_data("AEDAT4 file has no DVS event stream"))?
+            .id;
+
+        Ok(Self {
+            reader,
+            compression,
+            streams,
+            events_stream_id,
+            data_table_position,
+            position: (AEDAT4_VERSION.len() + 4 + header.len()) as u64,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// The compression applied to each packet
+    pub fn compression(&self) -> Aedat4Compression {
+        self.compression
+    }
+
+    /// The streams declared in the header
+    pub fn streams(&self) -> &[Aedat4Stream] {
+        &self.streams
+    }
+
+    /// The (width, height) of the sensor which produced the DVS events, if known
+    pub fn resolution(&self) -> Option<(u16, u16)> {
+        self.streams
+            .iter()
+            .find(|stream| stream.id == self.events_stream_id)
+            .and_then(|stream| stream.size)
+    }
+
+    /// Read the next DVS event, or `None` at the end of the file
+    pub fn next_event(&mut self) -> io::Result<Option<Aedat4Event>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            if !self.read_packet()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Read the next packet, queueing its events if it belongs to the DVS event stream. Returns
+    /// `false` at the end of the file.
+    fn read_packet(&mut self) -> io::Result<bool> {
+        if self.data_table_position == Some(self.position) {
+            return Ok(false);
+        }
+
+        let mut packet_header = [0; 8];
+        match self.reader.read_exact(&mut packet_header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let stream_id = i32::from_le_bytes(packet_header[0..4].try_into().unwrap());
+        let size = i32::from_le_bytes(packet_header[4..8].try_into().unwrap());
+        let size = usize::try_from(size).map_err(|_| invalid_data("Bad packet size"))?;
+        let mut data = vec![0; size];
+        self.reader.read_exact(&mut data)?;
+        self.position += (packet_header.len() + size) as u64;
+
+        if stream_id != self.events_stream_id {
+            return Ok(true);
+        }
+
+        let data = self.decompress(data)?;
+
+        // Each packet is a size-prefixed flatbuffer
+        let buffer = match data.get(0..4) {
+            Some(prefix)
+                if u32::from_le_bytes(prefix.try_into().unwrap()) as usize + 4 == data.len() =>
+            {
+                &data[4..]
+            }
+            _ => &data[..],
+        };
+        let table = FlatTable::root(buffer)?;
+        if let Some((start, len)) = table.vector(0)? {
+            for i in 0..len {
+                let element = start + i * EVENT_SIZE;
+                self.pending.push_back(Aedat4Event {
+                    t: i64::from_le_bytes(read_array(buffer, element)?),
+                    x: i16::from_le_bytes(read_array(buffer, element + 8)?) as u16,
+                    y: i16::from_le_bytes(read_array(buffer, element + 10)?) as u16,
+                    on: read_array::<1>(buffer, element + 12)?[0] != 0,
+                });
+            }
+        }
+        Ok(true)
+    }
+
+    fn decompress(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self.compression {
+            Aedat4Compression::None => return Ok(data),
+            Aedat4Compression::Lz4 | Aedat4Compression::Lz4High => {
+                lz4_flex::frame::FrameDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+            }
+            Aedat4Compression::Zstd | Aedat4Compression::ZstdHigh => {
+                ruzstd::StreamingDecoder::new(&data[..])
+                    .map_err(|e| invalid_data(&e.to_string()))?
+                    .read_to_end(&mut decompressed)?;
+            }
+        }
+        Ok(decompressed)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_array<const N: usize>(buffer: &[u8], position: usize) -> io::Result<[u8; N]> {
+    buffer
+        .get(position..position + N)
+        .map(|bytes| bytes.try_into().unwrap())
+        .ok_or_else(|| invalid_data("Truncated AEDAT4 flatbuffer"))
+}
+
+/// Just enough of a flatbuffer table reader for the AEDAT4 header and event packets
+struct FlatTable<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> FlatTable<'a> {
+    /// The root table of a flatbuffer
+    fn root(buffer: &'a [u8]) -> io::Result<Self> {
+        Ok(Self {
+            buffer,
+            position: u32::from_le_bytes(read_array(buffer, 0)?) as usize,
+        })
+    }
+
+    /// The position of the field with the given index, or `None` if it's absent
+    fn field(&self, index: usize) -> io::Result<Option<usize>> {
+        let vtable_offset = i32::from_le_bytes(read_array(self.buffer, self.position)?);
+        let vtable = usize::try_from(self.position as i64 - i64::from(vtable_offset))
+            .map_err(|_| invalid_data("Bad flatbuffer vtable"))?;
+        let vtable_size = usize::from(u16::from_le_bytes(read_array(self.buffer, vtable)?));
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_size {
+            return Ok(None);
+        }
+        let offset = u16::from_le_bytes(read_array(self.buffer, vtable + entry)?);
+        Ok((offset != 0).then_some(self.position + usize::from(offset)))
+    }
+
+    fn i32(&self, index: usize, default: i32) -> io::Result<i32> {
+        match self.field(index)? {
+            Some(position) => Ok(i32::from_le_bytes(read_array(self.buffer, position)?)),
+            None => Ok(default),
+        }
+    }
+
+    fn i64(&self, index: usize, default: i64) -> io::Result<i64> {
+        match self.field(index)? {
+            Some(position) => Ok(i64::from_le_bytes(read_array(self.buffer, position)?)),
+            None => Ok(default),
+        }
+    }
+
+    /// The position of the first element of a vector field, and its length
+    fn vector(&self, index: usize) -> io::Result<Option<(usize, usize)>> {
+        match self.field(index)? {
+            Some(position) => {
+                let start =
+                    position + u32::from_le_bytes(read_array(self.buffer, position)?) as usize;
+                let len = u32::from_le_bytes(read_array(self.buffer, start)?) as usize;
+                Ok(Some((start + 4, len)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn string(&self, index: usize) -> io::Result<Option<&'a str>> {
+        match self.vector(index)? {
+            Some((start, len)) => {
+                let bytes = self
+                    .buffer
+                    .get(start..start + len)
+                    .ok_or_else(|| invalid_data("Truncated AEDAT4 flatbuffer"))?;
+                Ok(Some(
+                    std::str::from_utf8(bytes).map_err(|_| invalid_data("Bad AEDAT4 string"))?,
+                ))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Get the streams declared in the XML info node of the header. Each stream is a numbered
+/// `<node>` holding a `typeIdentifier` attribute, and its `info` node holds the `sizeX` and
+/// `sizeY` of the sensor.
+fn parse_info_node(info: &str) -> Vec<Aedat4Stream> {
+    let mut streams: Vec<Aedat4Stream> = Vec::new();
+    let mut size = (None, None);
+    for tag in info.split('<').skip(1) {
+        if let Some(node) = tag.strip_prefix("node ") {
+            if let Some(id) = xml_attribute(node, "name").and_then(|name| name.parse().ok()) {
+                streams.push(Aedat4Stream {
+                    id,
+                    ..Default::default()
+                });
+                size = (None, None);
+            }
+        } else if let Some(attr) = tag.strip_prefix("attr ") {
+            let (Some(stream), Some(key), Some((_, value))) = (
+                streams.last_mut(),
+                xml_attribute(attr, "key"),
+                attr.split_once('>'),
+            ) else {
+                continue;
+            };
+            match key {
+                "typeIdentifier" => stream.type_identifier = value.trim().to_string(),
+                "sizeX" => size.0 = value.trim().parse().ok(),
+                "sizeY" => size.1 = value.trim().parse().ok(),
+                _ => {}
+            }
+            if let (Some(width), Some(height)) = size {
+                stream.size = Some((width, height));
+            }
+        }
+    }
+    streams
+}
+
+/// Get the value of an attribute of an XML tag
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{name}=\""))? + name.len() + 2;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+/// Attributes of an AEDAT4 DVS recording -> ADΔER transcode
+pub struct Aedat4<W: Write + std::marker::Send + std::marker::Sync + 'static> {
+    pub(crate) video: Video<W>,
+
+    reader: Aedat4Reader<BufReader<File>>,
+
+    /// The per-pixel log intensity model of the DVS events
+    pub integrator: DvsIntegrator,
+
+    params: DvsParams,
+
+    /// The timestamp of the first event, which is integrated at the start time of the integrator
+    first_t: Option<i64>,
+
+    /// Whether the end of the input file has been reached
+    ended: bool,
+}
+
+unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Sync for Aedat4<W> {}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Aedat4<W> {
+    /// Create a new `Aedat4` transcoder, with the resolution given in the file's header
+    pub fn new(
+        ref_time: u32,
+        input_filename: String,
+        params: DvsParams,
+    ) -> Result<Self, Box<dyn Error>> {
+        let source = File::open(PathBuf::from(input_filename))?;
+        let reader = Aedat4Reader::new(BufReader::new(source))?;
+        let (width, height) = reader
+            .resolution()
+            .ok_or_else(|| invalid_data("AEDAT4 file doesn't give the sensor resolution"))?;
+
+        let plane = PlaneSize::new(width, height, 1)?;
+        let mut video = dvs_video(plane, ref_time, &params)?;
+        let integrator = DvsIntegrator::new(&mut video, &params);
+
+        Ok(Aedat4 {
+            video,
+            reader,
+            integrator,
+            params,
+            first_t: None,
+            ended: false,
+        })
+    }
+
+    /// The reader of the source file
+    pub fn reader(&self) -> &Aedat4Reader<BufReader<File>> {
+        &self.reader
+    }
+
+    /// The sensor and model parameters of the transcode
+    pub fn params(&self) -> &DvsParams {
+        &self.params
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for Aedat4<W> {
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if self.ended {
+            return Err(SourceError::EndOfStream);
+        }
+
+        self.integrator.start(&mut self.video)?;
+
+        // Read events until we find a timestamp that exceeds our `running_t` by at least
+        // `view_interval`, or reach the end of the file
+        let mut dvs_events: Vec<DvsEvent> = Vec::new();
+        let start_running_t = self.integrator.running_t();
+        loop {
+            let Some(event) = self.reader.next_event()? else {
+                self.ended = true;
+                break;
+            };
+            let first_t = *self.first_t.get_or_insert(event.t);
+            let t = u32::try_from((event.t - first_t).max(0))
+                .ok()
+                .and_then(|t| t.checked_add(START_T))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "timestamp {} is too far after the first event at {first_t}",
+                            event.t
+                        ),
+                    )
+                })?;
+            let dvs_event = DvsEvent {
+                t,
+                x: event.x,
+                y: event.y,
+                p: u8::from(event.on),
+            };
+            dvs_events.push(dvs_event);
+            if dvs_event.t > start_running_t + self.params.view_interval {
+                break;
+            }
+        }
+
+        let mut events: Vec<Event> = Vec::new();
+        self.integrator
+            .integrate(&mut self.video, &dvs_events, &mut events);
+        if self.ended {
+            self.integrator
+                .end(&mut self.video, self.params.end_t, &mut events);
+        }
+
+        ingest_integrated_events(&mut self.video, events)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        None
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W> for Aedat4<W> {
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO_NODE: &str = r#"<dv version="2.0">
+    <node name="outInfo" path="/mainloop/Recorder/outInfo/">
+        <node name="0" path="/mainloop/Recorder/outInfo/0/">
+            <attr key="typeIdentifier" type="string">FRME</attr>
+            <node name="info" path="/mainloop/Recorder/outInfo/0/info/">
+                <attr key="sizeX" type="int">8</attr>
+                <attr key="sizeY" type="int">6</attr>
+            </node>
+        </node>
+        <node name="1" path="/mainloop/Recorder/outInfo/1/">
+            <attr key="compression" type="string">NONE</attr>
+            <attr key="typeIdentifier" type="string">EVTS</attr>
+            <node name="info" path="/mainloop/Recorder/outInfo/1/info/">
+                <attr key="sizeX" type="int">4</attr>
+                <attr key="sizeY" type="int">3</attr>
+            </node>
+        </node>
+    </node>
+</dv>"#;
+
+    /// A flatbuffer with a root table of a single `u32` field, pointing at `data`
+    fn flatbuffer(data: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&12_u32.to_le_bytes()); // Root table offset
+        buffer.extend_from_slice(&6_u16.to_le_bytes()); // Vtable size
+        buffer.extend_from_slice(&8_u16.to_le_bytes()); // Table size
+        buffer.extend_from_slice(&4_u16.to_le_bytes()); // Field 0 offset
+        buffer.extend_from_slice(&[0, 0]);
+        buffer.extend_from_slice(&8_i32.to_le_bytes()); // Table, with its vtable 8 bytes back
+        buffer.extend_from_slice(&4_u32.to_le_bytes()); // Field 0: the data follows
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    /// An AEDAT4 file of a frame packet and two event packets
+    fn file(events: &[[Aedat4Event; 2]]) -> Vec<u8> {
+        let mut file = AEDAT4_VERSION.to_vec();
+
+        // The header's fields are all defaults, other than the info node
+        let mut info = (INFO_NODE.len() as u32).to_le_bytes().to_vec();
+        info.extend_from_slice(INFO_NODE.as_bytes());
+        let mut header = Vec::new();
+        header.extend_from_slice(&16_u32.to_le_bytes());
+        header.extend_from_slice(&10_u16.to_le_bytes());
+        header.extend_from_slice(&8_u16.to_le_bytes());
+        header.extend_from_slice(&[0, 0, 0, 0, 4, 0, 0, 0]); // Only field 2 is present
+        header.extend_from_slice(&12_i32.to_le_bytes());
+        header.extend_from_slice(&4_u32.to_le_bytes());
+        header.extend_from_slice(&info);
+        file.extend_from_slice(&(header.len() as i32).to_le_bytes());
+        file.extend_from_slice(&header);
+
+        // A packet of another stream, which is skipped
+        file.extend_from_slice(&0_i32.to_le_bytes());
+        file.extend_from_slice(&3_i32.to_le_bytes());
+        file.extend_from_slice(&[1, 2, 3]);
+
+        for packet in events {
+            let mut elements = (packet.len() as u32).to_le_bytes().to_vec();
+            for event in packet {
+                elements.extend_from_slice(&event.t.to_le_bytes());
+                elements.extend_from_slice(&(event.x as i16).to_le_bytes());
+                elements.extend_from_slice(&(event.y as i16).to_le_bytes());
+                elements.extend_from_slice(&[u8::from(event.on), 0, 0, 0]);
+            }
+            let buffer = flatbuffer(&elements);
+            let mut data = (buffer.len() as u32).to_le_bytes().to_vec();
+            data.extend_from_slice(&buffer);
+
+            file.extend_from_slice(&1_i32.to_le_bytes());
+            file.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            file.extend_from_slice(&data);
+        }
+        file
+    }
+
+    fn event(t: i64, x: u16, y: u16, on: bool) -> Aedat4Event {
+        Aedat4Event { t, x, y, on }
+    }
+
+    #[test]
+    fn info_node() {
+        let streams = parse_info_node(INFO_NODE);
+        assert_eq!(
+            streams,
+            vec![
+                Aedat4Stream {
+                    id: 0,
+                    type_identifier: "FRME".to_string(),
+                    size: Some((8, 6)),
+                },
+                Aedat4Stream {
+                    id: 1,
+                    type_identifier: EVENTS_TYPE_IDENTIFIER.to_string(),
+                    size: Some((4, 3)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_events() -> io::Result<()> {
+        let t = 1_700_000_000_000_000;
+        let events = [
+            [event(t, 1, 2, true), event(t + 5, 3, 0, false)],
+            [event(t + 10, 0, 0, false), event(t + 10, 2, 2, true)],
+        ];
+        let file = file(&events);
+        let mut reader = Aedat4Reader::new(&file[..])?;
+        assert_eq!(reader.compression(), Aedat4Compression::None);
+        assert_eq!(reader.resolution(), Some((4, 3)));
+
+        let mut read = Vec::new();
+        while let Some(event) = reader.next_event()? {
+            read.push(event);
+        }
+        assert_eq!(read, events.concat());
+        Ok(())
+    }
+
+    #[test]
+    fn consume() -> Result<(), Box<dyn Error>> {
+        let t = 1_700_000_000_000_000;
+        let events = [[event(t, 1, 2, true), event(t + 5, 3, 0, false)]];
+        let path = std::env::temp_dir().join(format!("adder_aedat4_{}.aedat4", std::process::id()));
+        std::fs::write(&path, file(&events))?;
+
+        let source = Aedat4::new(10, path.to_str().unwrap().to_string(), DvsParams::default());
+        std::fs::remove_file(&path)?;
+        let mut source: Aedat4<io::Sink> = source?;
+        assert_eq!(source.get_video_ref().state.plane.w(), 4);
+        assert_eq!(source.get_video_ref().state.plane.h(), 3);
+
+        let events = source.consume()?;
+        assert!(!events[0].is_empty());
+        assert_eq!(source.integrator.running_t(), START_T + 5);
+        assert_eq!(
+            source.integrator.dvs_last_timestamps[[2, 1, 0]],
+            START_T + 5
+        );
+        assert!(matches!(source.consume(), Err(SourceError::EndOfStream)));
+        Ok(())
+    }
+
+    #[test]
+    fn consume_long_recording() -> Result<(), Box<dyn Error>> {
+        // The second event is more than 2^32 microseconds after the first
+        let t = 1_700_000_000_000_000;
+        let events = [[event(t, 1, 2, true), event(t + (1 << 32), 3, 0, false)]];
+        let path =
+            std::env::temp_dir().join(format!("adder_aedat4_long_{}.aedat4", std::process::id()));
+        std::fs::write(&path, file(&events))?;
+
+        let source = Aedat4::new(10, path.to_str().unwrap().to_string(), DvsParams::default());
+        std::fs::remove_file(&path)?;
+        let mut source: Aedat4<io::Sink> = source?;
+        assert!(matches!(
+            source.consume(),
+            Err(SourceError::IoError(e)) if e.kind() == io::ErrorKind::InvalidData
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+use crate::transcoder::source::dvs::{
+    dvs_video, ingest_integrated_events, DvsEvent, DvsParams, DVS_SOURCE_TPS,
+};
+use crate::transcoder::source::prophesee::{parse_header, EventDecoder};
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::{
+    Coord, DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode, D, D_EMPTY, D_MAX,
+};
+use ndarray::Array2;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::PathBuf;
+use video_rs_adder_dep::Frame;
+
+/// Sensor and model parameters for an [`Atis`] transcode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtisParams {
+    pub(crate) dvs: DvsParams,
+    pub(crate) threshold_d: D,
+}
+
+impl Default for AtisParams {
+    fn default() -> Self {
+        Self {
+            // Exposure measurements can last up to a second in the dark
+            dvs: DvsParams::default().delta_t_max_multiplier(DVS_SOURCE_TPS),
+            threshold_d: 15,
+        }
+    }
+}
+
+impl AtisParams {
+    /// Set the parameters shared with DVS sources. The contrast threshold is only used to update
+    /// the display between exposure measurements. Default `delta_t_max` is one second of source
+    /// time.
+    #[must_use]
+    pub fn dvs_params(mut self, dvs: DvsParams) -> Self {
+        self.dvs = dvs;
+        self
+    }
+
+    /// Set the D of the charge that a pixel integrates between the two thresholds of an exposure
+    /// measurement. A measurement of `t` microseconds has an 8-bit intensity of `2^D / t`, so the
+    /// default of 15 saturates for exposures shorter than 128 microseconds.
+    #[must_use]
+    pub fn threshold_d(mut self, threshold_d: D) -> Self {
+        self.threshold_d = threshold_d.min(D_MAX);
+        self
+    }
+}
+
+/// An input file of ATIS events, and the next event read from it
+struct AtisInput {
+    reader: BufReader<File>,
+    decoder: EventDecoder,
+    next_event: Option<DvsEvent>,
+}
+
+impl AtisInput {
+    fn open(filename: String) -> Result<(Self, (u32, u32)), Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(PathBuf::from(filename))?);
+        let (format, size) = parse_header(&mut reader)?;
+        let mut input = Self {
+            reader,
+            decoder: EventDecoder::new(format),
+            next_event: None,
+        };
+        input.advance()?;
+        Ok((input, size))
+    }
+
+    /// Take the peeked event, and read the one after it
+    fn advance(&mut self) -> io::Result<Option<DvsEvent>> {
+        let event = self.next_event.take();
+        self.next_event = match self.decoder.next_event(&mut self.reader) {
+            Ok(event) => Some(event),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e),
+        };
+        Ok(event)
+    }
+}
+
+/// Attributes of an ATIS -> ADΔER transcode.
+///
+/// An ATIS pixel measures its absolute intensity as the time its photocurrent takes to move
+/// between two thresholds, after each change detection (TD) event. That measurement is exactly
+/// an ADΔER event: a fixed amount of intensity `2^D` integrated over `Δt`. So each exposure
+/// measurement (EM) becomes one ADΔER event, without integrating log-intensity changes like the
+/// DVS sources do. The time between a pixel's measurements is covered by empty events, which
+/// continue the pixel's last intensity.
+///
+/// The EM events are read from a Prophesee recording where polarity 0 marks the first threshold
+/// crossing of a measurement and polarity 1 marks the second. TD events, from an optional
+/// second recording, only update the displayed intensity until the next measurement completes.
+pub struct Atis<W: Write + std::marker::Send + std::marker::Sync + 'static> {
+    pub(crate) video: Video<W>,
+
+    td_input: Option<AtisInput>,
+
+    em_input: AtisInput,
+
+    params: AtisParams,
+
+    /// The source timestamp that each pixel's ADΔER events have reached
+    last_timestamps: Array2<u32>,
+
+    /// The source timestamp of each pixel's first threshold crossing, if it's measuring its
+    /// exposure
+    em_starts: Array2<Option<u32>>,
+
+    /// The latest source timestamp seen
+    running_t: u32,
+
+    /// Whether the end of the input files has been reached
+    ended: bool,
+}
+
+unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Sync for Atis<W> {}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Atis<W> {
+    /// Create a new `Atis` transcoder from a recording of EM events, and optionally a recording
+    /// of TD events from the same sensor
+    pub fn new(
+        ref_time: u32,
+        em_filename: String,
+        td_filename: Option<String>,
+        params: AtisParams,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (em_input, size) = AtisInput::open(em_filename)?;
+        let td_input = match td_filename {
+            Some(td_filename) => Some(AtisInput::open(td_filename)?.0),
+            None => None,
+        };
+
+        let plane = PlaneSize::new(size.1 as u16, size.0 as u16, 1)?;
+        let mut video = dvs_video(plane, ref_time, &params.dvs)?;
+        video
+            .state
+            .running_intensities
+            .fill(params.dvs.start_intensity);
+        video.display_frame_features = video.state.running_intensities.clone();
+
+        Ok(Atis {
+            video,
+            td_input,
+            em_input,
+            params,
+            last_timestamps: Array2::zeros((plane.h_usize(), plane.w_usize())),
+            em_starts: Array2::from_elem((plane.h_usize(), plane.w_usize()), None),
+            running_t: 0,
+            ended: false,
+        })
+    }
+
+    /// The sensor and model parameters of the transcode
+    pub fn params(&self) -> &AtisParams {
+        &self.params
+    }
+
+    /// Read the earliest of the next TD and EM events. Returns the event and whether it's an EM
+    /// event, or `None` at the end of both files.
+    fn next_event(&mut self) -> io::Result<Option<(DvsEvent, bool)>> {
+        let td_t = self
+            .td_input
+            .as_ref()
+            .and_then(|input| input.next_event)
+            .map(|event| event.t);
+        let em_t = self.em_input.next_event.map(|event| event.t);
+        match (td_t, em_t) {
+            (Some(td_t), Some(em_t)) if td_t <= em_t => self.next_td_event(),
+            (Some(_), None) => self.next_td_event(),
+            (_, Some(_)) => Ok(self.em_input.advance()?.map(|event| (event, true))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn next_td_event(&mut self) -> io::Result<Option<(DvsEvent, bool)>> {
+        match &mut self.td_input {
+            Some(input) => Ok(input.advance()?.map(|event| (event, false))),
+            None => Ok(None),
+        }
+    }
+
+    /// Step the displayed intensity of the event's pixel by the contrast threshold
+    fn handle_td_event(&mut self, event: DvsEvent) {
+        let (y, x) = (event.y as usize, event.x as usize);
+        let Some(intensity) = self.video.state.running_intensities.get_mut([y, x, 0]) else {
+            return;
+        };
+        let theta = if event.p == 0 {
+            -self.params.dvs.camera_theta
+        } else {
+            self.params.dvs.camera_theta
+        };
+        let ln_val = (f64::from(*intensity) / 255.0).ln_1p() + theta;
+        *intensity = ((ln_val.exp() - 1.0) * 255.0).clamp(0.0, 255.0) as u8;
+        self.video.display_frame_features[[y, x, 0]] = *intensity;
+    }
+
+    /// Start or complete an exposure measurement. A completed measurement becomes an ADΔER
+    /// event, after empty events for the time since the pixel's last measurement.
+    fn handle_em_event(&mut self, event: DvsEvent, events: &mut Vec<Event>) {
+        let (y, x) = (event.y as usize, event.x as usize);
+        let Some(em_start) = self.em_starts.get_mut([y, x]) else {
+            return;
+        };
+        if event.p == 0 {
+            *em_start = Some(event.t);
+            return;
+        }
+        let Some(start_t) = em_start.take() else {
+            return;
+        };
+        let last_t = self.last_timestamps[[y, x]];
+        if event.t <= start_t || start_t < last_t {
+            return;
+        }
+
+        let coord = Coord {
+            x: event.x,
+            y: event.y,
+            c: None,
+        };
+        let ref_time = self.video.state.params.ref_time;
+        let delta_t_max = self.video.state.params.delta_t_max;
+        push_span(
+            events,
+            coord,
+            D_EMPTY,
+            last_t * ref_time,
+            start_t * ref_time,
+            delta_t_max,
+        );
+        push_span(
+            events,
+            coord,
+            self.params.threshold_d,
+            start_t * ref_time,
+            event.t * ref_time,
+            delta_t_max,
+        );
+        self.last_timestamps[[y, x]] = event.t;
+
+        // The intensity per source time unit, which is ref_time ADΔER ticks
+        let intensity = (1_u64 << self.params.threshold_d) as f64 / f64::from(event.t - start_t);
+        let intensity = intensity.min(255.0) as u8;
+        self.video.state.running_intensities[[y, x, 0]] = intensity;
+        self.video.display_frame_features[[y, x, 0]] = intensity;
+    }
+
+    /// Continue the last intensity of every pixel up to `end_t`, or up to the latest timestamp
+    /// seen if that's later
+    fn end(&mut self, events: &mut Vec<Event>) {
+        let end_t = self
+            .params
+            .dvs
+            .end_t
+            .map_or(self.running_t, |end_t| end_t.max(self.running_t));
+        let ref_time = self.video.state.params.ref_time;
+        let delta_t_max = self.video.state.params.delta_t_max;
+        for ((y, x), last_t) in self.last_timestamps.indexed_iter_mut() {
+            let coord = Coord {
+                x: x as u16,
+                y: y as u16,
+                c: None,
+            };
+            push_span(
+                events,
+                coord,
+                D_EMPTY,
+                *last_t * ref_time,
+                end_t * ref_time,
+                delta_t_max,
+            );
+            *last_t = (*last_t).max(end_t);
+        }
+        self.running_t = end_t;
+    }
+}
+
+/// Push events which cover the ticks from `start_t` to `end_t` (absolute) with the intensity of
+/// a `d` event over that span. Spans longer than `delta_t_max` are split into equal events: an
+/// empty event is simply repeated, while an intensity event is halved until its pieces fit, or
+/// until its D is 0.
+fn push_span(
+    events: &mut Vec<Event>,
+    coord: Coord,
+    mut d: D,
+    start_t: u32,
+    end_t: u32,
+    delta_t_max: DeltaT,
+) {
+    if end_t <= start_t {
+        return;
+    }
+    let span = u64::from(end_t - start_t);
+    let delta_t_max = u64::from(delta_t_max.max(1));
+    let mut pieces = 1_u64;
+    if d == D_EMPTY {
+        pieces = span.div_ceil(delta_t_max);
+    } else {
+        while span / pieces > delta_t_max && d > 0 {
+            d -= 1;
+            pieces *= 2;
+        }
+    }
+    for i in 1..=pieces {
+        events.push(Event {
+            coord,
+            d,
+            t: start_t + (span * i / pieces) as u32,
+        });
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for Atis<W> {
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if self.ended {
+            return Err(SourceError::EndOfStream);
+        }
+
+        // Read events until we find a timestamp that exceeds our `running_t` by at least
+        // `view_interval`, or reach the end of the files
+        let mut events: Vec<Event> = Vec::new();
+        let start_running_t = self.running_t;
+        loop {
+            let Some((event, is_em)) = self.next_event()? else {
+                self.ended = true;
+                break;
+            };
+            self.running_t = self.running_t.max(event.t);
+            if is_em {
+                self.handle_em_event(event, &mut events);
+            } else {
+                self.handle_td_event(event);
+            }
+            if event.t > start_running_t + self.params.dvs.view_interval {
+                break;
+            }
+        }
+
+        if self.ended {
+            self.end(&mut events);
+        }
+
+        ingest_integrated_events(&mut self.video, events)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        None
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W> for Atis<W> {
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a `.dat` recording of 4x4 pixels
+    fn write_dat(name: &str, events: &[(u32, u32, u32, u32)]) -> io::Result<String> {
+        let path =
+            std::env::temp_dir().join(format!("adder_atis_{}_{name}.dat", std::process::id()));
+        let mut bytes = b"% Height 4\n% Width 4\n".to_vec();
+        bytes.extend_from_slice(&[0, 8]);
+        for (t, x, y, p) in events {
+            bytes.extend_from_slice(&t.to_le_bytes());
+            bytes.extend_from_slice(&(x | (y << 14) | (p << 28)).to_le_bytes());
+        }
+        std::fs::write(&path, bytes)?;
+        Ok(path.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn spans() {
+        let coord = Coord {
+            x: 0,
+            y: 0,
+            c: None,
+        };
+        let mut events = Vec::new();
+        push_span(&mut events, coord, 10, 0, 100, 30);
+        assert_eq!(
+            events.iter().map(|e| (e.d, e.t)).collect::<Vec<_>>(),
+            vec![(8, 25), (8, 50), (8, 75), (8, 100)]
+        );
+
+        events.clear();
+        push_span(&mut events, coord, D_EMPTY, 100, 170, 30);
+        assert_eq!(
+            events.iter().map(|e| (e.d, e.t)).collect::<Vec<_>>(),
+            vec![(D_EMPTY, 123), (D_EMPTY, 146), (D_EMPTY, 170)]
+        );
+    }
+
+    #[test]
+    fn exposure_measurements() -> Result<(), Box<dyn Error>> {
+        let em_path = write_dat(
+            "em",
+            &[
+                (100, 1, 1, 0),
+                (108, 1, 1, 1),
+                (300, 1, 1, 0),
+                (316, 1, 1, 1),
+            ],
+        )?;
+        let td_path = write_dat("td", &[(250, 1, 1, 0)])?;
+        let params = AtisParams::default()
+            .dvs_params(DvsParams::default().end_t(1000))
+            .threshold_d(10);
+        let source = Atis::new(10, em_path.clone(), Some(td_path.clone()), params);
+        std::fs::remove_file(em_path)?;
+        std::fs::remove_file(td_path)?;
+        let mut source: Atis<io::Sink> = source?;
+
+        let events = source.consume()?;
+        let pixel_events: Vec<(D, u32)> = events[0]
+            .iter()
+            .filter(|e| e.coord.x == 1 && e.coord.y == 1)
+            .map(|e| (e.d, e.t))
+            .collect();
+        assert_eq!(
+            pixel_events,
+            vec![
+                (D_EMPTY, 1000),
+                (10, 1080),
+                (D_EMPTY, 3000),
+                (10, 3160),
+                (D_EMPTY, 10000)
+            ]
+        );
+        assert_eq!(events[0].len(), 20);
+        // 2^10 / 16 microseconds
+        assert_eq!(source.video.state.running_intensities[[1, 1, 0]], 64);
+        assert!(matches!(source.consume(), Err(SourceError::EndOfStream)));
+        Ok(())
+    }
+}
@@ -1,11 +1,11 @@
-use crate::transcoder::source::video::FramedViewMode::SAE;
-use crate::transcoder::source::video::SourceError::BufferEmpty;
-use crate::transcoder::source::video::{
-    integrate_for_px, Source, SourceError, Video, VideoBuilder,
+use crate::transcoder::source::dvs::{
+    ingest_integrated_events, DvsClamp, DvsEvent, DvsIntegrator, DvsParams, START_T,
 };
+use crate::transcoder::source::video::SourceError::BufferEmpty;
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
 use adder_codec_core::Mode::{Continuous, FramePerfect};
 use adder_codec_core::{DeltaT, PixelMultiMode};
-use davis_edi_rs::aedat::events_generated::Event as DvsEvent;
+use davis_edi_rs::aedat::events_generated::Event as DavisEvent;
 use davis_edi_rs::util::reconstructor::{IterVal, ReconstructionError, Reconstructor};
 use rayon::iter::IndexedParallelIterator;
 use rayon::iter::ParallelIterator;
@@ -13,8 +13,7 @@ use rayon::iter::ParallelIterator;
 use opencv::core::{Mat, CV_8U};
 use opencv::prelude::*;
 
-use bumpalo::Bump;
-use ndarray::{Array3, Axis};
+use ndarray::Axis;
 
 use rayon::iter::IntoParallelIterator;
 use rayon::{current_num_threads, ThreadPool};
@@ -24,12 +23,9 @@ use std::io::Write;
 use std::mem::swap;
 use std::thread;
 
-use adder_codec_core::codec::{CodecError, EncoderOptions, EncoderType};
-use adder_codec_core::{Event, PlaneSize, SourceCamera, SourceType, TimeMode};
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::{Event, PlaneSize, SourceCamera, TimeMode};
 
-use crate::framer::scale_intensity::{FrameValue, SaeTime};
-use crate::transcoder::event_pixel_tree::Intensity32;
-use crate::utils::cv::clamp_u8;
 use crate::utils::viz::ShowFeatureMode;
 use tokio::runtime::Runtime;
 use video_rs_adder_dep::Frame;
@@ -52,11 +48,10 @@ pub enum TranscoderMode {
     RawDvs,
 }
 
-struct Integration<W> {
-    dvs_c: f64,
-    dvs_events_before: Option<Vec<DvsEvent>>,
-    dvs_events_last_after: Option<Vec<DvsEvent>>,
-    dvs_events_after: Option<Vec<DvsEvent>>,
+struct Integration {
+    dvs_events_before: Option<Vec<DavisEvent>>,
+    dvs_events_last_after: Option<Vec<DavisEvent>>,
+    dvs_events_after: Option<Vec<DavisEvent>>,
 
     pub temp_first_frame_start_timestamp: i64,
 
@@ -68,13 +63,38 @@ struct Integration<W> {
 
     pub end_of_last_frame_timestamp: Option<i64>,
 
-    /// The timestamp of the last DVS event integrated for each pixel
-    pub dvs_last_timestamps: Array3<i64>,
+    /// The camera timestamp which the integrator counts its timestamps from, such that this
+    /// timestamp is [`START_T`]
+    pub first_timestamp: Option<i64>,
+}
 
-    /// The log-space last intensity value for each pixel
-    pub dvs_last_ln_val: Array3<f64>,
+impl Integration {
+    /// Convert the DAVIS events which pass the checks to the integrator's timestamps, dropping
+    /// any from before the first frame
+    fn convert_events(
+        &self,
+        dvs_events: &[DavisEvent],
+        check: impl Fn(i64) -> bool,
+    ) -> Vec<DvsEvent> {
+        dvs_events
+            .iter()
+            .filter(|event| check(event.t()))
+            .filter_map(|event| {
+                Some(DvsEvent {
+                    t: self.integrator_t(event.t())?,
+                    x: event.x() as u16,
+                    y: event.y() as u16,
+                    p: u8::from(event.on()),
+                })
+            })
+            .collect()
+    }
 
-    phantom: std::marker::PhantomData<W>,
+    /// Convert a camera timestamp to the integrator's timestamps
+    fn integrator_t(&self, t: i64) -> Option<u32> {
+        let offset = t - self.first_timestamp?;
+        u32::try_from(offset).ok().map(|t| t + START_T)
+    }
 }
 
 /// Attributes of a framed video -> ADΔER transcode
@@ -85,7 +105,15 @@ pub struct Davis<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     image_8u: Mat,
     thread_pool_edi: Option<ThreadPool>,
 
-    integration: Integration<W>,
+    integration: Integration,
+
+    /// The per-pixel log intensity model of the DVS events, shared with the other event camera
+    /// sources
+    pub integrator: DvsIntegrator,
+
+    /// Whether to use the contrast threshold estimated by EDI for each frame, rather than the
+    /// threshold of the [`DvsParams`]
+    use_edi_theta: bool,
 
     /// The latency between a DAVIS/DVS packet being sent by the camera and read by the reconstructor
     latency: u128,
@@ -105,11 +133,12 @@ pub struct Davis<W: Write + std::marker::Send + std::marker::Sync + 'static> {
 unsafe impl<W: Write + std::marker::Send + std::marker::Sync> Sync for Davis<W> {}
 
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
-    /// Create a new `Davis` transcoder
+    /// Create a new `Davis` transcoder. Its DVS events saturate at the ends of the 8-bit range,
+    /// with the contrast threshold estimated by EDI.
     pub fn new(reconstructor: Reconstructor, mode: TranscoderMode) -> Result<Self, Box<dyn Error>> {
         let plane = PlaneSize::new(reconstructor.width, reconstructor.height, 1)?;
 
-        let video = Video::new(
+        let mut video = Video::new(
             plane,
             match mode {
                 TranscoderMode::Framed => FramePerfect,
@@ -123,21 +152,10 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
             .num_threads(max(current_num_threads() - 4, 1))
             .build()?;
 
-        let plane = &video.state.plane;
-
-        let timestamps = vec![0_i64; video.state.plane.volume()];
-
-        let dvs_last_timestamps: Array3<i64> = Array3::from_shape_vec(
-            (plane.h().into(), plane.w().into(), plane.c().into()),
-            timestamps,
-        )?;
-
-        let timestamps = vec![0.0_f64; video.state.plane.volume()];
-
-        let dvs_last_ln_val: Array3<f64> = Array3::from_shape_vec(
-            (plane.h() as usize, plane.w() as usize, plane.c() as usize),
-            timestamps,
-        )?;
+        let params = DvsParams::default()
+            .camera_theta(0.15)
+            .clamp(DvsClamp::Saturate);
+        let integrator = DvsIntegrator::new(&mut video, &params);
 
         let davis_source = Davis {
             reconstructor: Some(reconstructor),
@@ -147,7 +165,6 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
             thread_pool_edi: Some(thread_pool_edi),
 
             integration: Integration {
-                dvs_c: 0.15,
                 dvs_events_before: None,
                 dvs_events_after: None,
                 dvs_events_last_after: None,
@@ -155,10 +172,10 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
                 start_of_frame_timestamp: None,
                 end_of_frame_timestamp: None,
                 end_of_last_frame_timestamp: None,
-                dvs_last_timestamps,
-                dvs_last_ln_val,
-                phantom: std::marker::PhantomData,
+                first_timestamp: None,
             },
+            integrator,
+            use_edi_theta: true,
             latency: 0,
             cached_mat_opt: None,
 
@@ -186,6 +203,15 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
         self
     }
 
+    /// Set the parameters of the DVS integration: the contrast threshold, clamping and
+    /// timestamp policy. `use_edi_theta` is whether the contrast threshold estimated by EDI for
+    /// each frame overrides the given one (default: `true`).
+    pub fn dvs_params(mut self, params: DvsParams, use_edi_theta: bool) -> Self {
+        self.integrator = DvsIntegrator::new(&mut self.video, &params);
+        self.use_edi_theta = use_edi_theta;
+        self
+    }
+
     // #[allow(clippy::cast_precision_loss)]
     // fn control_latency(&mut self, opt_timestamp: Option<Instant>) {
     //     if self.optimize_adder_controller {
@@ -229,374 +255,6 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
     }
 }
 
-impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Integration<W> {
-    /// Integrate a sequence of [DVS events](DvsEvent) into the ADΔER video model
-    #[allow(clippy::cast_sign_loss)]
-    pub fn integrate_dvs_events<
-        F: Fn(i64, i64) -> bool + Send + 'static + std::marker::Sync,
-        G: Fn(i64, i64) -> bool + Send + 'static + std::marker::Sync,
-    >(
-        &mut self,
-        video: &mut Video<W>,
-        dvs_events: &Vec<DvsEvent>,
-        frame_timestamp: i64,
-        event_check_1: F,
-        frame_timestamp_2: Option<i64>,
-        event_check_2: G,
-    ) -> Result<(), CodecError> {
-        // TODO: not fixed 4 chunks?
-        let mut dvs_chunks: [Vec<DvsEvent>; 4] = [
-            Vec::with_capacity(100_000),
-            Vec::with_capacity(100_000),
-            Vec::with_capacity(100_000),
-            Vec::with_capacity(100_000),
-        ];
-
-        let mut chunk_idx;
-        for dvs_event in dvs_events {
-            chunk_idx = dvs_event.y() as usize / (video.state.plane.h_usize() / 4);
-            dvs_chunks[chunk_idx].push(*dvs_event);
-        }
-
-        let chunk_rows = video.state.chunk_rows;
-        // let px_per_chunk: usize =
-        //     self.video.chunk_rows * self.video.width as usize * self.video.channels as usize;
-        let big_buffer: Vec<Vec<Event>> = video
-            .event_pixel_trees
-            .axis_chunks_iter_mut(Axis(0), chunk_rows)
-            .into_par_iter()
-            .zip(
-                self.dvs_last_ln_val
-                    .axis_chunks_iter_mut(Axis(0), chunk_rows)
-                    .into_par_iter()
-                    .zip(
-                        self.dvs_last_timestamps
-                            .axis_chunks_iter_mut(Axis(0), chunk_rows)
-                            .into_par_iter(),
-                    ),
-            )
-            .enumerate()
-            .map(
-                |(
-                    chunk_idx,
-                    (mut px_chunk, (mut dvs_last_ln_val_chunk, mut dvs_last_timestamps_chunk)),
-                )| {
-                    let mut buffer: Vec<Event> = Vec::with_capacity(100_000);
-
-                    for event in &dvs_chunks[chunk_idx] {
-                        // Ignore events occuring during the deblurred frame's
-                        // effective exposure time
-                        if event_check_1(event.t(), frame_timestamp)
-                            && if let Some(frame_timestamp_2) = frame_timestamp_2 {
-                                event_check_2(event.t(), frame_timestamp_2)
-                            } else {
-                                true
-                            }
-                        {
-                            let px = &mut px_chunk
-                                [[(event.y() as usize) % chunk_rows, event.x() as usize, 0]];
-                            let base_val = px.base_val;
-                            let last_val_ln = &mut dvs_last_ln_val_chunk
-                                [[(event.y() as usize) % chunk_rows, event.x() as usize, 0]];
-                            let last_val = (last_val_ln.exp() - 1.0) * 255.0;
-
-                            // in microseconds (1 million per second)
-                            let delta_t_micro = event.t()
-                                - dvs_last_timestamps_chunk
-                                    [[event.y() as usize % chunk_rows, event.x() as usize, 0]];
-                            if delta_t_micro == event.t() {
-                                continue;
-                            }
-                            let ticks_per_micro = video.state.tps as f32 / 1e6;
-                            let delta_t_ticks = delta_t_micro as f32 * ticks_per_micro;
-                            if delta_t_ticks < 0.0 {
-                                // Should get here only if the event has already been processed?
-                                continue; // TODO: do better
-                            }
-
-                            // First, integrate the previous value enough to fill the time since then
-                            let first_integration = ((last_val as Intensity32)
-                                / video.state.params.ref_time as f32
-                                * delta_t_ticks)
-                                .max(0.0);
-
-                            if px.need_to_pop_top {
-                                buffer.push(px.pop_top_event(
-                                    first_integration,
-                                    Continuous,
-                                    video.state.params.ref_time,
-                                ));
-                            }
-
-                            let running_t_before = px.running_t;
-                            px.integrate(
-                                first_integration,
-                                delta_t_ticks,
-                                Continuous,
-                                video.state.params.delta_t_max,
-                                video.state.params.ref_time,
-                                video.encoder.options.crf.get_parameters().c_thresh_max,
-                                video
-                                    .encoder
-                                    .options
-                                    .crf
-                                    .get_parameters()
-                                    .c_increase_velocity,
-                                video.state.params.pixel_multi_mode,
-                            );
-                            let running_t_after = px.running_t;
-                            debug_assert_eq!(running_t_after, running_t_before + delta_t_ticks);
-
-                            if px.need_to_pop_top {
-                                buffer.push(px.pop_top_event(
-                                    first_integration,
-                                    Continuous,
-                                    video.state.params.ref_time,
-                                ));
-                            }
-                            let running_t_after = px.running_t;
-                            debug_assert_eq!(running_t_after, running_t_before + delta_t_ticks);
-
-                            ///////////////////////////////////////////////////////
-                            // Then, integrate a tiny amount of the next intensity
-                            // let mut frame_val = (base_val as f64);
-                            // let mut lat_frame_val = (frame_val / 255.0).ln();
-
-                            *last_val_ln *= if event.on() { self.dvs_c } else { -self.dvs_c }.exp();
-                            let mut frame_val = (last_val_ln.exp() - 1.0) * 255.0;
-                            clamp_u8(&mut frame_val, last_val_ln);
-
-                            let frame_val_u8 = frame_val as u8; // TODO: don't let this be lossy here
-
-                            if frame_val_u8 < base_val.saturating_sub(px.c_thresh)
-                                || frame_val_u8 > base_val.saturating_add(px.c_thresh)
-                            {
-                                px.pop_best_events(
-                                    &mut buffer,
-                                    Continuous,
-                                    video.state.params.pixel_multi_mode,
-                                    video.state.params.ref_time,
-                                    frame_val as Intensity32,
-                                );
-                                px.base_val = frame_val_u8;
-
-                                // If continuous mode and the D value needs to be different now
-                                match px.set_d_for_continuous(
-                                    frame_val as Intensity32,
-                                    video.state.params.ref_time,
-                                ) {
-                                    None => {}
-                                    Some(event) => buffer.push(event),
-                                };
-                            }
-                            let tmpp = dvs_last_timestamps_chunk
-                                [[event.y() as usize % chunk_rows, event.x() as usize, 0]];
-
-                            dvs_last_timestamps_chunk
-                                [[event.y() as usize % chunk_rows, event.x() as usize, 0]] =
-                                event.t();
-
-                            debug_assert!(
-                                dvs_last_timestamps_chunk
-                                    [[event.y() as usize % chunk_rows, event.x() as usize, 0]]
-                                    >= tmpp
-                            );
-                        }
-                    }
-
-                    buffer
-                },
-            )
-            .collect();
-
-        let db: &mut [u8] = match video.display_frame_features.as_slice_mut() {
-            Some(db) => db,
-            None => return Err(CodecError::MalformedEncoder), // TODO: Wrong type of error
-        };
-
-        // TODO: split off into separate function
-        // TODO: When there's full support for various bit-depth sources, modify this accordingly
-        let practical_d_max = fast_math::log2_raw(
-            255.0 * (video.state.params.delta_t_max / video.state.params.ref_time) as f32,
-        );
-        db.iter_mut()
-            .zip(video.state.running_intensities.iter_mut())
-            .enumerate()
-            .for_each(|(idx, (val, running))| {
-                let y = idx / video.state.plane.area_wc();
-                let x = (idx % video.state.plane.area_wc()) / video.state.plane.c_usize();
-                let c = idx % video.state.plane.c_usize();
-                *val = match video.event_pixel_trees[[y, x, c]].arena[0].best_event {
-                    Some(event) => u8::get_frame_value(
-                        &event.into(),
-                        SourceType::U8,
-                        video.state.params.ref_time as f64,
-                        practical_d_max,
-                        video.state.params.delta_t_max,
-                        video.instantaneous_view_mode,
-                        if video.instantaneous_view_mode == SAE {
-                            Some(SaeTime {
-                                running_t: video.event_pixel_trees[[y, x, c]].running_t as DeltaT,
-                                last_fired_t: video.event_pixel_trees[[y, x, c]].last_fired_t
-                                    as DeltaT,
-                            })
-                        } else {
-                            None
-                        },
-                    ),
-                    None => *val,
-                };
-                *running = *val;
-            });
-
-        for events in &big_buffer {
-            for e1 in events.iter() {
-                video.encoder.ingest_event(*e1)?;
-            }
-        }
-
-        if let Err(e) = video.handle_features(&big_buffer) {
-            return Err(CodecError::VisionError(e.to_string()));
-        }
-
-        Ok(())
-    }
-
-    #[allow(clippy::cast_possible_truncation)]
-    fn integrate_frame_gaps(&mut self, video: &mut Video<W>) -> Result<(), SourceError> {
-        let px_per_chunk: usize = video.state.chunk_rows * video.state.plane.area_wc();
-
-        let start_of_frame_timestamp = match self.start_of_frame_timestamp {
-            Some(t) => t,
-            None => return Err(SourceError::UninitializedData),
-        };
-
-        // Important: if framing the events simultaneously, then the chunk division must be
-        // exactly the same as it is for the framer
-        let big_buffer: Vec<Vec<Event>> = video
-            .event_pixel_trees
-            .axis_chunks_iter_mut(Axis(0), video.state.chunk_rows)
-            .into_par_iter()
-            .zip(
-                self.dvs_last_ln_val
-                    .axis_chunks_iter_mut(Axis(0), video.state.chunk_rows)
-                    .into_par_iter(),
-            )
-            .enumerate()
-            .map(|(chunk_idx, (mut chunk_px, mut chunk_ln_val))| {
-                let mut buffer: Vec<Event> = Vec::with_capacity(px_per_chunk);
-                let bump = Bump::new();
-                let base_val = bump.alloc(0);
-                let px_idx = bump.alloc(0);
-                let frame_val = bump.alloc(0);
-
-                for (chunk_px_idx, (px, last_val_ln)) in
-                    chunk_px.iter_mut().zip(chunk_ln_val.iter_mut()).enumerate()
-                {
-                    *px_idx = chunk_px_idx + px_per_chunk * chunk_idx;
-
-                    let mut last_val = (last_val_ln.exp() - 1.0) * 255.0;
-
-                    clamp_u8(&mut last_val, last_val_ln);
-                    *base_val = px.base_val;
-                    *frame_val = last_val as u8;
-
-                    let ticks_per_micro = video.state.tps as f32 / 1e6;
-
-                    let delta_t_micro = start_of_frame_timestamp
-                        - self.dvs_last_timestamps[[px.coord.y as usize, px.coord.x as usize, 0]];
-
-                    if delta_t_micro == start_of_frame_timestamp {
-                        continue;
-                    }
-
-                    let delta_t_ticks = delta_t_micro as f32 * ticks_per_micro;
-                    if delta_t_ticks <= 0.0 {
-                        continue;
-                    }
-                    assert!(delta_t_ticks > 0.0);
-                    // assert_eq!(
-                    //     self.end_of_frame_timestamp.unwrap()
-                    //         - self.start_of_frame_timestamp.unwrap(),
-                    //     (self.video.ref_time as f32 / ticks_per_micro as f32) as i64
-                    // );
-
-                    let integration = ((last_val / f64::from(video.state.params.ref_time))
-                        * f64::from(delta_t_ticks))
-                    .max(0.0);
-                    assert!(integration >= 0.0);
-
-                    integrate_for_px(
-                        px,
-                        base_val,
-                        *frame_val,
-                        integration as f32,
-                        delta_t_ticks,
-                        &mut buffer,
-                        &video.state.params,
-                        video.encoder.options.crf.get_parameters(),
-                    );
-                }
-                buffer
-            })
-            .collect();
-
-        let db = match video.display_frame_features.as_slice_mut() {
-            Some(db) => db,
-            None => {
-                return Err(SourceError::VisionError(
-                    "No instantaneous frame".to_string(),
-                ))
-            }
-        };
-
-        // TODO: split off into separate function
-        // TODO: When there's full support for various bit-depth sources, modify this accordingly
-        let practical_d_max = fast_math::log2_raw(
-            255.0 * (video.state.params.delta_t_max / video.state.params.ref_time) as f32,
-        );
-        db.iter_mut()
-            .zip(video.state.running_intensities.iter_mut())
-            .enumerate()
-            .for_each(|(idx, (val, running))| {
-                let y = idx / video.state.plane.area_wc();
-                let x = (idx % video.state.plane.area_wc()) / video.state.plane.c_usize();
-                let c = idx % video.state.plane.c_usize();
-                *val = match video.event_pixel_trees[[y, x, c]].arena[0].best_event {
-                    Some(event) => u8::get_frame_value(
-                        &event.into(),
-                        SourceType::U8,
-                        video.state.params.ref_time as f64,
-                        practical_d_max,
-                        video.state.params.delta_t_max,
-                        video.instantaneous_view_mode,
-                        if video.instantaneous_view_mode == SAE {
-                            Some(SaeTime {
-                                running_t: video.event_pixel_trees[[y, x, c]].running_t as DeltaT,
-                                last_fired_t: video.event_pixel_trees[[y, x, c]].last_fired_t
-                                    as DeltaT,
-                            })
-                        } else {
-                            None
-                        },
-                    ),
-                    None => *val,
-                };
-                *running = *val;
-            });
-
-        for events in &big_buffer {
-            for e1 in events.iter() {
-                video.encoder.ingest_event(*e1)?;
-            }
-        }
-
-        video.handle_features(&big_buffer)?;
-
-        Ok(())
-    }
-}
-
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for Davis<W> {
     fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
         // Attempting new method for integration without requiring a buffer. Could be implemented
@@ -643,7 +301,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                     println!("Popping remaining events");
                     let px_per_chunk: usize =
                         self.video.state.chunk_rows * self.video.state.plane.area_wc();
-                    let big_buffer: Vec<Vec<Event>> = self
+                    let mut big_buffer: Vec<Vec<Event>> = self
                         .video
                         .event_pixel_trees
                         .axis_chunks_iter_mut(Axis(0), self.video.state.chunk_rows)
@@ -664,7 +322,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                         })
                         .collect();
 
-                    self.video.encoder.ingest_events_events(&big_buffer)?;
+                    self.video.ingest_events_events(&mut big_buffer)?;
 
                     return Err(SourceError::NoData);
                 }
@@ -691,7 +349,9 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                         // assert!(events_before.is_empty());
                         self.integration.end_of_frame_timestamp = Some(img_start_ts + 1);
                     }
-                    self.integration.dvs_c = c;
+                    if self.use_edi_theta && self.mode != TranscoderMode::RawDvs {
+                        self.integrator.set_camera_theta(c);
+                    }
                     self.integration.dvs_events_before = Some(events_before);
                     self.integration.dvs_events_after = Some(events_after);
 
@@ -725,58 +385,41 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                 if self.video.state.in_interval_count == 0 {
                     /* If at the very beginning of the video, then we need to initialize the
                     last timestamps */
-                    self.integration.dvs_last_timestamps.par_map_inplace(|ts| {
-                        *ts = start_of_frame_timestamp;
-                    });
+                    self.integration.first_timestamp = Some(start_of_frame_timestamp);
+                    self.integrator.set_timestamps(START_T);
                 } else {
                     let dvs_events_before = match &self.integration.dvs_events_before {
-                        Some(events) => events.clone(),
+                        Some(events) => events,
                         None => return Err(SourceError::UninitializedData),
                     };
 
+                    // The events after the last frame's exposure, and then the events before
+                    // this frame's exposure
+                    let mut dvs_events = Vec::new();
                     if let (Some(events), Some(end_of_last_timestamp)) = (
-                        self.integration.dvs_events_last_after.clone(),
+                        &self.integration.dvs_events_last_after,
                         self.integration.end_of_last_frame_timestamp,
                     ) {
-                        self.integration.integrate_dvs_events(
-                            &mut self.video,
-                            &events,
-                            start_of_frame_timestamp,
-                            check_dvs_before,
-                            if self.mode == TranscoderMode::RawDvs {
-                                None
-                            } else {
-                                Some(end_of_last_timestamp)
-                            },
-                            check_dvs_after,
-                        )?;
+                        dvs_events = self.integration.convert_events(events, |t| {
+                            check_dvs_before(t, start_of_frame_timestamp)
+                                && (self.mode == TranscoderMode::RawDvs
+                                    || check_dvs_after(t, end_of_last_timestamp))
+                        });
                     }
-
-                    self.integration.integrate_dvs_events(
-                        &mut self.video,
-                        &dvs_events_before,
-                        start_of_frame_timestamp,
-                        check_dvs_before,
-                        None,
-                        check_dvs_before,
-                    )?;
-
-                    for px in &self.video.event_pixel_trees {
-                        let a = px.running_t as i64;
-                        let b = start_of_frame_timestamp
-                            - self.integration.temp_first_frame_start_timestamp;
-                        // debug_assert!(a <= b);
-                        // debug_assert!(a <= start_of_frame_timestamp);
-                    }
-
-                    self.integration.integrate_frame_gaps(&mut self.video)?;
-                    for px in &self.video.event_pixel_trees {
-                        let a = px.running_t as i64;
-                        let b = start_of_frame_timestamp
-                            - self.integration.temp_first_frame_start_timestamp;
-                        // debug_assert!(a <= b);
-                        // debug_assert!(a > b - 1000);
+                    dvs_events.extend(self.integration.convert_events(dvs_events_before, |t| {
+                        check_dvs_before(t, start_of_frame_timestamp)
+                    }));
+
+                    let mut events = Vec::new();
+                    self.integrator
+                        .integrate(&mut self.video, &dvs_events, &mut events);
+
+                    // Fill the time up to the start of this frame's exposure
+                    if let Some(start_t) = self.integration.integrator_t(start_of_frame_timestamp) {
+                        self.integrator
+                            .integrate_to(&mut self.video, start_t, &mut events);
                     }
+                    ingest_integrated_events(&mut self.video, events)?;
                 }
             }
 
@@ -804,9 +447,8 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                     (end_of_frame_timestamp - start_of_frame_timestamp) as f32
                 }
                 TranscoderMode::RawDvs => {
-                    // TODO: Note how c is fixed here, since we don't have a mechanism for determining
-                    // its value
-                    self.integration.dvs_c = 0.15;
+                    // Note that c is the integrator's configured threshold here, since we don't
+                    // have a mechanism for determining its value
                     match tmp.data_bytes_mut() {
                         Ok(bytes) => {
                             for byte in bytes {
@@ -835,7 +477,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
             ret = self.video.integrate_matrix(frame, mat_integration_time);
 
             #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
-            for (idx, val) in self.integration.dvs_last_ln_val.iter_mut().enumerate() {
+            for (idx, val) in self.integrator.dvs_last_ln_val.iter_mut().enumerate() {
                 let px = match
                 // SAFETY:
                 // `dvs_last_ln_val` is the same size as `input_frame_scaled`
@@ -866,10 +508,9 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                 self.integration.end_of_last_frame_timestamp =
                     self.integration.end_of_frame_timestamp;
 
-                self.integration.dvs_last_timestamps.par_map_inplace(|ts| {
-                    debug_assert!(*ts < end_of_frame_timestamp);
-                    *ts = end_of_frame_timestamp;
-                });
+                if let Some(end_t) = self.integration.integrator_t(end_of_frame_timestamp) {
+                    self.integrator.set_timestamps(end_t);
+                }
 
                 // for px in &self.video.event_pixel_trees {
                 //     let a = px.running_t as i64;
@@ -0,0 +1,472 @@
+use crate::framer::scale_intensity::{FrameValue, SaeTime};
+use crate::transcoder::source::video::FramedViewMode::SAE;
+use crate::transcoder::source::video::{integrate_for_px, SourceError, Video};
+use crate::utils::cv::{clamp_u8, mid_clamp_u8};
+use adder_codec_core::Mode::Continuous;
+use adder_codec_core::{DeltaT, Event, PlaneSize, SourceType, TimeMode};
+use ndarray::Array3;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// The temporal granularity of DVS sources (ticks per second). Source timestamps are in
+/// microseconds.
+pub const DVS_SOURCE_TPS: u32 = 1000000;
+
+/// The source timestamp that every pixel starts at. The first two units of source time are spent
+/// integrating the start intensity of every pixel.
+pub(crate) const START_T: u32 = 2;
+
+/// A DVS-style contrast event
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct DvsEvent {
+    /// The timestamp of the event, in microseconds
+    pub t: u32,
+
+    /// The x-coordinate of the pixel
+    pub x: u16,
+
+    /// The y-coordinate of the pixel
+    pub y: u16,
+
+    /// The polarity of the event: 0 for a decrease in intensity, 1 for an increase
+    pub p: u8,
+}
+
+/// How the log intensity model handles a pixel whose intensity steps outside the 8-bit range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DvsClamp {
+    /// Reset the pixel to mid-gray, on the assumption that its model has drifted
+    #[default]
+    MidGray,
+
+    /// Saturate the pixel at 0 or 255
+    Saturate,
+}
+
+impl DvsClamp {
+    /// Clamp a linear intensity and its log intensity
+    fn apply(self, val: &mut f64, ln_val: &mut f64) {
+        match self {
+            DvsClamp::MidGray => mid_clamp_u8(val, ln_val),
+            DvsClamp::Saturate => clamp_u8(val, ln_val),
+        }
+    }
+}
+
+/// How the log intensity model handles an event older than the last event of its pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DvsTimestampPolicy {
+    /// Ignore the event
+    #[default]
+    Drop,
+
+    /// Apply the event's contrast step at the time of the pixel's last event
+    Clamp,
+}
+
+/// Sensor and model parameters for transcoding DVS events to ADΔER
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DvsParams {
+    pub(crate) camera_theta: f64,
+    pub(crate) view_interval: u32,
+    pub(crate) start_intensity: u8,
+    pub(crate) delta_t_max_multiplier: u32,
+    pub(crate) end_t: Option<u32>,
+    pub(crate) clamp: DvsClamp,
+    pub(crate) timestamp_policy: DvsTimestampPolicy,
+}
+
+impl Default for DvsParams {
+    fn default() -> Self {
+        Self {
+            camera_theta: 0.02,
+            view_interval: DVS_SOURCE_TPS / 60,
+            start_intensity: 128,
+            delta_t_max_multiplier: 2,
+            end_t: None,
+            clamp: DvsClamp::MidGray,
+            timestamp_policy: DvsTimestampPolicy::Drop,
+        }
+    }
+}
+
+impl DvsParams {
+    /// Set the contrast threshold of the sensor, as a change in log intensity. Default 0.02.
+    #[must_use]
+    pub fn camera_theta(mut self, camera_theta: f64) -> Self {
+        self.camera_theta = camera_theta;
+        self
+    }
+
+    /// Set the span of source time (in microseconds) read by each call to `consume`. Default
+    /// is 1/60th of a second.
+    #[must_use]
+    pub fn view_interval(mut self, view_interval: u32) -> Self {
+        self.view_interval = view_interval.max(1);
+        self
+    }
+
+    /// Set the intensity every pixel is assumed to have at the start of the recording. Default
+    /// 128.
+    #[must_use]
+    pub fn start_intensity(mut self, start_intensity: u8) -> Self {
+        self.start_intensity = start_intensity;
+        self
+    }
+
+    /// Set `delta_t_max` as a multiple of `ref_time`. Default 2.
+    #[must_use]
+    pub fn delta_t_max_multiplier(mut self, delta_t_max_multiplier: u32) -> Self {
+        self.delta_t_max_multiplier = delta_t_max_multiplier.max(1);
+        self
+    }
+
+    /// Set the source timestamp (in microseconds) up to which the last intensity of every pixel
+    /// is integrated when the end of the input is reached. By default, pixels are integrated up
+    /// to the timestamp of the last event.
+    #[must_use]
+    pub fn end_t(mut self, end_t: u32) -> Self {
+        self.end_t = Some(end_t);
+        self
+    }
+
+    /// Set how a pixel's intensity is clamped when it steps outside the 8-bit range. Default
+    /// [`DvsClamp::MidGray`].
+    #[must_use]
+    pub fn clamp(mut self, clamp: DvsClamp) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    /// Set how an event older than its pixel's last event is handled. Default
+    /// [`DvsTimestampPolicy::Drop`].
+    #[must_use]
+    pub fn timestamp_policy(mut self, timestamp_policy: DvsTimestampPolicy) -> Self {
+        self.timestamp_policy = timestamp_policy;
+        self
+    }
+}
+
+/// Create the video model for a DVS source. Its tps assumes the source has a temporal
+/// granularity of 1000000/second, which `ref_time` scales up. For example, with ref_time = 20, a
+/// timestamp of 12 in the source becomes 240 ADΔER ticks.
+pub(crate) fn dvs_video<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+    plane: PlaneSize,
+    ref_time: u32,
+    params: &DvsParams,
+) -> Result<Video<W>, SourceError> {
+    Video::new(plane, Continuous, None)?
+        .chunk_rows(1)
+        .time_parameters(
+            ref_time * DVS_SOURCE_TPS,
+            ref_time,
+            ref_time * params.delta_t_max_multiplier,
+            Some(TimeMode::AbsoluteT),
+        )
+}
+
+/// Integrates DVS events into the ADΔER model of a [`Video`]. Every event camera source shares
+/// it, so the contrast threshold, clamping and timestamp handling are the same for all of them.
+///
+/// Each pixel holds its last log intensity, which each event steps up or down by the contrast
+/// threshold. The linear intensity is integrated over the time between a pixel's events.
+/// Timestamps are in microseconds, and converted to ADΔER ticks with the video's tps.
+pub struct DvsIntegrator {
+    /// The timestamp (in-camera) of the last DVS event integrated for each pixel
+    pub dvs_last_timestamps: Array3<u32>,
+
+    /// The log-space last intensity value for each pixel
+    pub dvs_last_ln_val: Array3<f64>,
+
+    camera_theta: f64,
+
+    clamp: DvsClamp,
+
+    timestamp_policy: DvsTimestampPolicy,
+
+    /// The latest source timestamp seen, or 0 if integration hasn't started
+    running_t: u32,
+}
+
+impl DvsIntegrator {
+    /// Create an integrator for the given video, and set the video's running intensities to the
+    /// start intensity
+    pub fn new<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+        video: &mut Video<W>,
+        params: &DvsParams,
+    ) -> Self {
+        let plane = video.state.plane;
+        let shape = (plane.h_usize(), plane.w_usize(), plane.c_usize());
+        video.state.running_intensities = Array3::from_elem(shape, params.start_intensity);
+        video.display_frame_features = video.state.running_intensities.clone();
+
+        Self {
+            dvs_last_timestamps: Array3::from_elem(shape, START_T),
+            dvs_last_ln_val: Array3::from_elem(
+                shape,
+                (f64::from(params.start_intensity) / 255.0_f64).ln_1p(),
+            ),
+            camera_theta: params.camera_theta,
+            clamp: params.clamp,
+            timestamp_policy: params.timestamp_policy,
+            running_t: 0,
+        }
+    }
+
+    /// The latest source timestamp seen
+    pub fn running_t(&self) -> u32 {
+        self.running_t
+    }
+
+    /// Set the contrast threshold, such as one estimated while the source is running
+    pub fn set_camera_theta(&mut self, camera_theta: f64) {
+        self.camera_theta = camera_theta;
+    }
+
+    /// Set the last timestamp of every pixel, such as after integrating a frame that spans the
+    /// time up to `t`
+    pub fn set_timestamps(&mut self, t: u32) {
+        self.dvs_last_timestamps.fill(t);
+        self.running_t = self.running_t.max(t);
+    }
+
+    /// Integrate the start intensity of every pixel, if that hasn't been done yet
+    pub fn start<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+        &mut self,
+        video: &mut Video<W>,
+    ) -> Result<(), SourceError> {
+        if self.running_t > 0 {
+            return Ok(());
+        }
+        video.integrate_matrix(
+            video.state.running_intensities.clone(),
+            video.state.params.ref_time as f32,
+        )?;
+        let first_events: Vec<Event> = video
+            .integrate_matrix(
+                video.state.running_intensities.clone(),
+                video.state.params.ref_time as f32,
+            )?
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(first_events.len(), video.state.plane.volume());
+        self.running_t = START_T;
+        Ok(())
+    }
+
+    /// Integrate a sequence of DVS events, pushing the resulting ADΔER events onto `events`. For
+    /// each DVS event, the previously seen intensity of its pixel is integrated for all the time
+    /// between the pixel's last event and the current one.
+    pub fn integrate<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+        &mut self,
+        video: &mut Video<W>,
+        dvs_events: &[DvsEvent],
+        events: &mut Vec<Event>,
+    ) {
+        let crf_parameters = *video.encoder.options.crf.get_parameters();
+        let ticks_per_micro = ticks_per_micro(video);
+        let ref_time = f64::from(video.state.params.ref_time);
+
+        for dvs_event in dvs_events {
+            let x = dvs_event.x as usize;
+            let y = dvs_event.y as usize;
+            let mut t = dvs_event.t;
+            self.running_t = self.running_t.max(t);
+
+            if x >= video.state.plane.w_usize() || y >= video.state.plane.h_usize() {
+                continue;
+            }
+
+            // Get the last timestamp for this pixel
+            let last_t = self.dvs_last_timestamps[[y, x, 0]];
+
+            if t < last_t {
+                match self.timestamp_policy {
+                    DvsTimestampPolicy::Drop => continue,
+                    DvsTimestampPolicy::Clamp => t = last_t,
+                }
+            }
+
+            // Get the last ln intensity for this pixel
+            let mut last_ln_val = self.dvs_last_ln_val[[y, x, 0]];
+
+            let px = &mut video.event_pixel_trees[[y, x, 0]];
+
+            if t > last_t + 1 {
+                // Convert the ln intensity to a linear intensity
+                let mut last_val = (last_ln_val.exp() - 1.0) * 255.0;
+
+                self.clamp.apply(&mut last_val, &mut last_ln_val);
+
+                // Integrate the last intensity for this pixel over the time since the last event
+                let time_spanned = f64::from(t - last_t - 1) * ticks_per_micro;
+                let intensity_to_integrate = last_val * time_spanned / ref_time;
+
+                let mut base_val = 0;
+                let _ = integrate_for_px(
+                    px,
+                    &mut base_val,
+                    last_val as u8,
+                    intensity_to_integrate as f32,
+                    time_spanned as f32,
+                    events,
+                    &video.state.params,
+                    &crf_parameters,
+                );
+            }
+
+            // Get the new ln intensity
+            let mut new_ln_val = if dvs_event.p == 0 {
+                last_ln_val - self.camera_theta
+            } else {
+                last_ln_val + self.camera_theta
+            };
+
+            // Update the last intensity for this pixel
+            self.dvs_last_ln_val[[y, x, 0]] = new_ln_val;
+
+            // Update the last timestamp for this pixel
+            self.dvs_last_timestamps[[y, x, 0]] = t;
+
+            if t > last_t {
+                let mut new_val = (new_ln_val.exp() - 1.0) * 255.0;
+
+                self.clamp.apply(&mut new_val, &mut new_ln_val);
+
+                // Update the last intensity for this pixel
+                self.dvs_last_ln_val[[y, x, 0]] = new_ln_val;
+
+                // Integrate 1 source time unit of the new intensity
+                let time_spanned = ticks_per_micro;
+                let intensity_to_integrate = new_val * time_spanned / ref_time;
+
+                let mut base_val = 0;
+                let _ = integrate_for_px(
+                    px,
+                    &mut base_val,
+                    new_val as u8,
+                    intensity_to_integrate as f32,
+                    time_spanned as f32,
+                    events,
+                    &video.state.params,
+                    &crf_parameters,
+                );
+            }
+
+            // Update the running intensity for this pixel
+            if let Some(event) = px.arena[0].best_event {
+                video.state.running_intensities[[y, x, 0]] = u8::get_frame_value(
+                    &event.into(),
+                    SourceType::U8,
+                    video.state.params.ref_time as f64,
+                    32.0,
+                    video.state.params.delta_t_max,
+                    video.instantaneous_view_mode,
+                    if video.instantaneous_view_mode == SAE {
+                        Some(SaeTime {
+                            running_t: px.running_t as DeltaT,
+                            last_fired_t: px.last_fired_t as DeltaT,
+                        })
+                    } else {
+                        None
+                    },
+                );
+                video.display_frame_features[[y, x, 0]] =
+                    video.state.running_intensities[[y, x, 0]];
+            };
+        }
+    }
+
+    /// Integrate the last intensity of every pixel up to `end_t`, or up to the latest timestamp
+    /// seen if that's later, pushing the resulting ADΔER events onto `events`
+    pub fn end<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+        &mut self,
+        video: &mut Video<W>,
+        end_t: Option<u32>,
+        events: &mut Vec<Event>,
+    ) {
+        let end_t = end_t.map_or(self.running_t, |end_t| end_t.max(self.running_t));
+        self.integrate_to(video, end_t, events);
+    }
+
+    /// Integrate the last intensity of every pixel up to `end_t`, such as the start of a frame's
+    /// exposure, pushing the resulting ADΔER events onto `events`
+    pub fn integrate_to<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+        &mut self,
+        video: &mut Video<W>,
+        end_t: u32,
+        events: &mut Vec<Event>,
+    ) {
+        let crf_parameters = *video.encoder.options.crf.get_parameters();
+        let ticks_per_micro = ticks_per_micro(video);
+        let ref_time = f64::from(video.state.params.ref_time);
+
+        for y in 0..video.state.plane.h_usize() {
+            for x in 0..video.state.plane.w_usize() {
+                let last_t = self.dvs_last_timestamps[[y, x, 0]];
+                if end_t <= last_t {
+                    continue;
+                }
+
+                let px = &mut video.event_pixel_trees[[y, x, 0]];
+                let mut base_val = 0;
+
+                // Get the last ln intensity for this pixel
+                let mut last_ln_val = self.dvs_last_ln_val[[y, x, 0]];
+
+                // Convert the ln intensity to a linear intensity
+                let mut last_val = (last_ln_val.exp() - 1.0) * 255.0;
+
+                self.clamp.apply(&mut last_val, &mut last_ln_val);
+
+                // Integrate the last intensity for this pixel over the time since the last event
+                let time_spanned = f64::from(end_t - last_t) * ticks_per_micro;
+                let intensity_to_integrate = last_val * time_spanned / ref_time;
+
+                let _ = integrate_for_px(
+                    px,
+                    &mut base_val,
+                    last_val as u8,
+                    intensity_to_integrate as f32,
+                    time_spanned as f32,
+                    events,
+                    &video.state.params,
+                    &crf_parameters,
+                );
+                self.dvs_last_timestamps[[y, x, 0]] = end_t;
+            }
+        }
+        self.running_t = self.running_t.max(end_t);
+    }
+}
+
+/// The number of ADΔER ticks per microsecond of source time
+fn ticks_per_micro<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+    video: &Video<W>,
+) -> f64 {
+    f64::from(video.state.tps) / f64::from(DVS_SOURCE_TPS)
+}
+
+/// Ingest the integrated ADΔER events which pass the video's filters into its encoder, then
+/// handle their features
+pub(crate) fn ingest_integrated_events<
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+>(
+    video: &mut Video<W>,
+    events: Vec<Event>,
+) -> Result<Vec<Vec<Event>>, SourceError> {
+    if video.state.feature_detection {
+        video.display_frame_features = video.state.running_intensities.clone();
+    }
+
+    // It's expected that the function will spatially parallelize the integrations. With sparse
+    // data, though, this could be pretty wasteful. For now, just wrap the vec in another vec.
+    let mut events_nested: Vec<Vec<Event>> = vec![events];
+
+    video.ingest_events_events(&mut events_nested)?;
+
+    video.handle_features(&events_nested)?;
+
+    Ok(events_nested)
+}
@@ -0,0 +1,527 @@
+use crate::transcoder::source::dvs::{
+    dvs_video, ingest_integrated_events, DvsEvent, DvsIntegrator, DvsParams,
+};
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
+use crate::utils::npy::{read_dvs_record, read_npz_array, NpyReader};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::path::PathBuf;
+use video_rs_adder_dep::Frame;
+
+/// The encoding of a list of DVS events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DvsListFormat {
+    /// One `t,x,y,p` event per line, with the fields separated by commas, semicolons or
+    /// whitespace. Blank lines, comment lines starting with `#` or `%`, and a leading line of
+    /// column names are skipped. Any polarity greater than 0 is an increase in intensity.
+    #[default]
+    Text,
+
+    /// Fixed-size little-endian records of `t` (u64), `x` (u16), `y` (u16) and `p` (u8)
+    Binary,
+
+    /// A NumPy `.npy` array: either a structured array with the fields `t`, `x`, `y` and `p`, or
+    /// a 2-dimensional array with those columns. The array is read as it's consumed.
+    Npy,
+
+    /// The first array of a NumPy `.npz` archive, laid out as for [`DvsListFormat::Npy`]. The
+    /// array is decompressed into memory when the source is created.
+    Npz,
+}
+
+/// The unit of the timestamps in a list of DVS events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DvsTimeUnit {
+    /// Seconds, such as the floating-point timestamps of ESIM and v2e
+    Seconds,
+
+    /// Milliseconds
+    Milliseconds,
+
+    /// Microseconds
+    #[default]
+    Microseconds,
+
+    /// Nanoseconds
+    Nanoseconds,
+}
+
+impl DvsTimeUnit {
+    /// Convert a timestamp in this unit to microseconds
+    pub(crate) fn to_micros(self, t: f64) -> f64 {
+        match self {
+            DvsTimeUnit::Seconds => t * 1_000_000.0,
+            DvsTimeUnit::Milliseconds => t * 1_000.0,
+            DvsTimeUnit::Microseconds => t,
+            DvsTimeUnit::Nanoseconds => t / 1_000.0,
+        }
+    }
+}
+
+/// Converts the timestamps of a list of DVS events to whole microseconds after the first event,
+/// so that lists timed from the Unix epoch, such as those exported from ROS, still fit in a `u32`
+#[derive(Debug, Clone, Copy)]
+struct DvsClock {
+    unit: DvsTimeUnit,
+
+    /// The first timestamp of the list, in its own unit
+    t_start: Option<f64>,
+}
+
+impl DvsClock {
+    fn new(unit: DvsTimeUnit) -> Self {
+        Self {
+            unit,
+            t_start: None,
+        }
+    }
+
+    /// Convert a timestamp to whole microseconds after the first one
+    fn micros(&mut self, t: f64) -> io::Result<u32> {
+        let t_start = *self.t_start.get_or_insert(t);
+        let micros = self.unit.to_micros(t - t_start).max(0.0).round();
+        if micros > f64::from(u32::MAX) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("timestamp {t} is too far after the start time {t_start}"),
+            ));
+        }
+        Ok(micros as u32)
+    }
+}
+
+/// Attributes of a DVS event list -> ADΔER transcode
+pub struct DvsList<W: Write + std::marker::Send + std::marker::Sync + 'static> {
+    pub(crate) video: Video<W>,
+
+    input_reader: DvsListReader,
+
+    format: DvsListFormat,
+
+    clock: DvsClock,
+
+    /// The per-pixel log intensity model of the DVS events
+    pub integrator: DvsIntegrator,
+
+    params: DvsParams,
+
+    /// The number of lines of text read so far
+    line_count: usize,
+
+    /// Whether a line of text has been parsed as an event, or skipped as column names
+    past_header: bool,
+
+    /// Whether the end of the input file has been reached
+    ended: bool,
+}
+
+/// The reader of the events, depending on the format of the list
+enum DvsListReader {
+    File(BufReader<File>),
+    Npy(NpyReader<Box<dyn Read + Send>>),
+}
+
+unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Sync for DvsList<W> {}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> DvsList<W> {
+    /// Create a new `DvsList` transcoder for a sensor of the given resolution
+    pub fn new(
+        ref_time: u32,
+        input_filename: String,
+        width: u16,
+        height: u16,
+        format: DvsListFormat,
+        time_unit: DvsTimeUnit,
+        params: DvsParams,
+    ) -> Result<Self, Box<dyn Error>> {
+        let source = File::open(PathBuf::from(input_filename))?;
+        let input_reader = match format {
+            DvsListFormat::Text | DvsListFormat::Binary => {
+                DvsListReader::File(BufReader::new(source))
+            }
+            DvsListFormat::Npy | DvsListFormat::Npz => {
+                let reader: Box<dyn Read + Send> = if format == DvsListFormat::Npy {
+                    Box::new(BufReader::new(source))
+                } else {
+                    Box::new(Cursor::new(read_npz_array(BufReader::new(source), None)?))
+                };
+                DvsListReader::Npy(NpyReader::new(reader)?)
+            }
+        };
+
+        let plane = PlaneSize::new(width, height, 1)?;
+        let mut video = dvs_video(plane, ref_time, &params)?;
+        let integrator = DvsIntegrator::new(&mut video, &params);
+
+        Ok(DvsList {
+            video,
+            input_reader,
+            format,
+            clock: DvsClock::new(time_unit),
+            integrator,
+            params,
+            line_count: 0,
+            past_header: false,
+            ended: false,
+        })
+    }
+
+    /// The encoding of the events in the source file
+    pub fn format(&self) -> DvsListFormat {
+        self.format
+    }
+
+    /// The sensor and model parameters of the transcode
+    pub fn params(&self) -> &DvsParams {
+        &self.params
+    }
+
+    /// Read the next event from the list, or `None` at the end of the file
+    fn next_event(&mut self) -> io::Result<Option<DvsEvent>> {
+        if self.format == DvsListFormat::Text {
+            return self.next_text_event();
+        }
+        let clock = &mut self.clock;
+        match &mut self.input_reader {
+            DvsListReader::Npy(reader) => match reader.next_record()? {
+                None => Ok(None),
+                Some(record) => read_dvs_record(&record, |t| clock.micros(t))?
+                    .map(Some)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "The array is missing DVS event fields",
+                        )
+                    }),
+            },
+            DvsListReader::File(input_reader) => {
+                let mut buffer = [0; 13];
+                match input_reader.read_exact(&mut buffer) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+                let t = u64::from_le_bytes(buffer[0..8].try_into().unwrap());
+                Ok(Some(DvsEvent {
+                    t: clock.micros(t as f64)?,
+                    x: u16::from_le_bytes([buffer[8], buffer[9]]),
+                    y: u16::from_le_bytes([buffer[10], buffer[11]]),
+                    p: u8::from(buffer[12] > 0),
+                }))
+            }
+        }
+    }
+
+    fn next_text_event(&mut self) -> io::Result<Option<DvsEvent>> {
+        let DvsListReader::File(input_reader) = &mut self.input_reader else {
+            return Ok(None);
+        };
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if input_reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line_count += 1;
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('%') {
+                continue;
+            }
+            let event = parse_text_event(line, &mut self.clock);
+            let past_header = std::mem::replace(&mut self.past_header, true);
+            match event {
+                Some(event) => return event.map(Some),
+                // A line of column names, such as "t,x,y,p"
+                None if !past_header => continue,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid DVS event on line {}: {line}", self.line_count),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Parse a line of `t,x,y,p` text, or `None` if it isn't an event
+fn parse_text_event(line: &str, clock: &mut DvsClock) -> Option<io::Result<DvsEvent>> {
+    let mut fields = line
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|field| !field.is_empty());
+    let t: f64 = fields.next()?.parse().ok()?;
+    let x = fields.next()?.parse().ok()?;
+    let y = fields.next()?.parse().ok()?;
+    let p: f64 = fields.next()?.parse().ok()?;
+    Some(clock.micros(t).map(|t| DvsEvent {
+        t,
+        x,
+        y,
+        p: u8::from(p > 0.0),
+    }))
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for DvsList<W> {
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if self.ended {
+            return Err(SourceError::EndOfStream);
+        }
+
+        self.integrator.start(&mut self.video)?;
+
+        // Read events until we find a timestamp that exceeds our `running_t` by at least
+        // `view_interval`, or reach the end of the file
+        let mut dvs_events: Vec<DvsEvent> = Vec::new();
+        let start_running_t = self.integrator.running_t();
+        loop {
+            match self.next_event()? {
+                Some(dvs_event) => {
+                    dvs_events.push(dvs_event);
+                    if dvs_event.t > start_running_t + self.params.view_interval {
+                        break;
+                    }
+                }
+                None => {
+                    self.ended = true;
+                    break;
+                }
+            }
+        }
+
+        let mut events: Vec<Event> = Vec::new();
+        self.integrator
+            .integrate(&mut self.video, &dvs_events, &mut events);
+        if self.ended {
+            self.integrator
+                .end(&mut self.video, self.params.end_t, &mut events);
+        }
+
+        ingest_integrated_events(&mut self.video, events)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        None
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W> for DvsList<W> {
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::npy::{read_events_npy, write_events_npz, NpyEventWriter};
+
+    /// Parse a line of text, with timestamps relative to 0 rather than the first event
+    fn parse(line: &str, unit: DvsTimeUnit) -> Option<DvsEvent> {
+        let mut clock = DvsClock {
+            unit,
+            t_start: Some(0.0),
+        };
+        parse_text_event(line, &mut clock).map(|event| event.unwrap())
+    }
+
+    #[test]
+    fn text_events() {
+        let unit = DvsTimeUnit::Seconds;
+        assert_eq!(
+            parse("0.0015,3,4,1", unit),
+            Some(DvsEvent {
+                t: 1500,
+                x: 3,
+                y: 4,
+                p: 1
+            })
+        );
+        assert_eq!(
+            parse("0.002 5\t6 -1", unit),
+            Some(DvsEvent {
+                t: 2000,
+                x: 5,
+                y: 6,
+                p: 0
+            })
+        );
+        assert_eq!(parse("t,x,y,p", unit), None);
+        assert_eq!(parse("12,3,4", unit), None);
+        assert_eq!(
+            parse("1500000;3;4;0", DvsTimeUnit::Nanoseconds),
+            Some(DvsEvent {
+                t: 1500,
+                x: 3,
+                y: 4,
+                p: 0
+            })
+        );
+    }
+
+    #[test]
+    fn rebased_timestamps() {
+        // Seconds since the Unix epoch, as exported from ROS
+        let mut clock = DvsClock::new(DvsTimeUnit::Seconds);
+        assert_eq!(clock.micros(1.5e9).unwrap(), 0);
+        assert_eq!(clock.micros(1.5e9 + 0.25).unwrap(), 250_000);
+
+        // More than 2^32 microseconds after the first event
+        let err = clock.micros(1.5e9 + 5000.0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn consume_text_and_binary() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir();
+        let text_path = dir.join(format!("adder_dvs_list_{}.csv", std::process::id()));
+        let binary_path = dir.join(format!("adder_dvs_list_{}.bin", std::process::id()));
+        let npy_path = dir.join(format!("adder_dvs_list_{}.npy", std::process::id()));
+        let npz_path = dir.join(format!("adder_dvs_list_{}.npz", std::process::id()));
+        let events = [(10_u64, 1_u16, 1_u16, 1_u8), (20, 2, 2, 0), (30, 9, 9, 1)];
+
+        let mut text = String::from("# A comment\nt,x,y,p\n");
+        let mut binary = Vec::new();
+        let mut npy = NpyEventWriter::new(File::create(&npy_path)?, false)?;
+        for (t, x, y, p) in events {
+            text += &format!("{t},{x},{y},{p}\n");
+            binary.extend_from_slice(&t.to_le_bytes());
+            binary.extend_from_slice(&x.to_le_bytes());
+            binary.extend_from_slice(&y.to_le_bytes());
+            binary.push(p);
+            npy.write_event(&DvsEvent {
+                t: t as u32,
+                x,
+                y,
+                p,
+            })?;
+        }
+        npy.finish()?;
+        write_events_npz(
+            File::create(&npz_path)?,
+            "events",
+            read_events_npy::<DvsEvent, _>(File::open(&npy_path)?)?,
+            false,
+        )?;
+        std::fs::write(&text_path, text)?;
+        std::fs::write(&binary_path, binary)?;
+
+        for (path, format) in [
+            (&text_path, DvsListFormat::Text),
+            (&binary_path, DvsListFormat::Binary),
+            (&npy_path, DvsListFormat::Npy),
+            (&npz_path, DvsListFormat::Npz),
+        ] {
+            let source = DvsList::new(
+                10,
+                path.to_str().unwrap().to_string(),
+                4,
+                4,
+                format,
+                DvsTimeUnit::Microseconds,
+                DvsParams::default().end_t(100),
+            );
+            std::fs::remove_file(path)?;
+            let mut source: DvsList<io::Sink> = source?;
+
+            let events = source.consume()?;
+            assert!(!events[0].is_empty());
+            assert_eq!(source.integrator.dvs_last_timestamps[[1, 1, 0]], 100);
+            assert!(matches!(source.consume(), Err(SourceError::EndOfStream)));
+        }
+        Ok(())
+    }
+}
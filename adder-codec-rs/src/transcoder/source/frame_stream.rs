@@ -0,0 +1,274 @@
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::Mode::FramePerfect;
+use adder_codec_core::{
+    AbsoluteT, DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode,
+};
+use ndarray::Array3;
+use std::collections::VecDeque;
+use std::io::Write;
+use video_rs_adder_dep::Frame;
+
+/// Attributes of an in-memory frame -> ADΔER transcode.
+///
+/// The caller pushes frames with [`push_frame`](FrameStream::push_frame), and each call to
+/// [`consume`](Source::consume) integrates the oldest pushed frame. This lets a simulator or
+/// another pipeline transcode to ADΔER without writing a video file first.
+pub struct FrameStream<W: Write + 'static + std::marker::Send + std::marker::Sync> {
+    pub(crate) video: Video<W>,
+
+    /// An 8-bit copy of the previous input frame, for display
+    pub(crate) input_frame: Frame,
+
+    /// The frames waiting to be integrated, with the number of ticks each spans
+    queue: VecDeque<(Array3<f32>, f32)>,
+
+    /// The timestamp of the latest pushed frame
+    last_t: Option<AbsoluteT>,
+
+    /// The intensity of a saturated pixel
+    max_intensity: f32,
+
+    /// Whether the caller has pushed its last frame
+    ended: bool,
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> FrameStream<W> {
+    /// Create a new `FrameStream` source for frames of the given size
+    pub fn new(width: u16, height: u16, color_input: bool) -> Result<Self, SourceError> {
+        let plane = PlaneSize::new(width, height, if color_input { 3 } else { 1 })?;
+        let video = Video::new(plane, FramePerfect, None)?;
+
+        Ok(FrameStream {
+            video,
+            input_frame: Frame::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize())),
+            queue: VecDeque::new(),
+            last_t: None,
+            max_intensity: 255.0,
+            ended: false,
+        })
+    }
+
+    /// Set the intensity of a saturated pixel, such as 65535 for 16-bit frames. Defaults to 255.
+    pub fn max_intensity(mut self, max_intensity: f32) -> Result<Self, SourceError> {
+        if max_intensity <= 0.0 {
+            return Err(SourceError::BadParams(
+                "max_intensity must be positive".to_string(),
+            ));
+        }
+        self.max_intensity = max_intensity;
+        Ok(self)
+    }
+
+    /// Queue a frame to be integrated. The frame must have the shape `(height, width, channels)`.
+    ///
+    /// `timestamp` is the time, in ticks, at the end of the frame's exposure. Each frame spans
+    /// the ticks since the previous frame's timestamp, and the first frame spans `ref_time`.
+    pub fn push_frame<T: Copy + Into<f32>>(
+        &mut self,
+        frame: Array3<T>,
+        timestamp: AbsoluteT,
+    ) -> Result<(), SourceError> {
+        if self.ended {
+            return Err(SourceError::BufferChannelClosed);
+        }
+        let plane = &self.video.state.plane;
+        if frame.dim() != (plane.h_usize(), plane.w_usize(), plane.c_usize()) {
+            return Err(SourceError::BadParams(format!(
+                "Frame shape {:?} does not match the source's {}x{}x{}",
+                frame.dim(),
+                plane.h(),
+                plane.w(),
+                plane.c()
+            )));
+        }
+
+        let time_spanned = match self.last_t {
+            None => self.video.state.params.ref_time,
+            Some(last_t) if timestamp > last_t => timestamp - last_t,
+            Some(last_t) => {
+                return Err(SourceError::BadParams(format!(
+                    "Frame timestamp {timestamp} is not after the previous timestamp {last_t}"
+                )));
+            }
+        };
+        self.last_t = Some(timestamp);
+        self.queue
+            .push_back((frame.mapv(Into::into), time_spanned as f32));
+        Ok(())
+    }
+
+    /// Signal that no more frames will be pushed. Once the queued frames are consumed, the
+    /// source returns [`SourceError::EndOfStream`].
+    pub fn end(&mut self) {
+        self.ended = true;
+    }
+
+    /// Get the number of frames waiting to be integrated
+    pub fn queued_count(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Get the previous input frame, scaled to 8 bits
+    pub fn get_last_input_frame(&self) -> &Frame {
+        &self.input_frame
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for FrameStream<W> {
+    /// Integrate the oldest pushed frame over the ticks it spans. Returns
+    /// [`SourceError::BufferEmpty`] if no frame is waiting.
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        let Some((matrix, time_spanned)) = self.queue.pop_front() else {
+            return Err(if self.ended {
+                SourceError::EndOfStream
+            } else {
+                SourceError::BufferEmpty
+            });
+        };
+
+        let scale = 255.0 / self.max_intensity;
+        self.input_frame = matrix.mapv(|v| (v * scale).clamp(0.0, 255.0) as u8);
+
+        self.video
+            .integrate_matrix_precise(matrix, self.max_intensity, time_spanned)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        Some(self.get_last_input_frame())
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        let video = self.get_video_ref();
+        video.get_tps() as f64 / video.get_ref_time() as f64
+            * video.state.plane.volume() as f64
+            * 32.0
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
+    for FrameStream<W>
+{
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn push_and_consume() -> Result<(), Box<dyn Error>> {
+        let mut source: FrameStream<io::Sink> = FrameStream::new(4, 3, false)?
+            .max_intensity(65535.0)?
+            .time_parameters(25500, 255, 255 * 4, None)?;
+
+        assert!(matches!(source.consume(), Err(SourceError::BufferEmpty)));
+        assert!(source
+            .push_frame(Array3::<u16>::zeros((4, 3, 1)), 255)
+            .is_err());
+
+        source.push_frame(Array3::from_elem((3, 4, 1), 30000_u16), 255)?;
+        source.push_frame(Array3::from_elem((3, 4, 1), 40000_u16), 510)?;
+        assert!(source
+            .push_frame(Array3::from_elem((3, 4, 1), 40000_u16), 510)
+            .is_err());
+        source.end();
+        assert_eq!(source.queued_count(), 2);
+
+        source.consume()?;
+        source.consume()?;
+        assert_eq!(source.get_last_input_frame()[[0, 0, 0]], 155);
+        assert!(matches!(source.consume(), Err(SourceError::EndOfStream)));
+        Ok(())
+    }
+}
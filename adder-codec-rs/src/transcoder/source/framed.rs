@@ -1,6 +1,6 @@
 use crate::transcoder::source::video::SourceError;
-use crate::transcoder::source::video::Video;
 use crate::transcoder::source::video::{Source, VideoBuilder};
+use crate::transcoder::source::video::{Video, VideoCheckpoint};
 use adder_codec_core::Mode::FramePerfect;
 use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
 
@@ -11,15 +11,74 @@ use crate::utils::cv::handle_color;
 #[cfg(feature = "feature-logging")]
 use crate::utils::cv::{calculate_quality_metrics, QualityMetrics};
 
+use ndarray::s;
 use rayon::ThreadPool;
-use std::io::Write;
+use std::io::{Seek, Write};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[cfg(feature = "feature-logging")]
 use chrono::Local;
 use tokio::runtime::Runtime;
 use video_rs_adder_dep::{self, Decoder, Frame, Locator, Options, Resize};
 
+/// A rectangle of pixels, in the coordinates of the (scaled) source frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    /// The x-coordinate of the left column
+    pub x: u16,
+
+    /// The y-coordinate of the top row
+    pub y: u16,
+
+    /// The number of columns
+    pub width: u16,
+
+    /// The number of rows
+    pub height: u16,
+}
+
+impl Rect {
+    /// The x-coordinate just past the right column
+    fn right(&self) -> usize {
+        self.x as usize + self.width as usize
+    }
+
+    /// The y-coordinate just past the bottom row
+    fn bottom(&self) -> usize {
+        self.y as usize + self.height as usize
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x as usize..self.right()).contains(&x)
+            && (self.y as usize..self.bottom()).contains(&y)
+    }
+}
+
+impl FromStr for Rect {
+    type Err = SourceError;
+
+    /// Parse a rectangle given as `x,y,width,height`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_rect =
+            || SourceError::BadParams(format!("Invalid rectangle `{s}`. Use x,y,width,height"));
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u16>())
+            .collect::<Result<Vec<u16>, _>>()
+            .map_err(|_| bad_rect())?;
+        match values[..] {
+            [x, y, width, height] => Ok(Rect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(bad_rect()),
+        }
+    }
+}
+
 /// Attributes of a framed video -> ADΔER transcode
 pub struct Framed<W: Write + 'static + std::marker::Send + std::marker::Sync> {
     cap: Decoder,
@@ -37,6 +96,20 @@ pub struct Framed<W: Write + 'static + std::marker::Send + std::marker::Sync> {
     /// Whether the input video is color
     color_input: bool,
 
+    /// Whether each frame spans the time until the next frame's presentation timestamp, rather
+    /// than exactly `ref_time` ticks
+    use_timestamps: bool,
+
+    /// The next decoded frame and its presentation timestamp (in seconds), read ahead to find
+    /// the span of the current frame
+    next_frame: Option<(f64, Frame)>,
+
+    /// The region of the (scaled) input frames to transcode, if not the whole frame
+    crop: Option<Rect>,
+
+    /// The number of frames consumed since the start frame
+    frames_consumed: u64,
+
     pub(crate) video: Video<W>,
 }
 unsafe impl<W: Write + std::marker::Send + std::marker::Sync> Sync for Framed<W> {}
@@ -75,6 +148,10 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
             source_fps,
             scale,
             color_input,
+            use_timestamps: false,
+            next_frame: None,
+            crop: None,
+            frames_consumed: 0,
             video,
         })
     }
@@ -87,11 +164,84 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
         };
         let ts_millis = (frame_idx_start as f32 / self.source_fps * 1000.0) as i64;
         self.cap.reader.seek(ts_millis)?;
+        self.next_frame = None;
 
         self.frame_idx_start = frame_idx_start;
         Ok(self)
     }
 
+    /// Transcode only the given region of the (scaled) source frames. The ADΔER plane is sized
+    /// to the region, and the event coordinates are relative to its top-left corner.
+    ///
+    /// This recreates the underlying video, so it must be called before setting the other
+    /// parameters of the source.
+    pub fn crop(self, region: Rect) -> Result<Self, SourceError> {
+        self.regions(&[region])
+    }
+
+    /// Transcode only the pixels in the given regions of the (scaled) source frames. The ADΔER
+    /// plane is sized to the bounding box of the regions, and the event coordinates are relative
+    /// to its top-left corner. The pixels of the bounding box which are outside every region
+    /// never fire events.
+    ///
+    /// This recreates the underlying video, so it must be called before setting the other
+    /// parameters of the source.
+    pub fn regions(mut self, regions: &[Rect]) -> Result<Self, SourceError> {
+        let (width, height) = self.cap.size_out();
+        if regions.is_empty() {
+            return Err(SourceError::BadParams("No regions given".to_string()));
+        }
+        if regions.iter().any(|region| {
+            region.width == 0
+                || region.height == 0
+                || region.right() > width as usize
+                || region.bottom() > height as usize
+        }) {
+            return Err(SourceError::BadParams(format!(
+                "Regions must be non-empty and within the {width}x{height} frame"
+            )));
+        }
+
+        // The bounding box of the regions
+        let x = regions.iter().map(|region| region.x).min().unwrap_or(0);
+        let y = regions.iter().map(|region| region.y).min().unwrap_or(0);
+        let right = regions.iter().map(Rect::right).max().unwrap_or(0);
+        let bottom = regions.iter().map(Rect::bottom).max().unwrap_or(0);
+        let bounds = Rect {
+            x,
+            y,
+            width: (right - x as usize) as u16,
+            height: (bottom - y as usize) as u16,
+        };
+
+        let plane = PlaneSize::new(bounds.width, bounds.height, self.video.state.plane.c())?;
+        self.video = Video::new(plane, FramePerfect, None)?;
+        for px in self.video.event_pixel_trees.iter_mut() {
+            let x = bounds.x as usize + px.coord.x_usize();
+            let y = bounds.y as usize + px.coord.y_usize();
+            px.in_roi = regions.iter().any(|region| region.contains(x, y));
+        }
+
+        self.input_frame = Frame::default((plane.h_usize(), plane.w_usize(), 3));
+        self.crop = Some(bounds);
+        Ok(self)
+    }
+
+    /// Convert a decoded frame to the source's color mode, and crop it to the transcoded region
+    fn prepare_frame(&self, frame: Frame) -> Result<Frame, SourceError> {
+        let frame = handle_color(frame, self.color_input)?;
+        Ok(match self.crop {
+            None => frame,
+            Some(crop) => frame
+                .slice(s![
+                    crop.y as usize..crop.bottom(),
+                    crop.x as usize..crop.right(),
+                    ..
+                ])
+                .to_owned(),
+        })
+    }
+
     /// Automatically derive the ticks per second from the source FPS and `ref_time`
     pub fn auto_time_parameters(
         mut self,
@@ -112,7 +262,46 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
         Ok(self)
     }
 
-    /// Get the number of ticks each frame is said to span
+    /// Use the presentation timestamps of the container to set the number of ticks each frame
+    /// spans, for variable frame rate videos. Each frame spans the time until the next frame's
+    /// timestamp, and the last frame spans `ref_time`. The ticks per second are still set from
+    /// the average frame rate, so `ref_time` remains the span of a typical frame.
+    #[must_use]
+    pub fn use_timestamps(mut self, use_timestamps: bool) -> Self {
+        self.use_timestamps = use_timestamps;
+        self
+    }
+
+    /// Decode the next frame and the number of ticks it spans, from the presentation
+    /// timestamps of it and the frame after it
+    fn decode_timed(&mut self) -> Result<(Frame, f32), SourceError> {
+        let (t, frame) = match self.next_frame.take() {
+            Some(next_frame) => next_frame,
+            None => {
+                let (t, frame) = self.cap.decode()?;
+                (t.as_secs_f64(), frame)
+            }
+        };
+
+        let ref_time = self.video.state.params.ref_time as f32;
+        let time_spanned = match self.cap.decode() {
+            Ok((next_t, next_frame)) => {
+                let next_t = next_t.as_secs_f64();
+                self.next_frame = Some((next_t, next_frame));
+                let ticks = ((next_t - t) * self.video.get_tps() as f64) as f32;
+                // Fall back to the nominal span if the timestamps are missing or out of order
+                if ticks >= 1.0 {
+                    ticks
+                } else {
+                    ref_time
+                }
+            }
+            // The error (typically the end of the stream) is returned on the next call
+            Err(_) => ref_time,
+        };
+        Ok((frame, time_spanned))
+    }
+
     pub fn get_ref_time(&self) -> u32 {
         self.video.state.params.ref_time
     }
@@ -123,17 +312,58 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
     }
 }
 
+impl<W: Write + Seek + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
+    /// Flush the output and write a checkpoint of the transcode, including the number of frames
+    /// consumed so far. See [`Video::checkpoint`].
+    pub fn checkpoint<C: Write>(&mut self, writer: C) -> Result<(), SourceError> {
+        self.video.checkpoint(writer, self.frames_consumed)
+    }
+
+    /// Resume a transcode from a checkpoint written by [`Framed::checkpoint`]. The source must
+    /// be set up just as the checkpointed one was, including its start frame. See
+    /// [`Video::resume`].
+    ///
+    /// The frames which were already transcoded are decoded and skipped, which lands on exactly
+    /// the next frame, where seeking might not.
+    pub fn resume(mut self, checkpoint: VideoCheckpoint) -> Result<Self, SourceError> {
+        let frames_consumed = checkpoint.source_position();
+        self.video = self.video.resume(checkpoint)?;
+        self.next_frame = None;
+        for _ in 0..frames_consumed {
+            self.cap.decode()?;
+        }
+        self.frames_consumed = frames_consumed;
+        Ok(self)
+    }
+}
+
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for Framed<W> {
     /// Get pixel-wise intensities directly from source frame, and integrate them with
-    /// `ref_time` (the number of ticks each frame is said to span)
+    /// `ref_time` (the number of ticks each frame is said to span), or with the span between
+    /// presentation timestamps if [`use_timestamps`](Framed::use_timestamps) is set
     fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
-        let (_, frame) = self.cap.decode()?;
-        self.input_frame = handle_color(frame, self.color_input)?;
+        let res = if self.use_timestamps {
+            let (frame, time_spanned) = self.decode_timed()?;
+            self.frames_consumed += 1;
+            self.input_frame = self.prepare_frame(frame)?;
+
+            // Frame values are brightness per `ref_time`, so a longer span integrates more
+            // intensity
+            self.video.integrate_matrix_precise(
+                self.input_frame.mapv(f32::from),
+                255.0,
+                time_spanned,
+            )
+        } else {
+            let (_, frame) = self.cap.decode()?;
+            self.frames_consumed += 1;
+            self.input_frame = self.prepare_frame(frame)?;
 
-        let res = self.video.integrate_matrix(
-            self.input_frame.clone(),
-            self.video.state.params.ref_time as f32,
-        );
+            self.video.integrate_matrix(
+                self.input_frame.clone(),
+                self.video.state.params.ref_time as f32,
+            )
+        };
         #[cfg(feature = "feature-logging")]
         {
             if let Some(handle) = &mut self.video.state.feature_log_handle {
@@ -280,3 +510,74 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn parse_rect() {
+        assert_eq!(
+            "2, 3,40,50".parse::<Rect>().unwrap(),
+            Rect {
+                x: 2,
+                y: 3,
+                width: 40,
+                height: 50
+            }
+        );
+        assert!("2,3,40".parse::<Rect>().is_err());
+        assert!("2,3,40,-1".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn regions() -> Result<(), Box<dyn Error>> {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/samples/lake_scaled_hd_crop.mp4");
+        let regions = [
+            Rect {
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 10,
+            },
+            Rect {
+                x: 50,
+                y: 25,
+                width: 20,
+                height: 20,
+            },
+        ];
+        let mut source: Framed<io::Sink> = Framed::new(path.clone(), false, 1.0)?
+            .regions(&regions)?
+            .auto_time_parameters(255, 255 * 30, None)?;
+        let plane = source.get_video_ref().state.plane;
+        assert_eq!((plane.w(), plane.h()), (60, 25));
+
+        // Every pixel in a region fires by the time delta_t_max is reached, and no pixel outside
+        let mut event_count = 0;
+        for _ in 0..40 {
+            for event in source.consume()?.iter().flatten() {
+                let x = event.coord.x_usize() + 10;
+                let y = event.coord.y_usize() + 20;
+                assert!(regions.iter().any(|region| region.contains(x, y)));
+                event_count += 1;
+            }
+        }
+        assert!(event_count >= 30 * 10 + 20 * 20);
+        assert_eq!(source.get_last_input_frame().dim(), (25, 60, 1));
+
+        let too_wide = Rect {
+            x: 0,
+            y: 0,
+            width: u16::MAX,
+            height: 1,
+        };
+        assert!(Framed::<io::Sink>::new(path, false, 1.0)?
+            .crop(too_wide)
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,497 @@
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::Mode::FramePerfect;
+use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
+use image::DynamicImage;
+use ndarray::Array3;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use video_rs_adder_dep::Frame;
+
+/// The file extensions of the image formats an [`ImageSequence`] can read
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "tif", "tiff", "pgm", "ppm", "pnm"];
+
+/// The file extension of raw frames of little-endian 32-bit floats
+const RAW_F32_EXTENSION: &str = "f32";
+
+/// The sample precision of the frames of an [`ImageSequence`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageDepth {
+    /// 8-bit integer samples
+    U8,
+
+    /// 16-bit integer samples, including 10-, 12- and 14-bit data stored in 16 bits
+    U16,
+
+    /// 32-bit float samples, nominally in the range [0, 1]
+    F32,
+}
+
+/// Attributes of an image sequence -> ADΔER transcode.
+///
+/// Frames are read in file name order from a directory of PNG, TIFF or PNM (PGM/PPM) images, or
+/// of raw `.f32` frames. Unlike [`Framed`](crate::transcoder::source::framed::Framed), the
+/// frames are integrated at their full precision.
+pub struct ImageSequence<W: Write + 'static + std::marker::Send + std::marker::Sync> {
+    pub(crate) video: Video<W>,
+
+    /// An 8-bit copy of the previous input frame, for display
+    pub(crate) input_frame: Frame,
+
+    frame_paths: Vec<PathBuf>,
+
+    /// The index of the next frame to read
+    frame_idx: usize,
+
+    /// The capture time of each frame, in seconds
+    timestamps: Option<Vec<f64>>,
+
+    depth: ImageDepth,
+
+    /// Whether the frames are raw little-endian floats, rather than image files
+    raw: bool,
+
+    /// The intensity of a saturated pixel
+    max_intensity: f32,
+
+    /// Whether the input is color
+    color_input: bool,
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> ImageSequence<W> {
+    /// Create a new `ImageSequence` source from a directory of image files. The bit depth and
+    /// resolution are taken from the first image.
+    pub fn new(input_dir: PathBuf, color_input: bool) -> Result<Self, SourceError> {
+        let frame_paths = list_frames(&input_dir, &IMAGE_EXTENSIONS)?;
+        let first = image::open(&frame_paths[0])?;
+        let color = first.color();
+        let depth = match color.bytes_per_pixel() / color.channel_count() {
+            1 => ImageDepth::U8,
+            2 => ImageDepth::U16,
+            _ => ImageDepth::F32,
+        };
+        let plane = PlaneSize::new(
+            first.width() as u16,
+            first.height() as u16,
+            if color_input { 3 } else { 1 },
+        )?;
+        Self::with_frames(plane, frame_paths, depth, false, color_input)
+    }
+
+    /// Create a new `ImageSequence` source from a directory of raw `.f32` frames. Each frame is
+    /// `height * width * channels` little-endian 32-bit floats in row-major order, with
+    /// interleaved channels, and nominally in the range [0, 1].
+    pub fn new_raw_f32(
+        input_dir: PathBuf,
+        width: u16,
+        height: u16,
+        color_input: bool,
+    ) -> Result<Self, SourceError> {
+        let frame_paths = list_frames(&input_dir, &[RAW_F32_EXTENSION])?;
+        let plane = PlaneSize::new(width, height, if color_input { 3 } else { 1 })?;
+        Self::with_frames(plane, frame_paths, ImageDepth::F32, true, color_input)
+    }
+
+    fn with_frames(
+        plane: PlaneSize,
+        frame_paths: Vec<PathBuf>,
+        depth: ImageDepth,
+        raw: bool,
+        color_input: bool,
+    ) -> Result<Self, SourceError> {
+        let video = Video::new(plane, FramePerfect, None)?;
+
+        Ok(ImageSequence {
+            video,
+            input_frame: Frame::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize())),
+            frame_paths,
+            frame_idx: 0,
+            timestamps: None,
+            depth,
+            raw,
+            max_intensity: match depth {
+                ImageDepth::U8 => 255.0,
+                ImageDepth::U16 | ImageDepth::F32 => 65535.0,
+            },
+            color_input,
+        })
+    }
+
+    /// Set the intensity of a saturated pixel. For 16-bit frames, this is the maximum value of
+    /// the sensor's data, such as 4095 for 12-bit data. For float frames, this is the intensity
+    /// which a sample of 1.0 represents.
+    pub fn max_intensity(mut self, max_intensity: f32) -> Result<Self, SourceError> {
+        if max_intensity <= 0.0 {
+            return Err(SourceError::BadParams(
+                "max_intensity must be positive".to_string(),
+            ));
+        }
+        self.max_intensity = max_intensity;
+        Ok(self)
+    }
+
+    /// Read the capture time of each frame from a text file with one timestamp (in seconds) per
+    /// line. Each frame is then integrated over the ticks until the next frame, rather than
+    /// over `ref_time`.
+    pub fn timestamps(mut self, timestamps_path: &Path) -> Result<Self, SourceError> {
+        let text = std::fs::read_to_string(timestamps_path)?;
+        let timestamps = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse::<f64>()
+                    .map_err(|_| SourceError::BadParams(format!("Invalid timestamp: {line}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if timestamps.len() < self.frame_paths.len() {
+            return Err(SourceError::BadParams(format!(
+                "Found {} timestamps for {} frames",
+                timestamps.len(),
+                self.frame_paths.len()
+            )));
+        }
+        if timestamps.windows(2).any(|pair| pair[1] <= pair[0]) {
+            return Err(SourceError::BadParams(
+                "Timestamps must be increasing".to_string(),
+            ));
+        }
+        self.timestamps = Some(timestamps);
+        Ok(self)
+    }
+
+    /// Automatically derive the ticks per second from the given frame rate and `ref_time`
+    pub fn auto_time_parameters(
+        mut self,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        source_fps: f32,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            let tps = (ref_time as f32 * source_fps) as DeltaT;
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            return Err(SourceError::BadParams(
+                "delta_t_max must be a multiple of ref_time".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Get the sample precision of the frames
+    pub fn depth(&self) -> ImageDepth {
+        self.depth
+    }
+
+    /// Get the source camera type to write in the ADΔER header. Float frames are scaled to the
+    /// 16-bit range, so they're marked as 16-bit.
+    pub fn source_camera(&self) -> SourceCamera {
+        match self.depth {
+            ImageDepth::U8 => SourceCamera::FramedU8,
+            ImageDepth::U16 | ImageDepth::F32 => SourceCamera::FramedU16,
+        }
+    }
+
+    /// Get the number of frames in the sequence
+    pub fn frame_count(&self) -> usize {
+        self.frame_paths.len()
+    }
+
+    /// Get the previous input frame, scaled to 8 bits
+    pub fn get_last_input_frame(&self) -> &Frame {
+        &self.input_frame
+    }
+
+    /// The number of ticks the frame at `idx` spans
+    fn frame_span(&self, idx: usize) -> f32 {
+        let ref_time = self.video.state.params.ref_time as f32;
+        match &self.timestamps {
+            Some(timestamps) if idx + 1 < self.frame_paths.len() => {
+                let span = (timestamps[idx + 1] - timestamps[idx]) * self.video.get_tps() as f64;
+                (span.round() as f32).max(1.0)
+            }
+            _ => ref_time,
+        }
+    }
+
+    /// Read a frame as full-precision intensities
+    fn read_frame(&self, path: &Path) -> Result<Array3<f32>, SourceError> {
+        let plane = &self.video.state.plane;
+        let shape = (plane.h_usize(), plane.w_usize(), plane.c_usize());
+
+        let data = if self.raw {
+            let bytes = std::fs::read(path)?;
+            if bytes.len() != plane.volume() * 4 {
+                return Err(SourceError::BadParams(format!(
+                    "{} is not a {}x{}x{} float frame",
+                    path.display(),
+                    plane.w(),
+                    plane.h(),
+                    plane.c()
+                )));
+            }
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) * self.max_intensity)
+                .collect()
+        } else {
+            let image = image::open(path)?;
+            if image.width() != u32::from(plane.w()) || image.height() != u32::from(plane.h()) {
+                return Err(SourceError::BadParams(format!(
+                    "{} does not match the resolution of the first frame",
+                    path.display()
+                )));
+            }
+            self.image_intensities(image)
+        };
+
+        Ok(Array3::from_shape_vec(shape, data)?)
+    }
+
+    fn image_intensities(&self, image: DynamicImage) -> Vec<f32> {
+        match (self.depth, self.color_input) {
+            (ImageDepth::U8, true) => image
+                .into_rgb8()
+                .into_raw()
+                .into_iter()
+                .map(f32::from)
+                .collect(),
+            (ImageDepth::U8, false) => image
+                .into_luma8()
+                .into_raw()
+                .into_iter()
+                .map(f32::from)
+                .collect(),
+            (ImageDepth::U16, true) => image
+                .into_rgb16()
+                .into_raw()
+                .into_iter()
+                .map(f32::from)
+                .collect(),
+            (ImageDepth::U16, false) => image
+                .into_luma16()
+                .into_raw()
+                .into_iter()
+                .map(f32::from)
+                .collect(),
+            (ImageDepth::F32, true) => image
+                .into_rgb32f()
+                .into_raw()
+                .into_iter()
+                .map(|v| v * self.max_intensity)
+                .collect(),
+            (ImageDepth::F32, false) => image
+                .into_rgb32f()
+                .into_raw()
+                .chunks_exact(3)
+                .map(|rgb| (0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2]) * self.max_intensity)
+                .collect(),
+        }
+    }
+}
+
+/// List the frames in a directory with any of the given extensions, sorted by file name
+fn list_frames(input_dir: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>, SourceError> {
+    let mut frame_paths = std::fs::read_dir(input_dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .collect::<Vec<_>>();
+    frame_paths.sort();
+
+    if frame_paths.is_empty() {
+        return Err(SourceError::BadParams(format!(
+            "No frames found in {}",
+            input_dir.display()
+        )));
+    }
+    Ok(frame_paths)
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for ImageSequence<W> {
+    /// Read the next frame at full precision, and integrate it over the ticks it spans
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        let Some(path) = self.frame_paths.get(self.frame_idx) else {
+            return Err(SourceError::EndOfStream);
+        };
+        let matrix = self.read_frame(path)?;
+        let time_spanned = self.frame_span(self.frame_idx);
+        self.frame_idx += 1;
+
+        let scale = 255.0 / self.max_intensity;
+        self.input_frame = matrix.mapv(|v| (v * scale).clamp(0.0, 255.0) as u8);
+
+        self.video
+            .integrate_matrix_precise(matrix, self.max_intensity, time_spanned)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        Some(self.get_last_input_frame())
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        let video = self.get_video_ref();
+        let bits_per_sample = match self.depth {
+            ImageDepth::U8 => 8.0,
+            ImageDepth::U16 => 16.0,
+            ImageDepth::F32 => 32.0,
+        };
+        video.get_tps() as f64 / video.get_ref_time() as f64
+            * video.state.plane.volume() as f64
+            * bits_per_sample
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
+    for ImageSequence<W>
+{
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn consume_16_bit() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("adder_image_sequence_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        for i in 0..3_u16 {
+            let image: ImageBuffer<Luma<u16>, Vec<u16>> =
+                ImageBuffer::from_fn(4, 4, |x, _| Luma([x as u16 * 1000 + i * 10]));
+            image.save(dir.join(format!("frame_{i:03}.png")))?;
+        }
+        std::fs::write(dir.join("timestamps.txt"), "0.0\n0.01\n0.03\n")?;
+
+        let source = ImageSequence::new(dir.clone(), false).and_then(|source| {
+            source
+                .max_intensity(4095.0)?
+                .timestamps(&dir.join("timestamps.txt"))?
+                .time_parameters(25500, 255, 255 * 4, None)
+        });
+        let mut source: ImageSequence<io::Sink> = match source {
+            Ok(source) => source,
+            Err(e) => {
+                std::fs::remove_dir_all(&dir)?;
+                return Err(e.into());
+            }
+        };
+        assert_eq!(source.depth(), ImageDepth::U16);
+        assert_eq!(source.source_camera(), SourceCamera::FramedU16);
+        assert_eq!(source.frame_count(), 3);
+
+        // The frames are integrated over the ticks between their timestamps
+        assert_eq!(source.frame_span(0), 255.0);
+        assert_eq!(source.frame_span(1), 510.0);
+        assert_eq!(source.frame_span(2), 255.0);
+
+        for _ in 0..3 {
+            source.consume()?;
+        }
+        std::fs::remove_dir_all(&dir)?;
+
+        // The display frame is scaled from the 12-bit range to 8 bits
+        assert_eq!(source.get_last_input_frame()[[0, 3, 0]], 188);
+        assert!(matches!(source.consume(), Err(SourceError::EndOfStream)));
+        Ok(())
+    }
+}
@@ -7,8 +7,15 @@ use video_rs_adder_dep::Frame;
 
 #[cfg(feature = "open-cv")]
 use crate::transcoder::source::davis::Davis;
+use crate::transcoder::source::aedat4::Aedat4;
+use crate::transcoder::source::atis::Atis;
+use crate::transcoder::source::dvs_list::DvsList;
+use crate::transcoder::source::frame_stream::FrameStream;
 use crate::transcoder::source::framed::Framed;
+use crate::transcoder::source::image_sequence::ImageSequence;
 use crate::transcoder::source::prophesee::Prophesee;
+use crate::transcoder::source::retranscode::Retranscode;
+use crate::transcoder::source::synthetic::Synthetic;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 
@@ -16,19 +23,50 @@ use std::io::{BufWriter, Write};
 #[cfg(feature = "open-cv")]
 pub mod davis;
 
+/// Tools for transcoding from an AEDAT4 DVS recording to ADΔER, without OpenCV
+pub mod aedat4;
+
+/// Tools for transcoding from the exposure measurements of an ATIS recording to ADΔER
+pub mod atis;
+
+/// Shared tools for integrating DVS-style contrast events into ADΔER
+pub mod dvs;
+
+/// Tools for transcoding from a list of DVS events in a text or binary file to ADΔER
+pub mod dvs_list;
+
 /// Tools for transcoding from a framed video source to ADΔER
 pub mod framed;
 
+/// Tools for transcoding from frames pushed in memory by the caller, without a video file
+pub mod frame_stream;
+
+/// Tools for transcoding from a sequence of 8-bit, 16-bit or float image files to ADΔER
+pub mod image_sequence;
+
 /// Common functions and structs for all transcoder sources
 pub mod video;
 
 /// Tools for transcoding from a Prophesee video source to ADΔER
 pub mod prophesee;
 
+/// Tools for re-transcoding an existing ADΔER stream with new parameters
+pub mod retranscode;
+
+/// A synthetic scene source with exact ground-truth intensities, for testing without media files
+pub mod synthetic;
+
 #[enum_dispatch(Source<W>)]
 pub enum AdderSource<W: Write + 'static + std::marker::Send + std::marker::Sync> {
     Framed(Framed<W>),
     #[cfg(feature = "open-cv")]
     Davis(Davis<W>),
     Prophesee(Prophesee<W>),
+    DvsList(DvsList<W>),
+    Aedat4(Aedat4<W>),
+    Atis(Atis<W>),
+    ImageSequence(ImageSequence<W>),
+    FrameStream(FrameStream<W>),
+    Retranscode(Retranscode<W>),
+    Synthetic(Synthetic<W>),
 }
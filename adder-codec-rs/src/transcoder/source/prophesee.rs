@@ -1,302 +1,301 @@
-use crate::framer::scale_intensity::{FrameValue, SaeTime};
-use crate::transcoder::source::video::FramedViewMode::SAE;
-use crate::transcoder::source::video::{
-    integrate_for_px, Source, SourceError, Video, VideoBuilder,
+use crate::transcoder::source::dvs::{
+    dvs_video, ingest_integrated_events, DvsEvent, DvsIntegrator, DvsParams,
 };
-use crate::utils::cv::{clamp_u8, mid_clamp_u8};
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
 use crate::utils::viz::ShowFeatureMode;
 use adder_codec_core::codec::{EncoderOptions, EncoderType};
-use adder_codec_core::Mode::Continuous;
-use adder_codec_core::{
-    DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, SourceType, TimeMode,
-};
-use ndarray::Array3;
-use rayon::ThreadPool;
-use serde::{Deserialize, Serialize};
+use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
-use std::str::FromStr;
-use tokio::runtime::Runtime;
 use video_rs_adder_dep::Frame;
 
-/// The temporal granularity of the source (ticks per second)
-const PROPHESEE_SOURCE_TPS: u32 = 1000000;
-
 /// Attributes of a framed video -> ADΔER transcode
 pub struct Prophesee<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     pub(crate) video: Video<W>,
 
     input_reader: BufReader<File>,
 
-    running_t: u32,
+    decoder: EventDecoder,
 
-    t_subtract: u32,
+    /// The per-pixel log intensity model of the DVS events
+    pub integrator: DvsIntegrator,
 
-    /// The timestamp (in-camera) of the last DVS event integrated for each pixel
-    pub dvs_last_timestamps: Array3<u32>,
+    params: PropheseeParams,
 
-    /// The log-space last intensity value for each pixel
-    pub dvs_last_ln_val: Array3<f64>,
-
-    camera_theta: f64,
+    /// Whether the end of the input file has been reached
+    ended: bool,
 }
 
-/// A DVS-style contrast event
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct DvsEvent {
-    t: u32,
-    x: u16,
-    y: u16,
-    p: u8,
+/// Sensor and model parameters for a [`Prophesee`] transcode
+pub type PropheseeParams = DvsParams;
+
+/// The encoding of the events in a Prophesee recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropheseeFormat {
+    /// The legacy `.dat` format, with 8-byte records
+    Dat,
+
+    /// The EVT 2.0 `.raw` format, with 32-bit words
+    Evt2,
+
+    /// The EVT 3.0 `.raw` format, with vectorized 16-bit words
+    Evt3,
 }
 
-unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Sync for Prophesee<W> {}
+// EVT 2.0 word types, in the upper 4 bits of each word
+const EVT2_CD_OFF: u32 = 0x0;
+const EVT2_CD_ON: u32 = 0x1;
+const EVT2_TIME_HIGH: u32 = 0x8;
 
-impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
-    /// Create a new `Prophesee` transcoder
-    pub fn new(ref_time: u32, input_filename: String) -> Result<Self, Box<dyn Error>> {
-        let source = File::open(PathBuf::from(input_filename))?;
-        let mut input_reader = BufReader::new(source);
+// EVT 3.0 word types, in the upper 4 bits of each word
+const EVT3_ADDR_Y: u16 = 0x0;
+const EVT3_ADDR_X: u16 = 0x2;
+const EVT3_VECT_BASE_X: u16 = 0x3;
+const EVT3_VECT_12: u16 = 0x4;
+const EVT3_VECT_8: u16 = 0x5;
+const EVT3_TIME_LOW: u16 = 0x6;
+const EVT3_TIME_HIGH: u16 = 0x8;
 
-        // Parse header
-        let (_, _, _, size) = parse_header(&mut input_reader).unwrap();
+/// Decodes the DVS events in the data section of a Prophesee recording, keeping track of the
+/// state that the EVT 2.0 and EVT 3.0 encodings carry between words
+pub(crate) struct EventDecoder {
+    format: PropheseeFormat,
 
-        let plane = PlaneSize::new(size.1 as u16, size.0 as u16, 1)?;
+    /// The upper bits of the timestamp, from the last time high word. 28 bits for EVT 2.0, or
+    /// 12 bits for EVT 3.0.
+    time_high: u64,
 
-        let mut video = Video::new(plane, Continuous, None)?
-            .chunk_rows(1)
-            // Override the tps to assume the source has a temporal granularity of 1000000/second
-            // The `ref_time` in this case scales up the temporal granularity of the source.
-            // For example, with ref_time = 20, a timestamp of 12 in the source becomes 240
-            // ADDER ticks
-            .time_parameters(
-                ref_time * PROPHESEE_SOURCE_TPS,
-                ref_time,
-                ref_time * 2,
-                Some(TimeMode::AbsoluteT),
-            )?;
-
-        let start_intensities = vec![128_u8; video.state.plane.volume()];
-        video.state.running_intensities = Array3::from_shape_vec(
-            (plane.h().into(), plane.w().into(), plane.c().into()),
-            start_intensities,
-        )?;
-        video.display_frame_features = video.state.running_intensities.clone();
+    /// EVT 3.0: the lower 12 bits of the timestamp
+    time_low: u64,
 
-        let timestamps = vec![2_u32; video.state.plane.volume()];
+    /// EVT 3.0: the number of times the 24-bit timestamp has wrapped around
+    time_wraps: u64,
 
-        let dvs_last_timestamps: Array3<u32> = Array3::from_shape_vec(
-            (plane.h().into(), plane.w().into(), plane.c().into()),
-            timestamps,
-        )?;
+    /// EVT 3.0: the y-coordinate of the following events
+    y: u16,
 
-        let plane = &video.state.plane;
+    /// EVT 3.0: the x-coordinate of the next vector event
+    base_x: u16,
 
-        let start_vals = vec![(128.0_f64 / 255.0_f64).ln_1p(); video.state.plane.volume()];
+    /// EVT 3.0: the polarity of the following vector events
+    polarity: u8,
 
-        let dvs_last_ln_val: Array3<f64> = Array3::from_shape_vec(
-            (plane.h() as usize, plane.w() as usize, plane.c() as usize),
-            start_vals,
-        )?;
+    /// Events decoded from a vector word, but not yet returned
+    pending: VecDeque<DvsEvent>,
 
-        let prophesee_source = Prophesee {
-            video,
-            input_reader,
-            running_t: 0,
-            t_subtract: 0,
-            dvs_last_timestamps,
-            dvs_last_ln_val,
-            camera_theta: 0.02, // A fixed assumption
-        };
+    /// The timestamp that returned timestamps are relative to. `None` until the first event when
+    /// rebasing to the start of the recording.
+    t_start: Option<u64>,
+}
 
-        Ok(prophesee_source)
+impl EventDecoder {
+    pub(crate) fn new(format: PropheseeFormat) -> Self {
+        Self {
+            format,
+            time_high: 0,
+            time_low: 0,
+            time_wraps: 0,
+            y: 0,
+            base_x: 0,
+            polarity: 0,
+            pending: VecDeque::new(),
+            t_start: Some(0),
+        }
     }
-}
 
-impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for Prophesee<W> {
-    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
-        if self.running_t == 0 {
-            self.video.integrate_matrix(
-                self.video.state.running_intensities.clone(),
-                self.video.state.params.ref_time as f32,
-            )?;
-            let first_events: Vec<Event> = self
-                .video
-                .integrate_matrix(
-                    self.video.state.running_intensities.clone(),
-                    self.video.state.params.ref_time as f32,
-                )?
-                .into_iter()
-                .flatten()
-                .collect();
-            assert_eq!(first_events.len(), self.video.state.plane.volume());
-            self.running_t = 2;
+    /// Like [`EventDecoder::new`], but EVT 2.0 and EVT 3.0 timestamps are relative to the first
+    /// event, so that long recordings still fit in a `u32`
+    pub(crate) fn rebased(format: PropheseeFormat) -> Self {
+        Self {
+            t_start: None,
+            ..Self::new(format)
         }
+    }
 
-        // TODO hardcoded: scale the view interval to be 60 FPS GUI display
-        let view_interval = PROPHESEE_SOURCE_TPS / 60;
+    /// Narrow an absolute timestamp to a `u32`, relative to the start time
+    fn timestamp(&mut self, t: u64) -> io::Result<u32> {
+        let t_start = *self.t_start.get_or_insert(t);
+        u32::try_from(t.saturating_sub(t_start)).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("timestamp {t} is too far after the start time {t_start}"),
+            )
+        })
+    }
 
-        // Read events from the source file until we find a timestamp that exceeds our `running_t`
-        // by at least `view_interval`
-        let mut dvs_events: Vec<DvsEvent> = Vec::new();
-        let mut dvs_event;
-        let start_running_t = self.running_t;
+    /// Read words until the next event is decoded
+    pub(crate) fn next_event(&mut self, reader: &mut impl Read) -> io::Result<DvsEvent> {
         loop {
-            // TODO: integrate to fill in the rest of time once the eof is reached
-
-            dvs_event = match decode_event(&mut self.input_reader) {
-                Ok(mut dvs_event) => {
-                    // if self.running_t == 2 && dvs_events.is_empty() {
-                    //     self.t_subtract = dvs_event.t;
-                    //     eprintln!("t_subtract: {}", self.t_subtract);
-                    // }
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            match self.format {
+                PropheseeFormat::Dat => return decode_event(reader),
+                PropheseeFormat::Evt2 => self.decode_evt2_word(reader)?,
+                PropheseeFormat::Evt3 => self.decode_evt3_word(reader)?,
+            }
+        }
+    }
 
-                    dvs_event.t -= self.t_subtract;
+    fn decode_evt2_word(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        let mut buffer = [0; 4];
+        reader.read_exact(&mut buffer)?;
+        let word = u32::from_le_bytes(buffer);
+
+        match word >> 28 {
+            kind @ (EVT2_CD_OFF | EVT2_CD_ON) => {
+                let t = (self.time_high << 6) | u64::from((word >> 22) & 0x3F);
+                let t = self.timestamp(t)?;
+                self.pending.push_back(DvsEvent {
+                    t,
+                    x: ((word >> 11) & 0x7FF) as u16,
+                    y: (word & 0x7FF) as u16,
+                    p: kind as u8,
+                });
+            }
+            EVT2_TIME_HIGH => self.time_high = u64::from(word & 0x0FFF_FFFF),
+            _ => {} // Ignore triggers and other word types
+        }
+        Ok(())
+    }
 
-                    if dvs_event.t > self.running_t {
-                        self.running_t = dvs_event.t;
-                    }
-                    dvs_event
-                }
-                Err(e) => {
-                    dbg!("End of input file");
-                    end_events(self);
-                    return Err(e.into());
+    fn decode_evt3_word(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        let mut buffer = [0; 2];
+        reader.read_exact(&mut buffer)?;
+        let word = u16::from_le_bytes(buffer);
+        let payload = word & 0x0FFF;
+
+        match word >> 12 {
+            EVT3_ADDR_Y => self.y = payload & 0x7FF,
+            EVT3_ADDR_X => self.push_event(payload & 0x7FF, (payload >> 11) as u8)?,
+            EVT3_VECT_BASE_X => {
+                self.base_x = payload & 0x7FF;
+                self.polarity = (payload >> 11) as u8;
+            }
+            EVT3_VECT_12 => self.push_vector(payload, 12)?,
+            EVT3_VECT_8 => self.push_vector(payload & 0xFF, 8)?,
+            EVT3_TIME_LOW => self.time_low = u64::from(payload),
+            EVT3_TIME_HIGH => {
+                let time_high = u64::from(payload);
+                if time_high < self.time_high {
+                    self.time_wraps += 1;
                 }
-            };
-            dvs_events.push(dvs_event);
-            if dvs_events.last().unwrap().t > start_running_t + view_interval {
-                break;
+                self.time_high = time_high;
             }
+            _ => {} // Ignore triggers, continued words, and other word types
         }
+        Ok(())
+    }
 
-        let mut events: Vec<Event> = Vec::new();
-        let crf_parameters = *self.video.encoder.options.crf.get_parameters();
-
-        // For every dvs event in our queue, integrate the previously seen intensity for all the
-        // time between the pixel's last input and the current event
-        for dvs_event in dvs_events {
-            let x = dvs_event.x as usize;
-            let y = dvs_event.y as usize;
-            let p = dvs_event.p as usize;
-            let t = dvs_event.t;
-
-            // Get the last timestamp for this pixel
-            let last_t = self.dvs_last_timestamps[[y, x, 0]];
-
-            if t < last_t {
-                // dbg!("skipping event");
-                continue;
-            }
+    /// EVT 3.0: queue an event at the given x-coordinate and the current y-coordinate and time
+    fn push_event(&mut self, x: u16, p: u8) -> io::Result<()> {
+        let t = (self.time_wraps << 24) | (self.time_high << 12) | self.time_low;
+        let t = self.timestamp(t)?;
+        self.pending.push_back(DvsEvent { t, x, y: self.y, p });
+        Ok(())
+    }
 
-            // Get the last ln intensity for this pixel
-            let mut last_ln_val = self.dvs_last_ln_val[[y, x, 0]];
+    /// EVT 3.0: queue an event for each set bit of a vector word, starting at the base x-coordinate
+    fn push_vector(&mut self, mask: u16, bits: u16) -> io::Result<()> {
+        for i in 0..bits {
+            if mask & (1 << i) != 0 {
+                self.push_event(self.base_x + i, self.polarity)?;
+            }
+        }
+        self.base_x += bits;
+        Ok(())
+    }
+}
 
-            let px = &mut self.video.event_pixel_trees[[y, x, 0]];
+unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Sync for Prophesee<W> {}
 
-            if t > last_t + 1 {
-                // Convert the ln intensity to a linear intensity
-                let mut last_val = (last_ln_val.exp() - 1.0) * 255.0;
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
+    /// Create a new `Prophesee` transcoder with the default [`PropheseeParams`]
+    pub fn new(ref_time: u32, input_filename: String) -> Result<Self, Box<dyn Error>> {
+        Self::new_with_params(ref_time, input_filename, PropheseeParams::default())
+    }
 
-                mid_clamp_u8(&mut last_val, &mut last_ln_val);
+    /// Create a new `Prophesee` transcoder with the given sensor and model parameters
+    pub fn new_with_params(
+        ref_time: u32,
+        input_filename: String,
+        params: PropheseeParams,
+    ) -> Result<Self, Box<dyn Error>> {
+        let source = File::open(PathBuf::from(input_filename))?;
+        let mut input_reader = BufReader::new(source);
 
-                // Integrate the last intensity for this pixel over the time since the last event
-                let time_spanned = (t - last_t - 1) * self.video.state.params.ref_time;
-                let intensity_to_integrate = last_val * (t - last_t - 1) as f64;
+        // Parse header
+        let (format, size) = parse_header(&mut input_reader)?;
 
-                let mut base_val = 0;
-                let _ = integrate_for_px(
-                    px,
-                    &mut base_val,
-                    last_val as u8,
-                    intensity_to_integrate as f32,
-                    time_spanned as f32,
-                    &mut events,
-                    &self.video.state.params,
-                    &crf_parameters,
-                );
-            }
+        let plane = PlaneSize::new(size.1 as u16, size.0 as u16, 1)?;
 
-            // Get the new ln intensity
-            let mut new_ln_val = match p {
-                0 => last_ln_val - self.camera_theta,
-                1 => last_ln_val + self.camera_theta,
-                _ => panic!("Invalid polarity"),
-            };
+        let mut video = dvs_video(plane, ref_time, &params)?;
+        let integrator = DvsIntegrator::new(&mut video, &params);
 
-            // Update the last intensity for this pixel
-            self.dvs_last_ln_val[[y, x, 0]] = new_ln_val;
+        let prophesee_source = Prophesee {
+            video,
+            input_reader,
+            decoder: EventDecoder::rebased(format),
+            integrator,
+            params,
+            ended: false,
+        };
 
-            // Update the last timestamp for this pixel
-            self.dvs_last_timestamps[[y, x, 0]] = t;
+        Ok(prophesee_source)
+    }
 
-            if t > last_t {
-                let mut new_val = (new_ln_val.exp() - 1.0) * 255.0;
+    /// The encoding of the events in the source file
+    pub fn format(&self) -> PropheseeFormat {
+        self.decoder.format
+    }
 
-                mid_clamp_u8(&mut new_val, &mut new_ln_val);
+    /// The sensor and model parameters of the transcode
+    pub fn params(&self) -> &PropheseeParams {
+        &self.params
+    }
+}
 
-                // Update the last intensity for this pixel
-                self.dvs_last_ln_val[[y, x, 0]] = new_ln_val;
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for Prophesee<W> {
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if self.ended {
+            return Err(SourceError::EndOfStream);
+        }
 
-                // Integrate 1 source time unit of the new intensity
-                let time_spanned = self.video.state.params.ref_time;
-                let intensity_to_integrate = new_val;
+        self.integrator.start(&mut self.video)?;
 
-                let mut base_val = 0;
-                let _ = integrate_for_px(
-                    px,
-                    &mut base_val,
-                    new_val as u8,
-                    intensity_to_integrate as f32,
-                    time_spanned as f32,
-                    &mut events,
-                    &self.video.state.params,
-                    &crf_parameters,
-                );
-            }
+        let view_interval = self.params.view_interval;
 
-            // Update the running intensity for this pixel
-            if let Some(event) = px.arena[0].best_event {
-                self.video.state.running_intensities[[y, x, 0]] = u8::get_frame_value(
-                    &event.into(),
-                    SourceType::U8,
-                    self.video.state.params.ref_time as f64,
-                    32.0,
-                    self.video.state.params.delta_t_max,
-                    self.video.instantaneous_view_mode,
-                    if self.video.instantaneous_view_mode == SAE {
-                        Some(SaeTime {
-                            running_t: px.running_t as DeltaT,
-                            last_fired_t: px.last_fired_t as DeltaT,
-                        })
-                    } else {
-                        None
-                    },
-                );
-                self.video.display_frame_features[[y, x, 0]] =
-                    self.video.state.running_intensities[[y, x, 0]];
+        // Read events from the source file until we find a timestamp that exceeds our `running_t`
+        // by at least `view_interval`, or reach the end of the file
+        let mut dvs_events: Vec<DvsEvent> = Vec::new();
+        let start_running_t = self.integrator.running_t();
+        loop {
+            let dvs_event = match self.decoder.next_event(&mut self.input_reader) {
+                Ok(dvs_event) => dvs_event,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.ended = true;
+                    break;
+                }
+                Err(e) => return Err(e.into()),
             };
+            dvs_events.push(dvs_event);
+            if dvs_events.last().unwrap().t > start_running_t + view_interval {
+                break;
+            }
         }
 
-        if self.video.state.feature_detection {
-            self.video.display_frame_features = self.video.state.running_intensities.clone();
-        }
-
-        // It's expected that the function will spatially parallelize the integrations. With sparse
-        // data, though, this could be pretty wasteful. For now, just wrap the vec in another vec.
-        let events_nested: Vec<Vec<Event>> = vec![events];
-
-        self.video.handle_features(&events_nested)?;
-
-        for events in &events_nested {
-            for event in events {
-                self.video.encoder.ingest_event(*event)?;
-            }
+        let mut events: Vec<Event> = Vec::new();
+        self.integrator
+            .integrate(&mut self.video, &dvs_events, &mut events);
+        if self.ended {
+            self.integrator
+                .end(&mut self.video, self.params.end_t, &mut events);
         }
 
-        Ok(events_nested)
+        ingest_integrated_events(&mut self.video, events)
     }
 
     fn crf(&mut self, crf: u8) {
@@ -325,54 +324,17 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for P
     }
 }
 
-fn end_events<W: Write + std::marker::Send + std::marker::Sync + 'static>(
-    prophesee: &mut Prophesee<W>,
-) {
-    let mut events: Vec<Event> = Vec::new();
-    let crf_parameters = *prophesee.video.encoder.options.crf.get_parameters();
-
-    for y in 0..prophesee.video.state.plane.h_usize() {
-        for x in 0..prophesee.video.state.plane.w_usize() {
-            let px = &mut prophesee.video.event_pixel_trees[[y, x, 0]];
-            let mut base_val = 0;
-
-            // Get the last ln intensity for this pixel
-            let last_ln_val = prophesee.dvs_last_ln_val[[y, x, 0]];
-
-            // Convert the ln intensity to a linear intensity
-            let last_val = (last_ln_val.exp() - 1.0) * 255.0;
-
-            assert!(prophesee.running_t - prophesee.dvs_last_timestamps[[y, x, 0]] > 0);
-
-            // Integrate the last intensity for this pixel over the time since the last event
-            let time_spanned = (prophesee.running_t - prophesee.dvs_last_timestamps[[y, x, 0]])
-                * prophesee.video.state.params.ref_time;
-            let intensity_to_integrate = last_val * time_spanned as f64;
-
-            let _ = integrate_for_px(
-                px,
-                &mut base_val,
-                last_val as u8,
-                intensity_to_integrate as f32,
-                time_spanned as f32,
-                &mut events,
-                &prophesee.video.state.params,
-                &crf_parameters,
-            );
-        }
-    }
-
-    for event in &events {
-        prophesee.video.encoder.ingest_event(*event).unwrap();
-    }
-}
-
-fn parse_header(file: &mut BufReader<File>) -> io::Result<(u64, u8, u8, (u32, u32))> {
+/// Parse the header of a Prophesee recording, and leave the reader at the start of the event data.
+/// Returns the encoding of the events and the (height, width) of the sensor.
+pub(crate) fn parse_header(
+    file: &mut BufReader<File>,
+) -> io::Result<(PropheseeFormat, (u32, u32))> {
     file.seek(SeekFrom::Start(0))?; // Seek to the beginning of the file
     let mut bod = 0;
     let mut end_of_header = false;
     let mut num_comment_line = 0;
     let mut size = [None, None];
+    let mut format = None;
 
     // Parse header
     while !end_of_header {
@@ -382,18 +344,34 @@ fn parse_header(file: &mut BufReader<File>) -> io::Result<(u64, u8, u8, (u32, u3
         if line.is_empty() || line[0] != b'%' {
             end_of_header = true;
         } else {
-            let words: Vec<&[u8]> = line.split(|&x| x == b' ' || x == b'\t').collect(); // Use &[u8] instead of &str
-
-            if words.len() > 1 {
-                match words[1] {
-                    b"Height" => {
-                        size[0] = line_to_hw(words);
+            let line = String::from_utf8_lossy(&line);
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match (words.get(1).copied(), words.get(2).copied()) {
+                (Some("Height"), Some(value)) => size[0] = value.parse().ok(),
+                (Some("Width"), Some(value)) => size[1] = value.parse().ok(),
+                // e.g., "% evt 3.0"
+                (Some("evt"), Some(version)) => format = Some(parse_evt_format(version)?),
+                // e.g., "% format EVT3;height=720;width=1280"
+                (Some("format"), Some(value)) => {
+                    let mut fields = value.split(';');
+                    format = Some(parse_evt_format(fields.next().unwrap_or_default())?);
+                    for field in fields {
+                        match field.split_once('=') {
+                            Some(("height", value)) => size[0] = value.parse().ok(),
+                            Some(("width", value)) => size[1] = value.parse().ok(),
+                            _ => {}
+                        }
                     }
-                    b"Width" => {
-                        size[1] = line_to_hw(words);
+                }
+                // e.g., "% geometry 1280x720"
+                (Some("geometry"), Some(value)) => {
+                    if let Some((width, height)) = value.split_once('x') {
+                        size[0] = height.parse().ok();
+                        size[1] = width.parse().ok();
                     }
-                    _ => {}
                 }
+                _ => {}
             }
             num_comment_line += 1;
         }
@@ -401,42 +379,41 @@ fn parse_header(file: &mut BufReader<File>) -> io::Result<(u64, u8, u8, (u32, u3
 
     // Parse data
     file.seek(SeekFrom::Start(bod))?; // Seek back to the position after the header
-    let (ev_type, ev_size) = if num_comment_line > 0 {
-        // Read event type and size
-        let mut buf = [0; 2]; // Adjust the buffer size based on your data size
-        file.read_exact(&mut buf)?;
-        let ev_type = buf[0];
-        let ev_size = buf[1];
-        if ev_size != 8 || (ev_type != 0 && ev_type != 12) {
-            panic!("Invalid Prophesee event size");
+    let format = match format {
+        Some(format) => format,
+        None => {
+            if num_comment_line > 0 {
+                // Read event type and size
+                let mut buf = [0; 2];
+                file.read_exact(&mut buf)?;
+                let ev_type = buf[0];
+                let ev_size = buf[1];
+                if ev_size != 8 || (ev_type != 0 && ev_type != 12) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Invalid Prophesee event size",
+                    ));
+                }
+            }
+            PropheseeFormat::Dat
         }
-
-        (ev_type, ev_size)
-    } else {
-        (0, 0) // Placeholder values, replace with actual logic
     };
-    bod = file.seek(SeekFrom::Current(0))?;
-    Ok((
-        bod,
-        ev_type,
-        ev_size,
-        (size[0].unwrap_or(70), size[1].unwrap_or(100)),
-    ))
+    Ok((format, (size[0].unwrap_or(70), size[1].unwrap_or(100))))
 }
 
-fn line_to_hw(words: Vec<&[u8]>) -> Option<u32> {
-    let word = words.get(2).unwrap();
-    let mut new_word = *word;
-    if *word.last().unwrap() == '\n' as u8 {
-        // Remove the trailing newline
-        new_word = &word[..word.len() - 1];
+/// Parse the event format named in a `.raw` header
+fn parse_evt_format(name: &str) -> io::Result<PropheseeFormat> {
+    match name {
+        "2.0" | "EVT2" => Ok(PropheseeFormat::Evt2),
+        "3.0" | "EVT3" => Ok(PropheseeFormat::Evt3),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported Prophesee event format: {name}"),
+        )),
     }
-    std::str::from_utf8(new_word)
-        .ok()
-        .and_then(|s| s.parse().ok())
 }
 
-fn decode_event(reader: &mut BufReader<File>) -> io::Result<DvsEvent> {
+fn decode_event(reader: &mut impl Read) -> io::Result<DvsEvent> {
     // Read one record
     let mut buffer = [0; 8]; // Adjust this size to match your record size
     reader.read_exact(&mut buffer)?;
@@ -527,3 +504,122 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W>
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn decode_all(format: PropheseeFormat, bytes: Vec<u8>) -> Vec<(u32, u16, u16, u8)> {
+        let mut decoder = EventDecoder::new(format);
+        let mut reader = Cursor::new(bytes);
+        let mut events = Vec::new();
+        while let Ok(event) = decoder.next_event(&mut reader) {
+            events.push((event.t, event.x, event.y, event.p));
+        }
+        events
+    }
+
+    #[test]
+    fn evt2() {
+        let words: [u32; 4] = [
+            (EVT2_TIME_HIGH << 28) | 5,
+            (EVT2_CD_ON << 28) | (3 << 22) | (10 << 11) | 20,
+            (0xA << 28) | 1, // External trigger
+            (EVT2_CD_OFF << 28) | (4 << 22) | (11 << 11) | 21,
+        ];
+        let bytes = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert_eq!(
+            decode_all(PropheseeFormat::Evt2, bytes),
+            vec![((5 << 6) | 3, 10, 20, 1), ((5 << 6) | 4, 11, 21, 0)]
+        );
+    }
+
+    #[test]
+    fn evt3() {
+        let words: [u16; 11] = [
+            (EVT3_TIME_HIGH << 12) | 1,
+            (EVT3_TIME_LOW << 12) | 2,
+            (EVT3_ADDR_Y << 12) | 7,
+            (EVT3_ADDR_X << 12) | (1 << 11) | 3,
+            (EVT3_VECT_BASE_X << 12) | 100,
+            (EVT3_VECT_12 << 12) | 0b1000_0000_0101,
+            (EVT3_VECT_8 << 12) | 0b1,
+            // The 24-bit timestamp wraps around
+            (EVT3_TIME_HIGH << 12) | 4095,
+            EVT3_TIME_HIGH << 12,
+            EVT3_TIME_LOW << 12,
+            (EVT3_ADDR_X << 12) | 5,
+        ];
+        let bytes = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let t = (1 << 12) | 2;
+        assert_eq!(
+            decode_all(PropheseeFormat::Evt3, bytes),
+            vec![
+                (t, 3, 7, 1),
+                (t, 100, 7, 0),
+                (t, 102, 7, 0),
+                (t, 111, 7, 0),
+                (t, 112, 7, 0),
+                (1 << 24, 5, 7, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn evt2_rebased() {
+        // More than 2^32 microseconds into the recording
+        let words: [u32; 3] = [
+            (EVT2_TIME_HIGH << 28) | (1 << 27),
+            (EVT2_CD_ON << 28) | (3 << 22) | (10 << 11) | 20,
+            (EVT2_CD_OFF << 28) | (4 << 22) | (11 << 11) | 21,
+        ];
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+
+        let mut decoder = EventDecoder::rebased(PropheseeFormat::Evt2);
+        let mut reader = Cursor::new(bytes.clone());
+        let mut events = Vec::new();
+        while let Ok(event) = decoder.next_event(&mut reader) {
+            events.push((event.t, event.x, event.y, event.p));
+        }
+        assert_eq!(events, vec![(0, 10, 20, 1), (1, 11, 21, 0)]);
+
+        // Without rebasing, the timestamp doesn't fit in a u32
+        let mut decoder = EventDecoder::new(PropheseeFormat::Evt2);
+        let err = decoder.next_event(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn end_of_stream() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("adder_prophesee_{}.raw", std::process::id()));
+        let mut bytes = b"% format EVT2;height=4;width=4\n% end\n".to_vec();
+        let words: [u32; 3] = [
+            EVT2_TIME_HIGH << 28,
+            (EVT2_CD_ON << 28) | (10 << 22) | (1 << 11) | 1,
+            (EVT2_CD_OFF << 28) | (20 << 22) | (2 << 11) | 2,
+        ];
+        bytes.extend(words.iter().flat_map(|word| word.to_le_bytes()));
+        std::fs::write(&path, bytes)?;
+
+        let params = PropheseeParams::default().start_intensity(100).end_t(1000);
+        let source = Prophesee::new_with_params(10, path.to_str().unwrap().to_string(), params);
+        std::fs::remove_file(&path)?;
+        let mut source: Prophesee<io::Sink> = source?;
+        assert_eq!(source.format(), PropheseeFormat::Evt2);
+        assert_eq!(source.video.state.running_intensities[[0, 0, 0]], 100);
+
+        // The whole file fits in one view interval, so the final intensities are integrated
+        // right away
+        let events = source.consume()?;
+        assert!(!events[0].is_empty());
+        assert_eq!(source.integrator.running_t(), 1000);
+        assert!(source
+            .integrator
+            .dvs_last_timestamps
+            .iter()
+            .all(|t| *t == 1000));
+        assert!(matches!(source.consume(), Err(SourceError::EndOfStream)));
+        Ok(())
+    }
+}
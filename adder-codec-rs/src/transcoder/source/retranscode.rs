@@ -0,0 +1,467 @@
+use crate::framer::scale_intensity::{event_to_intensity, FrameValue, SaeTime};
+use crate::transcoder::source::dvs::ingest_integrated_events;
+use crate::transcoder::source::video::FramedViewMode::SAE;
+use crate::transcoder::source::video::{
+    integrate_for_px, Source, SourceError, Video, VideoBuilder,
+};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::decoder::Decoder;
+use adder_codec_core::codec::{CodecError, CodecMetadata, EncoderOptions, EncoderType};
+use adder_codec_core::Mode::{Continuous, FramePerfect};
+use adder_codec_core::{
+    open_file_decoder, AbsoluteT, DeltaT, Event, PixelMultiMode, SourceCamera, SourceType,
+    TimeMode, D_EMPTY,
+};
+use bitstream_io::{BigEndian, BitReader};
+use ndarray::Array3;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use video_rs_adder_dep::Frame;
+
+/// Attributes of an ADΔER -> ADΔER transcode.
+///
+/// The events of an existing stream are decoded, and the intensity each one represents is
+/// re-integrated with the parameters of this source's [`Video`]. For example, a lossless stream
+/// can be turned into a lower-rate stream with a higher CRF or `delta_t_max`, without the
+/// original video.
+pub struct Retranscode<W: Write + std::marker::Send + std::marker::Sync + 'static> {
+    pub(crate) video: Video<W>,
+
+    input_stream: Decoder<BufReader<File>>,
+
+    bitreader: BitReader<BufReader<File>, BigEndian>,
+
+    /// The metadata of the input stream
+    input_meta: CodecMetadata,
+
+    /// The timestamp (in input ticks) of the last event decoded for each pixel
+    last_timestamps: Array3<AbsoluteT>,
+
+    /// The intensity (per input tick) of the last non-empty event decoded for each pixel
+    last_intensities: Array3<f64>,
+
+    /// The first event past the current interval, held for the next call to `consume`
+    pending_event: Option<Event>,
+
+    /// The input time (in input ticks) up to which events have been consumed
+    running_t: AbsoluteT,
+
+    /// Whether the end of the input stream has been reached
+    ended: bool,
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Retranscode<W> {
+    /// Create a new `Retranscode` source from an ADΔER file. The video starts with the time
+    /// parameters of the input stream.
+    pub fn new(input_filename: &str) -> Result<Self, SourceError> {
+        let (input_stream, bitreader) = open_file_decoder(input_filename)?;
+        let input_meta = *input_stream.meta();
+
+        if input_meta.time_mode == TimeMode::Mixed {
+            return Err(SourceError::BadParams(
+                "Mixed time mode streams are not supported".to_string(),
+            ));
+        }
+
+        let plane = input_meta.plane;
+        let mode = match input_meta.source_camera {
+            SourceCamera::FramedU8
+            | SourceCamera::FramedU16
+            | SourceCamera::FramedU32
+            | SourceCamera::FramedU64
+            | SourceCamera::FramedF32
+            | SourceCamera::FramedF64 => FramePerfect,
+            SourceCamera::Dvs
+            | SourceCamera::DavisU8
+            | SourceCamera::Atis
+            | SourceCamera::Asint => Continuous,
+        };
+        let video = Video::new(plane, mode, None)?.time_parameters(
+            input_meta.tps,
+            input_meta.ref_interval,
+            input_meta.delta_t_max,
+            Some(input_meta.time_mode),
+        )?;
+
+        let shape = (plane.h_usize(), plane.w_usize(), plane.c_usize());
+        Ok(Self {
+            video,
+            input_stream,
+            bitreader,
+            input_meta,
+            last_timestamps: Array3::zeros(shape),
+            last_intensities: Array3::zeros(shape),
+            pending_event: None,
+            running_t: 0,
+            ended: false,
+        })
+    }
+
+    /// Get the metadata of the input stream
+    pub fn input_meta(&self) -> &CodecMetadata {
+        &self.input_meta
+    }
+
+    /// Get the source camera of the input stream, to carry over to the output stream
+    pub fn source_camera(&self) -> SourceCamera {
+        self.input_meta.source_camera
+    }
+
+    /// Whether the timestamps of the input events are absolute, rather than relative to each
+    /// pixel's previous event
+    fn absolute_input(&self) -> bool {
+        self.input_meta.codec_version >= 2 && self.input_meta.time_mode == TimeMode::AbsoluteT
+    }
+
+    /// Convert a span of input ticks to output ticks
+    fn output_ticks(&self, input_ticks: DeltaT) -> f32 {
+        (f64::from(input_ticks) * f64::from(self.video.state.tps) / f64::from(self.input_meta.tps))
+            as f32
+    }
+
+    /// The scale from an input intensity over one input `ref_interval` to the 8-bit range
+    fn frame_scale(&self) -> f64 {
+        let max_intensity = match self.input_stream.get_source_type() {
+            SourceType::U8 => f64::from(u8::MAX),
+            SourceType::U16 => f64::from(u16::MAX),
+            SourceType::U32 => f64::from(u32::MAX),
+            SourceType::U64 => u64::MAX as f64,
+            SourceType::F32 | SourceType::F64 => 1.0,
+        };
+        f64::from(self.input_meta.ref_interval) * 255.0 / max_intensity
+    }
+
+    /// Get the absolute timestamp (in input ticks) of a decoded event, or `None` if it's outside
+    /// the plane
+    fn event_t(&self, event: &Event) -> Option<AbsoluteT> {
+        let last_t = self.last_timestamps.get([
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        ])?;
+        Some(if self.absolute_input() {
+            event.t
+        } else {
+            last_t.saturating_add(event.t)
+        })
+    }
+
+    /// Re-integrate a decoded event, pushing the resulting ADΔER events onto `events`
+    fn integrate_event(&mut self, event: Event, events: &mut Vec<Event>) {
+        let Some(t) = self.event_t(&event) else {
+            return;
+        };
+        let (y, x, c) = (
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        );
+        let last_t = self.last_timestamps[[y, x, c]];
+        if t <= last_t {
+            return;
+        }
+        self.running_t = self.running_t.max(t);
+
+        // An empty event continues the pixel's last intensity
+        let intensity = if event.d == D_EMPTY {
+            self.last_intensities[[y, x, c]]
+        } else {
+            event_to_intensity(&Event {
+                t: t - last_t,
+                ..event
+            })
+        };
+        self.last_intensities[[y, x, c]] = intensity;
+        self.last_timestamps[[y, x, c]] = t;
+
+        self.integrate_px(y, x, c, intensity, t - last_t, events);
+    }
+
+    /// Integrate an intensity (per input tick) for a span of input ticks at one pixel
+    fn integrate_px(
+        &mut self,
+        y: usize,
+        x: usize,
+        c: usize,
+        intensity: f64,
+        input_ticks: DeltaT,
+        events: &mut Vec<Event>,
+    ) {
+        let crf_parameters = *self.video.encoder.options.crf.get_parameters();
+        let frame_val = (intensity * self.frame_scale()).clamp(0.0, 255.0) as u8;
+        let time_spanned = self.output_ticks(input_ticks);
+        let video = &mut self.video;
+        let px = &mut video.event_pixel_trees[[y, x, c]];
+
+        let mut base_val = 0;
+        let _ = integrate_for_px(
+            px,
+            &mut base_val,
+            frame_val,
+            (intensity * f64::from(input_ticks)) as f32,
+            time_spanned,
+            events,
+            &video.state.params,
+            &crf_parameters,
+        );
+
+        // Update the running intensity for this pixel
+        if let Some(event) = px.arena[0].best_event {
+            video.state.running_intensities[[y, x, c]] = u8::get_frame_value(
+                &event.into(),
+                SourceType::U8,
+                video.state.params.ref_time as f64,
+                32.0,
+                video.state.params.delta_t_max,
+                video.instantaneous_view_mode,
+                if video.instantaneous_view_mode == SAE {
+                    Some(SaeTime {
+                        running_t: px.running_t as DeltaT,
+                        last_fired_t: px.last_fired_t as DeltaT,
+                    })
+                } else {
+                    None
+                },
+            );
+            video.display_frame_features[[y, x, c]] = video.state.running_intensities[[y, x, c]];
+        };
+    }
+
+    /// Integrate the last intensity of every pixel up to the latest timestamp of the input
+    fn end(&mut self, events: &mut Vec<Event>) {
+        let end_t = self.running_t;
+        let shape = self.last_timestamps.dim();
+        for y in 0..shape.0 {
+            for x in 0..shape.1 {
+                for c in 0..shape.2 {
+                    let last_t = self.last_timestamps[[y, x, c]];
+                    if end_t <= last_t {
+                        continue;
+                    }
+                    let intensity = self.last_intensities[[y, x, c]];
+                    self.integrate_px(y, x, c, intensity, end_t - last_t, events);
+                    self.last_timestamps[[y, x, c]] = end_t;
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for Retranscode<W> {
+    /// Decode the events of the next `ref_interval` of the input stream, and re-integrate them
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if self.ended {
+            return Err(SourceError::EndOfStream);
+        }
+
+        let interval_end = self.running_t.saturating_add(self.input_meta.ref_interval);
+        let mut events = Vec::new();
+
+        if let Some(event) = self.pending_event.take() {
+            self.integrate_event(event, &mut events);
+        }
+
+        loop {
+            let event = match self.input_stream.digest_event(&mut self.bitreader) {
+                Ok(event) => event,
+                Err(CodecError::Eof | CodecError::Deserialize) => {
+                    self.ended = true;
+                    break;
+                }
+                Err(CodecError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.ended = true;
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if self.event_t(&event).is_some_and(|t| t > interval_end) {
+                self.pending_event = Some(event);
+                break;
+            }
+            self.integrate_event(event, &mut events);
+        }
+
+        if self.ended {
+            self.end(&mut events);
+        } else {
+            self.running_t = self.running_t.max(interval_end);
+        }
+
+        ingest_integrated_events(&mut self.video, events)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        None
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        0.0
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W>
+    for Retranscode<W>
+{
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    /// Set the time parameters of the output. If `tps` differs from the input's, the input
+    /// timestamps are rescaled to it.
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adder_codec_core::codec::encoder::Encoder;
+    use adder_codec_core::codec::raw::stream::RawOutput;
+    use adder_codec_core::{Coord, PlaneSize};
+    use std::error::Error;
+    use std::io::{self, BufWriter};
+
+    #[test]
+    fn retranscode_raw() -> Result<(), Box<dyn Error>> {
+        let path =
+            std::env::temp_dir().join(format!("adder_retranscode_{}.adder", std::process::id()));
+        let plane = PlaneSize::new(2, 1, 1)?;
+        let compression = RawOutput::new(
+            CodecMetadata {
+                codec_version: 2,
+                header_size: 0,
+                time_mode: TimeMode::DeltaT,
+                plane,
+                tps: 255 * 30,
+                ref_interval: 255,
+                delta_t_max: 255 * 30,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
+            },
+            BufWriter::new(File::create(&path)?),
+        );
+        let mut stream = Encoder::new_raw(compression, EncoderOptions::default(plane));
+
+        // Each pixel fires an intensity of 128 per frame, for 20 frames
+        for _ in 0..20 {
+            for x in 0..2 {
+                stream.ingest_event(Event {
+                    coord: Coord { x, y: 0, c: None },
+                    d: 7,
+                    t: 255,
+                })?;
+            }
+        }
+        if let Some(mut writer) = stream.close_writer()? {
+            writer.flush()?;
+        }
+
+        let source = Retranscode::new(path.to_str().unwrap());
+        std::fs::remove_file(&path)?;
+        let mut source: Retranscode<io::Sink> = source?;
+        assert_eq!(source.input_meta().tps, 255 * 30);
+        assert_eq!(source.source_camera(), SourceCamera::FramedU8);
+
+        let mut event_count = 0;
+        loop {
+            match source.consume() {
+                Ok(events) => event_count += events.iter().map(Vec::len).sum::<usize>(),
+                Err(SourceError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        assert!(event_count > 0);
+        assert_eq!(source.last_timestamps[[0, 1, 0]], 255 * 20);
+
+        // The re-integrated intensity is close to the original
+        let intensity = source.get_video_ref().state.running_intensities[[0, 0, 0]];
+        assert!((120..=136).contains(&intensity));
+        Ok(())
+    }
+}
@@ -0,0 +1,444 @@
+use crate::transcoder::source::video::{Source, SourceError, Video, VideoBuilder};
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_core::Mode::FramePerfect;
+use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
+use ndarray::Array3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io::Write;
+use video_rs_adder_dep::Frame;
+
+/// A parameterized scene, defining the exact intensity of every pixel in every frame.
+/// Intensities are per `ref_time`, in the range [0, `max_intensity`] of the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntheticScene {
+    /// A vertical bar moving right by `speed` pixels per frame, wrapping around the plane
+    MovingBar {
+        /// The width of the bar, in pixels
+        width: f32,
+        /// Pixels per frame
+        speed: f32,
+        /// The intensity outside the bar
+        background: f32,
+        /// The intensity of the bar
+        foreground: f32,
+    },
+
+    /// A vertical edge moving right by `speed` pixels per frame, with the bright side behind it
+    MovingEdge {
+        /// Pixels per frame
+        speed: f32,
+        /// The intensity ahead of the edge
+        dark: f32,
+        /// The intensity behind the edge
+        bright: f32,
+    },
+
+    /// The whole plane alternating between two intensities, spending `period / 2` frames at
+    /// each
+    Flicker {
+        /// The intensity of the first half of each period
+        high: f32,
+        /// The intensity of the second half of each period
+        low: f32,
+        /// Frames per period
+        period: u32,
+    },
+
+    /// The whole plane changing linearly from one intensity to another over `frames` frames,
+    /// then holding
+    Ramp {
+        /// The intensity of the first frame
+        start: f32,
+        /// The intensity after the ramp
+        end: f32,
+        /// The length of the ramp, in frames
+        frames: u32,
+    },
+
+    /// The whole plane stepping from one intensity to another, such as from a dim scene to a
+    /// very bright one
+    Step {
+        /// The intensity before the step
+        before: f32,
+        /// The intensity from the step onwards
+        after: f32,
+        /// The index of the first frame after the step
+        step_frame: u32,
+    },
+}
+
+impl SyntheticScene {
+    /// Get the exact intensity of a pixel in the given frame
+    pub fn intensity_at(&self, plane: &PlaneSize, x: u16, frame_idx: u32) -> f32 {
+        let x = f32::from(x);
+        let frame = frame_idx as f32;
+        match *self {
+            SyntheticScene::MovingBar {
+                width,
+                speed,
+                background,
+                foreground,
+            } => {
+                let plane_width = f32::from(plane.w());
+                let left = (speed * frame).rem_euclid(plane_width);
+                if (x - left).rem_euclid(plane_width) < width {
+                    foreground
+                } else {
+                    background
+                }
+            }
+            SyntheticScene::MovingEdge {
+                speed,
+                dark,
+                bright,
+            } => {
+                if x < speed * frame {
+                    bright
+                } else {
+                    dark
+                }
+            }
+            SyntheticScene::Flicker { high, low, period } => {
+                let period = period.max(2);
+                if frame_idx % period < period / 2 {
+                    high
+                } else {
+                    low
+                }
+            }
+            SyntheticScene::Ramp { start, end, frames } => {
+                if frame_idx >= frames {
+                    end
+                } else {
+                    start + (end - start) * frame / frames as f32
+                }
+            }
+            SyntheticScene::Step {
+                before,
+                after,
+                step_frame,
+            } => {
+                if frame_idx < step_frame {
+                    before
+                } else {
+                    after
+                }
+            }
+        }
+    }
+}
+
+/// Attributes of a synthetic scene -> ADΔER transcode.
+///
+/// Each frame of the scene spans `ref_time` ticks, so the ground-truth intensity of a pixel at
+/// tick `t` is that of frame `t / ref_time`. Optional uniform noise is added to the frames
+/// which are integrated, but not to the ground truth.
+pub struct Synthetic<W: Write + 'static + std::marker::Send + std::marker::Sync> {
+    pub(crate) video: Video<W>,
+
+    /// An 8-bit copy of the previous input frame, for display
+    pub(crate) input_frame: Frame,
+
+    scene: SyntheticScene,
+
+    /// The index of the next frame to generate
+    frame_idx: u32,
+
+    /// The number of frames to generate
+    frame_count: u32,
+
+    /// The intensity of a saturated pixel
+    max_intensity: f32,
+
+    /// The maximum magnitude of the noise added to each pixel
+    noise_amplitude: f32,
+
+    rng: StdRng,
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Synthetic<W> {
+    /// Create a new `Synthetic` source, which generates `frame_count` grayscale frames of the
+    /// given scene
+    pub fn new(
+        width: u16,
+        height: u16,
+        scene: SyntheticScene,
+        frame_count: u32,
+    ) -> Result<Self, SourceError> {
+        let plane = PlaneSize::new(width, height, 1)?;
+        let video = Video::new(plane, FramePerfect, None)?;
+
+        Ok(Synthetic {
+            video,
+            input_frame: Frame::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize())),
+            scene,
+            frame_idx: 0,
+            frame_count,
+            max_intensity: 255.0,
+            noise_amplitude: 0.0,
+            rng: StdRng::seed_from_u64(0),
+        })
+    }
+
+    /// Set the intensity of a saturated pixel. Defaults to 255. Raise it for scenes with
+    /// intensities beyond the 8-bit range.
+    pub fn max_intensity(mut self, max_intensity: f32) -> Result<Self, SourceError> {
+        if max_intensity <= 0.0 {
+            return Err(SourceError::BadParams(
+                "max_intensity must be positive".to_string(),
+            ));
+        }
+        self.max_intensity = max_intensity;
+        Ok(self)
+    }
+
+    /// Add uniform noise in the range [-`amplitude`, `amplitude`] to each pixel of each frame,
+    /// from a generator with the given seed
+    #[must_use]
+    pub fn noise(mut self, amplitude: f32, seed: u64) -> Self {
+        self.noise_amplitude = amplitude.abs();
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Get the scene being generated
+    pub fn scene(&self) -> &SyntheticScene {
+        &self.scene
+    }
+
+    /// Get the index of the next frame to be generated
+    pub fn frame_idx(&self) -> u32 {
+        self.frame_idx
+    }
+
+    /// Get the source camera type to write in the ADΔER header
+    pub fn source_camera(&self) -> SourceCamera {
+        if self.max_intensity <= 255.0 {
+            SourceCamera::FramedU8
+        } else {
+            SourceCamera::FramedU16
+        }
+    }
+
+    /// Get the exact, noise-free intensity of every pixel in the given frame
+    pub fn ground_truth(&self, frame_idx: u32) -> Array3<f32> {
+        let plane = self.video.state.plane;
+        Array3::from_shape_fn(
+            (plane.h_usize(), plane.w_usize(), plane.c_usize()),
+            |(_, x, _)| {
+                self.scene
+                    .intensity_at(&plane, x as u16, frame_idx)
+                    .clamp(0.0, self.max_intensity)
+            },
+        )
+    }
+
+    /// Get the exact, noise-free intensity of a pixel at the given tick
+    pub fn ground_truth_at(&self, x: u16, t: u32) -> f32 {
+        let frame_idx = t / self.video.state.params.ref_time;
+        self.scene
+            .intensity_at(&self.video.state.plane, x, frame_idx)
+            .clamp(0.0, self.max_intensity)
+    }
+
+    /// Get the previous input frame, scaled to 8 bits
+    pub fn get_last_input_frame(&self) -> &Frame {
+        &self.input_frame
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for Synthetic<W> {
+    /// Generate the next frame of the scene, and integrate it over `ref_time`
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if self.frame_idx >= self.frame_count {
+            return Err(SourceError::EndOfStream);
+        }
+
+        let mut matrix = self.ground_truth(self.frame_idx);
+        self.frame_idx += 1;
+        if self.noise_amplitude > 0.0 {
+            let (amplitude, max_intensity) = (self.noise_amplitude, self.max_intensity);
+            let rng = &mut self.rng;
+            matrix.mapv_inplace(|v| {
+                (v + rng.gen_range(-amplitude..=amplitude)).clamp(0.0, max_intensity)
+            });
+        }
+
+        let scale = 255.0 / self.max_intensity;
+        self.input_frame = matrix.mapv(|v| (v * scale) as u8);
+
+        let ref_time = self.video.state.params.ref_time as f32;
+        self.video
+            .integrate_matrix_precise(matrix, self.max_intensity, ref_time)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        Some(self.get_last_input_frame())
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        let video = self.get_video_ref();
+        video.get_tps() as f64 / video.get_ref_time() as f64
+            * video.state.plane.volume() as f64
+            * 32.0
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W> for Synthetic<W> {
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(self, _name: String) -> Self {
+        todo!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn scenes() -> Result<(), Box<dyn Error>> {
+        let plane = PlaneSize::new(8, 1, 1)?;
+        let bar = SyntheticScene::MovingBar {
+            width: 2.0,
+            speed: 3.0,
+            background: 10.0,
+            foreground: 200.0,
+        };
+        assert_eq!(bar.intensity_at(&plane, 0, 0), 200.0);
+        assert_eq!(bar.intensity_at(&plane, 2, 0), 10.0);
+        assert_eq!(bar.intensity_at(&plane, 3, 1), 200.0);
+        assert_eq!(bar.intensity_at(&plane, 1, 3), 200.0); // Wrapped around
+
+        let ramp = SyntheticScene::Ramp {
+            start: 0.0,
+            end: 100.0,
+            frames: 4,
+        };
+        assert_eq!(ramp.intensity_at(&plane, 0, 1), 25.0);
+        assert_eq!(ramp.intensity_at(&plane, 0, 9), 100.0);
+
+        let flicker = SyntheticScene::Flicker {
+            high: 200.0,
+            low: 50.0,
+            period: 4,
+        };
+        assert_eq!(flicker.intensity_at(&plane, 0, 1), 200.0);
+        assert_eq!(flicker.intensity_at(&plane, 0, 2), 50.0);
+        Ok(())
+    }
+
+    #[test]
+    fn consume_static_scene() -> Result<(), Box<dyn Error>> {
+        let scene = SyntheticScene::Step {
+            before: 100.0,
+            after: 4000.0,
+            step_frame: 1000,
+        };
+        let mut source: Synthetic<io::Sink> = Synthetic::new(4, 4, scene, 20)?
+            .max_intensity(65535.0)?
+            .time_parameters(25500, 255, 255 * 8, None)?;
+        assert_eq!(source.source_camera(), SourceCamera::FramedU16);
+        assert_eq!(source.ground_truth_at(2, 255 * 999), 100.0);
+        assert_eq!(source.ground_truth_at(2, 255 * 1000), 4000.0);
+
+        let mut event_count = 0;
+        loop {
+            match source.consume() {
+                Ok(events) => event_count += events.iter().map(Vec::len).sum::<usize>(),
+                Err(SourceError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        assert!(event_count > 0);
+        assert_eq!(source.frame_idx(), 20);
+        Ok(())
+    }
+}
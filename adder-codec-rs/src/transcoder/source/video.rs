@@ -6,11 +6,11 @@ use std::cmp::min;
 use std::collections::HashSet;
 #[cfg(feature = "feature-logging")]
 use std::ffi::c_void;
-use std::io::{sink, Write};
+use std::io::{sink, Read, Seek, Write};
 use std::mem::swap;
 
 use adder_codec_core::codec::empty::stream::EmptyOutput;
-use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::codec::encoder::{Encoder, EncoderCheckpoint};
 use adder_codec_core::codec::raw::stream::RawOutput;
 use adder_codec_core::codec::{
     CodecError, CodecMetadata, EncoderOptions, EncoderType, LATEST_CODEC_VERSION,
@@ -19,13 +19,13 @@ use adder_codec_core::{
     Coord, DeltaT, Event, Mode, PixelAddress, PixelMultiMode, PlaneError, PlaneSize, SourceCamera,
     SourceType, TimeMode, D_EMPTY, D_ZERO_INTEGRATION,
 };
-use bumpalo::Bump;
 
 use std::sync::mpsc::{channel, Sender};
 use std::time::Instant;
 
 use crate::framer::scale_intensity::{FrameValue, SaeTime};
 use crate::transcoder::event_pixel_tree::{Intensity32, PixelArena};
+use crate::transcoder::filter::{EventFilter, FilterChain};
 use adder_codec_core::D;
 #[cfg(feature = "opencv")]
 use davis_edi_rs::util::reconstructor::ReconstructionError;
@@ -48,6 +48,7 @@ use crate::utils::cv::is_feature;
 use crate::utils::viz::{draw_feature_coord, draw_rect, ShowFeatureMode};
 use adder_codec_core::codec::rate_controller::{Crf, CrfParameters};
 use kiddo::{KdTree, SquaredEuclidean};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::task::JoinError;
 use video_rs_adder_dep::Frame;
@@ -79,6 +80,10 @@ pub enum SourceError {
     #[error("No data from next spot in buffer")]
     NoData,
 
+    /// The source has been fully consumed, and its final events have been produced
+    #[error("End of source stream")]
+    EndOfStream,
+
     /// Data not initialized
     #[error("Data not initialized")]
     UninitializedData,
@@ -120,6 +125,10 @@ pub enum SourceError {
     /// I/O error
     #[error("I/O error")]
     IoError(#[from] std::io::Error),
+
+    /// Image decoding error
+    #[error("Image error")]
+    ImageError(#[from] image::ImageError),
 }
 
 #[cfg(feature = "open-cv")]
@@ -310,6 +319,59 @@ pub trait VideoBuilder<W> {
 
 // impl VideoBuilder for Video {}
 
+/// The state of a [`Video`] transcode, written by [`Video::checkpoint`], from which
+/// [`Video::resume`] continues the transcode
+#[derive(Deserialize)]
+pub struct VideoCheckpoint {
+    plane: PlaneSize,
+    tps: DeltaT,
+    ref_time: DeltaT,
+    delta_t_max: DeltaT,
+    chunk_rows: usize,
+    source_position: u64,
+    in_interval_count: u32,
+    event_pixel_trees: Array3<PixelArena>,
+    running_intensities: Array3<u8>,
+    features: Vec<HashSet<Coord>>,
+    encoder: EncoderCheckpoint,
+}
+
+/// The borrowed form of [`VideoCheckpoint`], for writing it without copying the pixel state.
+/// The fields must stay in the same order.
+#[derive(Serialize)]
+struct VideoCheckpointRef<'a> {
+    plane: PlaneSize,
+    tps: DeltaT,
+    ref_time: DeltaT,
+    delta_t_max: DeltaT,
+    chunk_rows: usize,
+    source_position: u64,
+    in_interval_count: u32,
+    event_pixel_trees: &'a Array3<PixelArena>,
+    running_intensities: &'a Array3<u8>,
+    features: &'a Vec<HashSet<Coord>>,
+    encoder: EncoderCheckpoint,
+}
+
+impl VideoCheckpoint {
+    /// Read a checkpoint written by [`Video::checkpoint`]
+    pub fn read<R: Read>(reader: R) -> Result<Self, SourceError> {
+        Ok(bincode::deserialize_from(reader).map_err(CodecError::from)?)
+    }
+
+    /// The number of bytes of the output at the checkpoint. Anything the output holds past this
+    /// was written after the checkpoint, and should be truncated before resuming.
+    pub fn output_len(&self) -> u64 {
+        self.encoder.output_len
+    }
+
+    /// The position in the source at the checkpoint, as recorded by the source, such as the
+    /// number of frames a [`Framed`](crate::transcoder::source::framed::Framed) source consumed
+    pub fn source_position(&self) -> u64 {
+        self.source_position
+    }
+}
+
 /// Attributes common to ADΔER transcode process
 pub struct Video<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     /// The current state of the video transcode
@@ -331,6 +393,9 @@ pub struct Video<W: Write + std::marker::Send + std::marker::Sync + 'static> {
 
     /// The type of encoder being used (e.g., compressed or raw)
     pub encoder_type: EncoderType,
+
+    /// The filters which each generated event must pass before it's encoded or returned
+    pub filters: FilterChain,
     // TODO: Hold multiple encoder options and an enum, so that boxing isn't required.
     // Also hold a state for whether or not to write out events at all, so that a null writer isn't required.
     // Eric: this is somewhat addressed above
@@ -392,6 +457,8 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             event_size: 0,
             source_camera: SourceCamera::default(), // TODO: Allow for setting this
             adu_interval: Default::default(),
+            lossless: false,
+            entropy_coder: Default::default(),
         };
 
         match writer {
@@ -408,6 +475,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                     event_sender,
                     encoder,
                     encoder_type: EncoderType::Empty,
+                    filters: FilterChain::new(),
                 })
             }
             Some(w) => {
@@ -424,6 +492,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                     event_sender,
                     encoder,
                     encoder_type: EncoderType::Empty,
+                    filters: FilterChain::new(),
                 })
             }
         }
@@ -470,6 +539,12 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         self
     }
 
+    /// Set the filters which each generated event must pass before it's encoded
+    pub fn filters(mut self, filters: FilterChain) -> Self {
+        self.filters = filters;
+        self
+    }
+
     /// Set the time parameters for the video.
     ///
     /// These parameters, in conjunction, determine the temporal resolution and maximum transcode
@@ -545,24 +620,88 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         encoder_options: EncoderOptions,
         write: W,
     ) -> Result<Self, SourceError> {
-        let encoder: Encoder<_> = match encoder_type {
+        self.state.params.pixel_multi_mode = pixel_multi_mode.unwrap_or(PixelMultiMode::Collapse);
+        let encoder = self.new_encoder(
+            source_camera.unwrap_or_default(),
+            time_mode.unwrap_or_default(),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+
+        self.encoder = encoder;
+        self.encoder_type = encoder_type;
+
+        self.event_pixel_trees.par_map_inplace(|px| {
+            px.time_mode(time_mode);
+        });
+        Ok(self)
+    }
+
+    /// Write the video to an additional output, alongside the one set up by
+    /// [`Video::write_out`], which must be called first. Each output has its own encoder type,
+    /// options, and writer, but shares the source camera and time mode of the first output.
+    ///
+    /// # Arguments
+    ///
+    /// * `adu_interval`: the number of reference intervals per ADU, for a compressed output
+    /// * `encoder_type`: the type of encoder for this output
+    /// * `encoder_options`: the encoder options for this output
+    /// * `write`: the output stream to write to
+    pub fn add_output(
+        mut self,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Self, SourceError> {
+        let meta = *self.encoder.meta();
+        let encoder = self.new_encoder(
+            meta.source_camera,
+            meta.time_mode,
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        self.encoder.tee(encoder);
+        Ok(self)
+    }
+
+    /// Create an encoder for the video, with the given parameters
+    fn new_encoder(
+        &self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Encoder<W>, SourceError> {
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            header_size: 0,
+            time_mode,
+            plane: self.state.plane,
+            tps: self.state.tps,
+            ref_interval: self.state.params.ref_time,
+            delta_t_max: self.state.params.delta_t_max,
+            event_size: 0,
+            source_camera,
+            adu_interval: Default::default(),
+            lossless: false,
+            entropy_coder: Default::default(),
+        };
+
+        Ok(match encoder_type {
             EncoderType::Compressed => {
                 #[cfg(feature = "compression")]
                 {
-                    self.state.params.pixel_multi_mode =
-                        pixel_multi_mode.unwrap_or(PixelMultiMode::Collapse);
                     let compression = CompressedOutput::new(
                         CodecMetadata {
-                            codec_version: LATEST_CODEC_VERSION,
-                            header_size: 0,
-                            time_mode: time_mode.unwrap_or_default(),
-                            plane: self.state.plane,
-                            tps: self.state.tps,
-                            ref_interval: self.state.params.ref_time,
-                            delta_t_max: self.state.params.delta_t_max,
-                            event_size: 0,
-                            source_camera: source_camera.unwrap_or_default(),
                             adu_interval: adu_interval.unwrap_or_default(),
+                            ..meta
                         },
                         write,
                     );
@@ -576,67 +715,51 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                     ));
                 }
             }
-            EncoderType::Raw => {
-                self.state.params.pixel_multi_mode =
-                    pixel_multi_mode.unwrap_or(PixelMultiMode::Collapse);
-                let compression = RawOutput::new(
-                    CodecMetadata {
-                        codec_version: LATEST_CODEC_VERSION,
-                        header_size: 0,
-                        time_mode: time_mode.unwrap_or_default(),
-                        plane: self.state.plane,
-                        tps: self.state.tps,
-                        ref_interval: self.state.params.ref_time,
-                        delta_t_max: self.state.params.delta_t_max,
-                        event_size: 0,
-                        source_camera: source_camera.unwrap_or_default(),
-                        adu_interval: Default::default(),
-                    },
-                    write,
-                );
-                Encoder::new_raw(compression, encoder_options)
-            }
+            EncoderType::Raw => Encoder::new_raw(RawOutput::new(meta, write), encoder_options),
             EncoderType::Empty => {
-                self.state.params.pixel_multi_mode =
-                    pixel_multi_mode.unwrap_or(PixelMultiMode::Collapse);
-                let compression = EmptyOutput::new(
-                    CodecMetadata {
-                        codec_version: LATEST_CODEC_VERSION,
-                        header_size: 0,
-                        time_mode: time_mode.unwrap_or_default(),
-                        plane: self.state.plane,
-                        tps: self.state.tps,
-                        ref_interval: self.state.params.ref_time,
-                        delta_t_max: self.state.params.delta_t_max,
-                        event_size: 0,
-                        source_camera: source_camera.unwrap_or_default(),
-                        adu_interval: Default::default(),
-                    },
-                    sink(),
-                );
-                Encoder::new_empty(compression, encoder_options)
+                Encoder::new_empty(EmptyOutput::new(meta, sink()), encoder_options)
             }
-        };
-
-        self.encoder = encoder;
-        self.encoder_type = encoder_type;
-
-        self.event_pixel_trees.par_map_inplace(|px| {
-            px.time_mode(time_mode);
-        });
-        Ok(self)
+        })
     }
 
     /// Close and flush the stream writer.
     /// # Errors
     /// Returns an error if the stream writer cannot be closed cleanly.
     pub fn end_write_stream(&mut self) -> Result<Option<W>, SourceError> {
+        Ok(self.take_encoder().close_writer()?)
+    }
+
+    /// Close and flush the stream writers of all the outputs, returning them in the order they
+    /// were added.
+    /// # Errors
+    /// Returns an error if a stream writer cannot be closed cleanly.
+    pub fn end_write_streams(&mut self) -> Result<Vec<Option<W>>, SourceError> {
+        Ok(self.take_encoder().close_writers()?)
+    }
+
+    /// Take the encoder out of the video, leaving an empty encoder in its place
+    fn take_encoder(&mut self) -> Encoder<W> {
         let mut tmp: Encoder<W> = Encoder::new_empty(
             EmptyOutput::new(CodecMetadata::default(), sink()),
             self.encoder.options,
         );
         swap(&mut self.encoder, &mut tmp);
-        Ok(tmp.close_writer()?)
+        tmp
+    }
+
+    /// Drop the events which don't pass the filters, then ingest the rest into the encoder. The
+    /// dropped events are removed from `events`, so they're not passed on to the caller either.
+    pub(crate) fn ingest_events_events(
+        &mut self,
+        events: &mut [Vec<Event>],
+    ) -> Result<(), CodecError> {
+        if !self.filters.is_empty() {
+            self.filters.retain(events);
+        }
+        for events in events.iter() {
+            self.encoder.ingest_events(events)?;
+        }
+        Ok(())
     }
 
     #[allow(clippy::needless_pass_by_value)]
@@ -645,20 +768,51 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         matrix: Frame,
         time_spanned: f32,
     ) -> Result<Vec<Vec<Event>>, SourceError> {
+        // let matrix_f32 = convert_u8_to_f32_simd(&matrix.into_raw_vec());
+        self.integrate_intensities(matrix.mapv(f32::from), 255.0, 1.0, time_spanned)
+    }
+
+    /// Integrate a matrix of intensities at the full precision of the source, rather than as
+    /// 8-bit values. `max_intensity` is the intensity of a saturated pixel, such as 65535 for a
+    /// 16-bit source. Each intensity is the amount of light over `ref_time` ticks, so it's scaled
+    /// to the given `time_spanned`.
+    ///
+    /// The contrast thresholds are scaled from the 8-bit range to `max_intensity`, so intensity
+    /// changes finer than an 8-bit step can still fire events. The running intensities are
+    /// still in the 8-bit range.
+    pub(crate) fn integrate_matrix_precise(
+        &mut self,
+        matrix: Array3<f32>,
+        max_intensity: f32,
+        time_spanned: f32,
+    ) -> Result<Vec<Vec<Event>>, SourceError> {
+        let intensity_scale = time_spanned / self.state.params.ref_time as f32;
+        self.integrate_intensities(matrix, max_intensity, intensity_scale, time_spanned)
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn integrate_intensities(
+        &mut self,
+        matrix: Array3<f32>,
+        max_intensity: f32,
+        intensity_scale: f32,
+        time_spanned: f32,
+    ) -> Result<Vec<Vec<Event>>, SourceError> {
+        // The scale from source intensities to the 8-bit range, and from the 8-bit contrast
+        // thresholds back to source intensities
+        let frame_scale = 255.0 / max_intensity;
+        let contrast_scale = max_intensity / 255.0;
+
         if self.state.in_interval_count == 0 {
-            self.set_initial_d(&matrix);
+            self.set_initial_d(&matrix, frame_scale);
         }
 
         let parameters = *self.encoder.options.crf.get_parameters();
 
         self.state.in_interval_count += 1;
 
-        // let matrix_f32 = convert_u8_to_f32_simd(&matrix.into_raw_vec());
-        let matrix = matrix.mapv(f32::from);
-
-        // TODO: When there's full support for various bit-depth sources, modify this accordingly
         let practical_d_max = fast_math::log2_raw(
-            255.0 * (self.state.params.delta_t_max / self.state.params.ref_time) as f32,
+            max_intensity * (self.state.params.delta_t_max / self.state.params.ref_time) as f32,
         );
 
         let tpf = self.state.params.ref_time as f64;
@@ -666,7 +820,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         let params = &self.state.params;
         // Important: if framing the events simultaneously, then the chunk division must be
         // exactly the same as it is for the framer
-        let big_buffer: Vec<Vec<Event>> = self
+        let mut big_buffer: Vec<Vec<Event>> = self
             .event_pixel_trees
             .axis_chunks_iter_mut(Axis(0), self.state.chunk_rows)
             .into_par_iter()
@@ -683,19 +837,20 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             )
             .map(|((mut px_chunk, matrix_chunk), mut running_chunk)| {
                 let mut buffer: Vec<Event> = Vec::with_capacity(10);
-                let bump = Bump::new();
-                let base_val = bump.alloc(0);
 
                 for ((px, input), running) in px_chunk
                     .iter_mut()
                     .zip(matrix_chunk.iter())
                     .zip(running_chunk.iter_mut())
                 {
-                    integrate_for_px(
+                    if !px.in_roi {
+                        continue;
+                    }
+                    integrate_for_px_scaled(
                         px,
-                        base_val,
-                        *input as u8,
-                        *input, // In this case, frame val is the same as intensity to integrate
+                        *input,
+                        contrast_scale,
+                        *input * intensity_scale,
                         time_spanned,
                         &mut buffer,
                         params,
@@ -706,7 +861,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                         *running = u8::get_frame_value(
                             &event.into(),
                             SourceType::U8,
-                            tpf,
+                            tpf * f64::from(frame_scale),
                             practical_d_max,
                             self.state.params.delta_t_max,
                             self.instantaneous_view_mode,
@@ -725,11 +880,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             })
             .collect();
 
-        for events in &big_buffer {
-            for e1 in events.iter() {
-                self.encoder.ingest_event(*e1)?;
-            }
-        }
+        self.ingest_events_events(&mut big_buffer)?;
 
         self.display_frame_features = self.state.running_intensities.clone();
 
@@ -767,7 +918,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         Ok(big_buffer)
     }
 
-    fn set_initial_d(&mut self, frame: &Frame) {
+    fn set_initial_d(&mut self, frame: &Array3<f32>, frame_scale: f32) {
         self.event_pixel_trees
             .axis_chunks_iter_mut(Axis(0), self.state.chunk_rows)
             .into_par_iter()
@@ -778,14 +929,14 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             )
             .for_each(|(mut px, frame_chunk)| {
                 for (px, frame_val) in px.iter_mut().zip(frame_chunk.iter()) {
-                    let d_start = if *frame_val == 0 {
+                    let d_start = if *frame_val < 1.0 {
                         D_ZERO_INTEGRATION
                     } else {
-                        (f32::from(*frame_val)).log2().floor() as D
+                        frame_val.log2().floor() as D
                     };
 
                     px.arena[0].set_d(d_start);
-                    px.base_val = *frame_val as u8;
+                    px.base_val = *frame_val;
                 }
             });
     }
@@ -1260,6 +1411,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
 
     pub fn update_encoder_options(&mut self, options: EncoderOptions) {
         self.encoder.options = options;
+        self.encoder.sync_crf();
     }
 
     /// Get the size of the raw events (in bytes)
@@ -1268,6 +1420,68 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
     }
 }
 
+impl<W: Write + Seek + 'static + std::marker::Send + std::marker::Sync + 'static> Video<W> {
+    /// Flush the output and write a checkpoint of the transcode to `writer`, so that a
+    /// transcode which stops can be [resumed](Video::resume) from here, with the same output as if
+    /// it had never stopped. `source_position` is where the source is in its input, for it to
+    /// skip ahead to on resuming.
+    ///
+    /// The state of the [`filters`](Video::filters) isn't captured, so a video with filters can't
+    /// be checkpointed.
+    pub fn checkpoint<C: Write>(
+        &mut self,
+        writer: C,
+        source_position: u64,
+    ) -> Result<(), SourceError> {
+        if !self.filters.is_empty() {
+            return Err(SourceError::BadParams(
+                "Can't checkpoint a video with event filters".to_string(),
+            ));
+        }
+
+        let checkpoint = VideoCheckpointRef {
+            plane: self.state.plane,
+            tps: self.state.tps,
+            ref_time: self.state.params.ref_time,
+            delta_t_max: self.state.params.delta_t_max,
+            chunk_rows: self.state.chunk_rows,
+            source_position,
+            in_interval_count: self.state.in_interval_count,
+            event_pixel_trees: &self.event_pixel_trees,
+            running_intensities: &self.state.running_intensities,
+            features: &self.state.features,
+            encoder: self.encoder.checkpoint()?,
+        };
+        bincode::serialize_into(writer, &checkpoint).map_err(CodecError::from)?;
+        Ok(())
+    }
+
+    /// Resume a transcode from a checkpoint. The video must be set up with the same parameters
+    /// as the checkpointed one, and write out to the same output, opened without truncating it
+    /// (but truncated to [`VideoCheckpoint::output_len`]). Events are then written from the end
+    /// of the checkpointed output.
+    pub fn resume(mut self, checkpoint: VideoCheckpoint) -> Result<Self, SourceError> {
+        if checkpoint.plane != self.state.plane
+            || checkpoint.tps != self.state.tps
+            || checkpoint.ref_time != self.state.params.ref_time
+            || checkpoint.delta_t_max != self.state.params.delta_t_max
+            || checkpoint.chunk_rows != self.state.chunk_rows
+        {
+            return Err(SourceError::BadParams(
+                "The checkpoint's parameters don't match the video's".to_string(),
+            ));
+        }
+
+        self.encoder.resume(&checkpoint.encoder)?;
+        self.state.in_interval_count = checkpoint.in_interval_count;
+        self.event_pixel_trees = checkpoint.event_pixel_trees;
+        self.state.running_intensities = checkpoint.running_intensities;
+        self.display_frame_features = self.state.running_intensities.clone();
+        self.state.features = checkpoint.features;
+        Ok(self)
+    }
+}
+
 /// Integrate an intensity value for a pixel, over a given time span
 ///
 /// # Arguments
@@ -1286,8 +1500,35 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
 pub fn integrate_for_px(
     px: &mut PixelArena,
     base_val: &mut u8,
-    mut frame_val: u8,
-    mut intensity: Intensity32,
+    frame_val: u8,
+    intensity: Intensity32,
+    time_spanned: f32,
+    buffer: &mut Vec<Event>,
+    params: &VideoStateParams,
+    parameters: &CrfParameters,
+) -> bool {
+    *base_val = px.base_val as u8;
+    integrate_for_px_scaled(
+        px,
+        f32::from(frame_val),
+        1.0,
+        intensity,
+        time_spanned,
+        buffer,
+        params,
+        parameters,
+    )
+}
+
+/// Like [`integrate_for_px`], but `frame_val` is in the units of the source's intensities, and
+/// the pixel's 8-bit contrast threshold is multiplied by `contrast_scale` to match them
+#[inline(always)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn integrate_for_px_scaled(
+    px: &mut PixelArena,
+    frame_val: f32,
+    contrast_scale: f32,
+    intensity: Intensity32,
     time_spanned: f32,
     buffer: &mut Vec<Event>,
     params: &VideoStateParams,
@@ -1300,11 +1541,8 @@ pub fn integrate_for_px(
         grew_buffer = true;
     }
 
-    *base_val = px.base_val;
-
-    if frame_val < base_val.saturating_sub(px.c_thresh)
-        || frame_val > base_val.saturating_add(px.c_thresh)
-    {
+    let c_thresh = f32::from(px.c_thresh) * contrast_scale;
+    if frame_val < px.base_val - c_thresh || frame_val > px.base_val + c_thresh {
         let _tmp = buffer.len();
         px.pop_best_events(
             buffer,
@@ -1426,3 +1664,118 @@ pub trait Source<W: Write + std::marker::Send + std::marker::Sync + 'static> {
 //
 //     result
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::io::Cursor;
+
+    /// A frame with a pattern that changes over time
+    fn frame(plane: PlaneSize, i: usize) -> Frame {
+        Frame::from_shape_fn(
+            (plane.h_usize(), plane.w_usize(), plane.c_usize()),
+            |(y, x, _)| ((x * 31 + y * 17 + i * (x + 1) * 5) % 256) as u8,
+        )
+    }
+
+    fn new_video(
+        plane: PlaneSize,
+        encoder_type: EncoderType,
+        output: Cursor<Vec<u8>>,
+    ) -> Result<Video<Cursor<Vec<u8>>>, SourceError> {
+        Video::new(plane, Continuous, None)?
+            .chunk_rows(4)
+            .write_out(
+                Some(SourceCamera::FramedU8),
+                Some(TimeMode::AbsoluteT),
+                None,
+                Some(5),
+                encoder_type,
+                EncoderOptions::default(plane),
+                output,
+            )
+    }
+
+    #[test]
+    fn checkpoint_resume() -> Result<(), Box<dyn Error>> {
+        let plane = PlaneSize::new(16, 8, 1)?;
+        let encoder_types: &[EncoderType] = if cfg!(feature = "compression") {
+            &[EncoderType::Raw, EncoderType::Compressed]
+        } else {
+            &[EncoderType::Raw]
+        };
+
+        for &encoder_type in encoder_types {
+            let mut video = new_video(plane, encoder_type, Cursor::new(Vec::new()))?;
+            for i in 0..40 {
+                video.integrate_matrix(frame(plane, i), 255.0)?;
+            }
+            let expected = video.end_write_stream()?.unwrap().into_inner();
+
+            let mut video = new_video(plane, encoder_type, Cursor::new(Vec::new()))?;
+            for i in 0..20 {
+                video.integrate_matrix(frame(plane, i), 255.0)?;
+            }
+            let mut checkpoint = Vec::new();
+            video.checkpoint(&mut checkpoint, 20)?;
+
+            // Some more progress is lost when the transcode stops
+            for i in 20..25 {
+                video.integrate_matrix(frame(plane, i), 255.0)?;
+            }
+            let mut output = video.end_write_stream()?.unwrap().into_inner();
+
+            let checkpoint = VideoCheckpoint::read(checkpoint.as_slice())?;
+            assert_eq!(checkpoint.source_position(), 20);
+            output.truncate(checkpoint.output_len() as usize);
+
+            let mut video =
+                new_video(plane, encoder_type, Cursor::new(output))?.resume(checkpoint)?;
+            for i in 20..40 {
+                video.integrate_matrix(frame(plane, i), 255.0)?;
+            }
+            assert_eq!(video.end_write_stream()?.unwrap().into_inner(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn precise_contrast_threshold() -> Result<(), Box<dyn Error>> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let shape = (plane.h_usize(), plane.w_usize(), plane.c_usize());
+
+        // Both intensities are 117 when scaled to 8 bits
+        let (before, after) = (30100.0, 30300.0);
+        assert_eq!(
+            (before * 255.0 / 65535.0) as u8,
+            (after * 255.0 / 65535.0) as u8
+        );
+
+        let mut last_events = Vec::new();
+        for last in [before, after] {
+            let mut video = new_video(plane, EncoderType::Raw, Cursor::new(Vec::new()))?;
+            video.update_quality_manual(0, 0, 100, 1, 0.0);
+            let ref_time = video.state.params.ref_time as f32;
+            for _ in 0..3 {
+                video.integrate_matrix_precise(
+                    Array3::from_elem(shape, before),
+                    65535.0,
+                    ref_time,
+                )?;
+            }
+            let events = video.integrate_matrix_precise(
+                Array3::from_elem(shape, last),
+                65535.0,
+                ref_time,
+            )?;
+            assert!(video.event_pixel_trees.iter().all(|px| px.base_val == last));
+            last_events.push(events.iter().map(Vec::len).sum::<usize>());
+        }
+
+        // Only the change in intensity makes the pixels fire before delta_t_max
+        assert_eq!(last_events[0], 0);
+        assert!(last_events[1] > 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,570 @@
+use crate::framer::scale_intensity::event_to_intensity;
+use crate::transcoder::source::dvs::{DvsEvent, DVS_SOURCE_TPS};
+use crate::transcoder::source::video::{Source, SourceError};
+use adder_codec_core::codec::decoder::Decoder;
+use adder_codec_core::codec::{CodecError, CodecMetadata, EncoderType};
+use adder_codec_core::{open_file_decoder, Event, PlaneSize, TimeMode, D_EMPTY};
+use bitstream_io::{BigEndian, BitReader};
+use ndarray::{Array2, Array3, Axis};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::marker::PhantomData;
+
+/// Parameters of the DVS sensor model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DvsSimParams {
+    pub(crate) theta: f64,
+    pub(crate) refractory_period: u32,
+    pub(crate) noise_rate: f64,
+    pub(crate) seed: u64,
+}
+
+impl Default for DvsSimParams {
+    fn default() -> Self {
+        Self {
+            theta: 0.2,
+            refractory_period: 0,
+            noise_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl DvsSimParams {
+    /// Set the contrast threshold, as a change in log intensity. Default 0.2.
+    #[must_use]
+    pub fn theta(mut self, theta: f64) -> Self {
+        self.theta = theta.max(f64::EPSILON);
+        self
+    }
+
+    /// Set the minimum time (in microseconds) between two events of the same pixel. Events
+    /// within the period are dropped. Default 0.
+    #[must_use]
+    pub fn refractory_period(mut self, refractory_period: u32) -> Self {
+        self.refractory_period = refractory_period;
+        self
+    }
+
+    /// Set the rate of spurious events, per pixel per second, each with a random polarity.
+    /// Default 0.
+    #[must_use]
+    pub fn noise_rate(mut self, noise_rate: f64) -> Self {
+        self.noise_rate = noise_rate.max(0.0);
+        self
+    }
+
+    /// Set the seed of the noise generator. Default 0.
+    #[must_use]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// A DVS sensor model. Each pixel holds a reference log intensity, and fires an event each time
+/// its log intensity moves a contrast threshold away from the reference.
+///
+/// Timestamps are in microseconds, like those of [`DvsEvent`]s from a real sensor.
+pub struct DvsSimulator {
+    params: DvsSimParams,
+    plane: PlaneSize,
+
+    /// The log intensity at which each pixel last fired, or was initialized
+    ref_ln: Array2<f64>,
+
+    /// The log intensity of each pixel's latest sample
+    last_ln: Array2<f64>,
+
+    /// The timestamp of each pixel's latest sample
+    last_t: Array2<u32>,
+
+    /// The timestamp of each pixel's latest event
+    last_fired_t: Array2<Option<u32>>,
+
+    /// Whether each pixel has been sampled yet
+    initialized: Array2<bool>,
+
+    rng: StdRng,
+}
+
+impl DvsSimulator {
+    /// Create a simulator for the given plane. Color inputs are averaged across their channels.
+    pub fn new(plane: PlaneSize, params: DvsSimParams) -> Self {
+        let shape = (plane.h_usize(), plane.w_usize());
+        Self {
+            params,
+            plane,
+            ref_ln: Array2::zeros(shape),
+            last_ln: Array2::zeros(shape),
+            last_t: Array2::zeros(shape),
+            last_fired_t: Array2::from_elem(shape, None),
+            initialized: Array2::from_elem(shape, false),
+            rng: StdRng::seed_from_u64(params.seed),
+        }
+    }
+
+    /// Get the plane of the simulated sensor
+    pub fn plane(&self) -> &PlaneSize {
+        &self.plane
+    }
+
+    /// Sample the intensity of a pixel at time `t`, pushing any resulting events onto `events`.
+    ///
+    /// If `interpolate` is true, the log intensity is assumed to change linearly since the
+    /// pixel's last sample, and the events are spread across that time. Otherwise, the change is
+    /// a step at `t`.
+    pub fn sample_pixel(
+        &mut self,
+        x: u16,
+        y: u16,
+        intensity: f64,
+        t: u32,
+        interpolate: bool,
+        events: &mut Vec<DvsEvent>,
+    ) {
+        let idx = [y as usize, x as usize];
+        let ln = intensity.max(0.0).ln_1p();
+        if !self.initialized[idx] {
+            self.initialized[idx] = true;
+            self.ref_ln[idx] = ln;
+            self.last_ln[idx] = ln;
+            self.last_t[idx] = t;
+            return;
+        }
+
+        let (last_ln, last_t) = (self.last_ln[idx], self.last_t[idx]);
+        let ref_ln = self.ref_ln[idx];
+        let theta = self.params.theta;
+        let crossings = ((ln - ref_ln).abs() / theta).floor() as u32;
+        let sign = if ln > ref_ln { 1.0 } else { -1.0 };
+
+        for i in 1..=crossings {
+            let event_t = if interpolate && t > last_t && ln != last_ln {
+                let level = ref_ln + sign * f64::from(i) * theta;
+                let frac = ((level - last_ln) / (ln - last_ln)).clamp(0.0, 1.0);
+                last_t + (f64::from(t - last_t) * frac) as u32
+            } else {
+                t
+            };
+
+            if let Some(fired_t) = self.last_fired_t[idx] {
+                if event_t.saturating_sub(fired_t) < self.params.refractory_period {
+                    continue;
+                }
+            }
+            self.last_fired_t[idx] = Some(event_t);
+            events.push(DvsEvent {
+                t: event_t,
+                x,
+                y,
+                p: u8::from(sign > 0.0),
+            });
+        }
+
+        self.ref_ln[idx] = ref_ln + sign * f64::from(crossings) * theta;
+        self.last_ln[idx] = ln;
+        self.last_t[idx] = t;
+    }
+
+    /// Sample every pixel of a frame at time `t`, returning the resulting events (including
+    /// noise since the previous frame) in timestamp order
+    pub fn sample_frame(&mut self, frame: &Array3<f32>, t: u32) -> Vec<DvsEvent> {
+        let mut events = Vec::new();
+        let prev_t = match (self.initialized.first(), self.last_t.first()) {
+            (Some(true), Some(last_t)) => *last_t,
+            _ => t,
+        };
+        let intensities = frame.mean_axis(Axis(2));
+        if let Some(intensities) = intensities {
+            for ((y, x), intensity) in intensities.indexed_iter() {
+                self.sample_pixel(
+                    x as u16,
+                    y as u16,
+                    f64::from(*intensity),
+                    t,
+                    true,
+                    &mut events,
+                );
+            }
+        }
+        self.add_noise(prev_t, t, &mut events);
+        events.sort_by_key(|event| event.t);
+        events
+    }
+
+    /// Push spurious events for the time between `start_t` and `end_t` onto `events`
+    pub fn add_noise(&mut self, start_t: u32, end_t: u32, events: &mut Vec<DvsEvent>) {
+        if self.params.noise_rate <= 0.0 || end_t <= start_t {
+            return;
+        }
+        let probability = (self.params.noise_rate * f64::from(end_t - start_t)
+            / f64::from(DVS_SOURCE_TPS))
+        .min(1.0);
+        for y in 0..self.plane.h() {
+            for x in 0..self.plane.w() {
+                if self.rng.gen_bool(probability) {
+                    events.push(DvsEvent {
+                        t: self.rng.gen_range(start_t..end_t),
+                        x,
+                        y,
+                        p: u8::from(self.rng.gen_bool(0.5)),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Simulate the events of a sequence of `(frame, timestamp)` pairs, with timestamps in
+    /// microseconds
+    pub fn simulate_frames<I: IntoIterator<Item = (Array3<f32>, u32)>>(
+        self,
+        frames: I,
+    ) -> FrameDvsEvents<I::IntoIter> {
+        FrameDvsEvents {
+            frames: frames.into_iter(),
+            simulator: self,
+            buffer: VecDeque::new(),
+        }
+    }
+}
+
+/// An iterator over the DVS events simulated from a sequence of frames
+pub struct FrameDvsEvents<I: Iterator<Item = (Array3<f32>, u32)>> {
+    frames: I,
+    simulator: DvsSimulator,
+    buffer: VecDeque<DvsEvent>,
+}
+
+impl<I: Iterator<Item = (Array3<f32>, u32)>> Iterator for FrameDvsEvents<I> {
+    type Item = DvsEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() {
+            let (frame, t) = self.frames.next()?;
+            self.buffer.extend(self.simulator.sample_frame(&frame, t));
+        }
+        self.buffer.pop_front()
+    }
+}
+
+/// An iterator over the input frames of a framed [`Source`], as it's consumed. Each frame is
+/// timestamped (in microseconds) by the `ref_time` ticks it spans, so the same pass can both
+/// transcode the source to ADΔER and simulate its DVS events.
+pub struct SourceFrames<'a, W, S>
+where
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    S: Source<W>,
+{
+    source: &'a mut S,
+    frame_idx: u32,
+    error: Option<SourceError>,
+    _phantom: PhantomData<W>,
+}
+
+impl<'a, W, S> SourceFrames<'a, W, S>
+where
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    S: Source<W>,
+{
+    /// Iterate over the frames of the given source
+    pub fn new(source: &'a mut S) -> Self {
+        Self {
+            source,
+            frame_idx: 0,
+            error: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Take the error which ended the iteration, if it wasn't the end of the source
+    pub fn take_error(&mut self) -> Option<SourceError> {
+        self.error.take()
+    }
+}
+
+impl<'a, W, S> Iterator for SourceFrames<'a, W, S>
+where
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    S: Source<W>,
+{
+    type Item = (Array3<f32>, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.source.consume() {
+            Ok(_) => {}
+            Err(SourceError::EndOfStream) => return None,
+            Err(e) => {
+                self.error = Some(e);
+                return None;
+            }
+        }
+        let frame = self.source.get_input()?.mapv(f32::from);
+        let video = self.source.get_video_ref();
+        let t =
+            u64::from(self.frame_idx) * u64::from(video.get_ref_time()) * u64::from(DVS_SOURCE_TPS)
+                / u64::from(video.get_tps());
+        self.frame_idx += 1;
+        Some((frame, t as u32))
+    }
+}
+
+/// An iterator over the DVS events simulated from an ADΔER stream. Each ADΔER event is a step
+/// change in its pixel's intensity at the start of the event's time span.
+///
+/// Events are yielded in the order their pixels change, which is not strictly timestamp order.
+/// Color streams are simulated from their first channel. An error decoding the stream, such as
+/// from a corrupt or truncated file, is yielded and ends the iteration.
+pub struct AdderDvsEvents {
+    input_stream: Decoder<BufReader<File>>,
+    bitreader: BitReader<BufReader<File>, BigEndian>,
+    meta: CodecMetadata,
+    simulator: DvsSimulator,
+
+    /// The timestamp (in input ticks) of the last event decoded for each pixel
+    last_timestamps: Array2<u32>,
+
+    /// The latest timestamp (in microseconds) up to which noise has been added
+    noise_t: u32,
+
+    buffer: VecDeque<DvsEvent>,
+
+    /// Whether the end of the stream, or an error, has been reached
+    ended: bool,
+}
+
+impl AdderDvsEvents {
+    /// Open an ADΔER file to simulate DVS events from
+    pub fn new(input_filename: &str, params: DvsSimParams) -> Result<Self, CodecError> {
+        let (input_stream, bitreader) = open_file_decoder(input_filename)?;
+        let meta = *input_stream.meta();
+        let plane = meta.plane;
+        Ok(Self {
+            input_stream,
+            bitreader,
+            meta,
+            simulator: DvsSimulator::new(plane, params),
+            last_timestamps: Array2::zeros((plane.h_usize(), plane.w_usize())),
+            noise_t: 0,
+            buffer: VecDeque::new(),
+            ended: false,
+        })
+    }
+
+    /// Whether a decoding error is the normal end of the stream. Compressed streams have no end
+    /// marker, so they end at an unexpected EOF between Adus.
+    fn is_end_of_stream(&self, e: &CodecError) -> bool {
+        match e {
+            CodecError::Eof => true,
+            CodecError::IoError(e) => {
+                e.kind() == io::ErrorKind::UnexpectedEof
+                    && self.input_stream.get_compression_type() == EncoderType::Compressed
+            }
+            _ => false,
+        }
+    }
+
+    /// Convert input ticks to microseconds
+    fn to_micros(&self, t: u32) -> u32 {
+        (u64::from(t) * u64::from(DVS_SOURCE_TPS) / u64::from(self.meta.tps)) as u32
+    }
+
+    /// Simulate the DVS events of one decoded ADΔER event
+    fn sample_event(&mut self, event: Event, events: &mut Vec<DvsEvent>) {
+        let (y, x) = (event.coord.y_usize(), event.coord.x_usize());
+        if event.coord.c_usize() != 0
+            || y >= self.meta.plane.h_usize()
+            || x >= self.meta.plane.w_usize()
+        {
+            return;
+        }
+        let start_t = self.last_timestamps[[y, x]];
+        let t = if self.meta.codec_version >= 2 && self.meta.time_mode == TimeMode::AbsoluteT {
+            event.t
+        } else {
+            start_t.saturating_add(event.t)
+        };
+        if t <= start_t {
+            return;
+        }
+        self.last_timestamps[[y, x]] = t;
+
+        // An empty event continues the pixel's last intensity
+        if event.d == D_EMPTY {
+            return;
+        }
+        let intensity = event_to_intensity(&Event {
+            t: t - start_t,
+            ..event
+        }) * f64::from(self.meta.ref_interval);
+        let start_micros = self.to_micros(start_t);
+        self.simulator.sample_pixel(
+            event.coord.x,
+            event.coord.y,
+            intensity,
+            start_micros,
+            false,
+            events,
+        );
+
+        // Add noise one reference interval at a time
+        let interval = self.to_micros(self.meta.ref_interval).max(1);
+        while start_micros >= self.noise_t + interval {
+            self.simulator
+                .add_noise(self.noise_t, self.noise_t + interval, events);
+            self.noise_t += interval;
+        }
+    }
+}
+
+impl Iterator for AdderDvsEvents {
+    type Item = Result<DvsEvent, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut events = Vec::new();
+        while self.buffer.is_empty() {
+            if self.ended {
+                return None;
+            }
+            let event = match self.input_stream.digest_event(&mut self.bitreader) {
+                Ok(event) => event,
+                Err(e) => {
+                    self.ended = true;
+                    return (!self.is_end_of_stream(&e)).then_some(Err(e));
+                }
+            };
+            self.sample_event(event, &mut events);
+            events.sort_by_key(|event| event.t);
+            self.buffer.extend(events.drain(..));
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane() -> PlaneSize {
+        PlaneSize::new(2, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn threshold_crossings() {
+        let mut simulator = DvsSimulator::new(plane(), DvsSimParams::default().theta(0.5));
+        let mut events = Vec::new();
+        simulator.sample_pixel(0, 0, 10.0, 0, true, &mut events);
+        assert!(events.is_empty());
+
+        // ln(101) - ln(11) = 2.22, so four thresholds are crossed, spread across the interval
+        simulator.sample_pixel(0, 0, 100.0, 1000, true, &mut events);
+        assert_eq!(events.len(), 4);
+        assert!(events.iter().all(|event| event.p == 1));
+        assert!(events.windows(2).all(|pair| pair[0].t < pair[1].t));
+        assert!(events[3].t <= 1000);
+
+        // Back down, as a step. ln(13) is 0.33 (less than one threshold) above the reference.
+        events.clear();
+        simulator.sample_pixel(0, 0, 12.0, 2000, false, &mut events);
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|event| event.p == 0 && event.t == 2000));
+    }
+
+    #[test]
+    fn refractory_period() {
+        let params = DvsSimParams::default().theta(0.5).refractory_period(500);
+        let mut simulator = DvsSimulator::new(plane(), params);
+        let mut events = Vec::new();
+        simulator.sample_pixel(0, 0, 10.0, 0, true, &mut events);
+        simulator.sample_pixel(0, 0, 100.0, 1000, true, &mut events);
+        assert!(events.len() < 4);
+        assert!(events.windows(2).all(|pair| pair[1].t - pair[0].t >= 500));
+    }
+
+    #[test]
+    fn frames_and_noise() {
+        let frames = [20.0, 180.0, 30.0, 180.0]
+            .into_iter()
+            .zip(0_u32..)
+            .map(|(value, i)| (Array3::from_elem((1, 2, 1), value), i * 10_000));
+        let events: Vec<DvsEvent> = DvsSimulator::new(plane(), DvsSimParams::default())
+            .simulate_frames(frames)
+            .collect();
+        assert_eq!(events.iter().filter(|event| event.p == 1).count(), 36);
+        assert_eq!(events.iter().filter(|event| event.p == 0).count(), 16);
+        assert!(events.windows(2).all(|pair| pair[0].t <= pair[1].t));
+
+        let params = DvsSimParams::default().noise_rate(1_000_000.0).seed(1);
+        let frames = (0..3_u32).map(|i| (Array3::from_elem((1, 2, 1), 50.0), i * 10_000));
+        let events: Vec<DvsEvent> = DvsSimulator::new(plane(), params)
+            .simulate_frames(frames)
+            .collect();
+        assert_eq!(events.len(), 4);
+    }
+
+    #[test]
+    fn adder_events_truncated() -> Result<(), Box<dyn std::error::Error>> {
+        use adder_codec_core::codec::encoder::Encoder;
+        use adder_codec_core::codec::raw::stream::RawOutput;
+        use adder_codec_core::codec::EncoderOptions;
+        use adder_codec_core::{Coord, SourceCamera};
+        use std::fs::OpenOptions;
+        use std::io::BufWriter;
+
+        let path = std::env::temp_dir().join(format!("adder_dvs_sim_{}.adder", std::process::id()));
+        let compression = RawOutput::new(
+            CodecMetadata {
+                codec_version: 2,
+                header_size: 0,
+                time_mode: TimeMode::DeltaT,
+                plane: plane(),
+                tps: 255 * 30,
+                ref_interval: 255,
+                delta_t_max: 255 * 30,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
+            },
+            BufWriter::new(File::create(&path)?),
+        );
+        let mut stream = Encoder::new_raw(compression, EncoderOptions::default(plane()));
+
+        // Each pixel alternates between two intensities every frame
+        for i in 0..20 {
+            for x in 0..2 {
+                stream.ingest_event(Event {
+                    coord: Coord { x, y: 0, c: None },
+                    d: if i % 2 == 0 { 5 } else { 7 },
+                    t: 255,
+                })?;
+            }
+        }
+        if let Some(mut writer) = stream.close_writer()? {
+            writer.flush()?;
+        }
+
+        let events: Vec<_> =
+            AdderDvsEvents::new(path.to_str().unwrap(), DvsSimParams::default())?.collect();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(Result::is_ok));
+
+        // Cut the stream off partway through its end marker
+        let len = std::fs::metadata(&path)?.len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len - 3)?;
+        let events: Vec<_> =
+            AdderDvsEvents::new(path.to_str().unwrap(), DvsSimParams::default())?.collect();
+        std::fs::remove_file(&path)?;
+        let (last, rest) = events.split_last().unwrap();
+        assert!(last.is_err());
+        assert!(rest.iter().all(Result::is_ok));
+        Ok(())
+    }
+}
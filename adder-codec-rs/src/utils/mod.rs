@@ -2,6 +2,12 @@
 /// video from ADΔER
 pub mod simulproc;
 
+/// A module for simulating DVS events from framed sources or ADΔER streams
+pub mod dvs_sim;
+
+/// A module for reading and writing ADΔER and DVS events as NumPy `.npy` and `.npz` arrays
+pub mod npy;
+
 /// A module for migrating streams from one format to another
 pub mod stream_migration;
 
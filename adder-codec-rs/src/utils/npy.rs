@@ -0,0 +1,588 @@
+use crate::transcoder::source::dvs::DvsEvent;
+use adder_codec_core::{Coord, Event};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// The length of the header of the arrays written by [`NpyEventWriter`], including the magic
+/// string, version and header length. It's fixed so that the header can be rewritten with the
+/// final array length.
+const WRITE_HEADER_LEN: usize = 256;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// A field of a NumPy dtype
+#[derive(Debug, Clone, PartialEq)]
+struct NpyField {
+    name: String,
+    kind: char,
+    size: usize,
+    big_endian: bool,
+    offset: usize,
+}
+
+impl NpyField {
+    /// Parse a type string such as `<u2` or `|b1`
+    fn parse(name: String, type_str: &str, offset: usize) -> io::Result<Self> {
+        let mut chars = type_str.chars().peekable();
+        let big_endian = match chars.peek() {
+            Some('>') => true,
+            Some('<' | '|' | '=') => false,
+            _ => return Err(invalid_data(format!("Unsupported dtype {type_str}"))),
+        };
+        chars.next();
+        let kind = chars.next().unwrap_or(' ');
+        let size: usize = chars.collect::<String>().parse().unwrap_or(0);
+        match (kind, size) {
+            ('u' | 'i', 1 | 2 | 4 | 8) | ('f', 4 | 8) | ('b', 1) => Ok(Self {
+                name,
+                kind,
+                size,
+                big_endian,
+                offset,
+            }),
+            _ => Err(invalid_data(format!("Unsupported dtype {type_str}"))),
+        }
+    }
+
+    /// Read the field's value from a record
+    fn value(&self, record: &[u8]) -> f64 {
+        let mut bytes = [0; 8];
+        let field = &record[self.offset..self.offset + self.size];
+        bytes[..self.size].copy_from_slice(field);
+        if self.big_endian {
+            bytes[..self.size].reverse();
+        }
+        let unsigned = u64::from_le_bytes(bytes);
+        match (self.kind, self.size) {
+            ('f', 4) => f64::from(f32::from_bits(unsigned as u32)),
+            ('f', _) => f64::from_bits(unsigned),
+            ('i', size) => {
+                // Sign-extend from the field's size
+                let shift = 64 - size * 8;
+                ((unsigned << shift) as i64 >> shift) as f64
+            }
+            _ => unsigned as f64,
+        }
+    }
+}
+
+/// One record of a NumPy array, with its fields accessible by name. The columns of a
+/// 2-dimensional array are named by their index.
+pub struct NpyRecord<'a> {
+    fields: &'a [NpyField],
+    bytes: &'a [u8],
+}
+
+impl NpyRecord<'_> {
+    /// Get the value of the named field, if it exists
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value(self.bytes))
+    }
+
+    /// Get the value of the first of the given fields which exists
+    pub fn get_any(&self, names: &[&str]) -> Option<f64> {
+        names.iter().find_map(|name| self.get(name))
+    }
+
+    /// Get the number of fields in the record
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+}
+
+/// An event type which can be stored as a record of a structured NumPy array
+pub trait NpyEvent: Sized {
+    /// The NumPy `descr` of the record. `color` is whether the events have a channel field.
+    fn descr(color: bool) -> String;
+
+    /// Write the event as a record
+    fn write_record<W: Write>(&self, color: bool, writer: &mut W) -> io::Result<()>;
+
+    /// Read an event from a record, or `None` if the record lacks a required field
+    fn read_record(record: &NpyRecord) -> Option<Self>;
+}
+
+/// ADΔER events are stored as `x`, `y`, `c` (only for color streams), `d` and `t`. A
+/// 2-dimensional array is read as columns in that order.
+impl NpyEvent for Event {
+    fn descr(color: bool) -> String {
+        if color {
+            "[('x', '<u2'), ('y', '<u2'), ('c', '|u1'), ('d', '|u1'), ('t', '<u4')]".to_string()
+        } else {
+            "[('x', '<u2'), ('y', '<u2'), ('d', '|u1'), ('t', '<u4')]".to_string()
+        }
+    }
+
+    fn write_record<W: Write>(&self, color: bool, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.coord.x.to_le_bytes())?;
+        writer.write_all(&self.coord.y.to_le_bytes())?;
+        if color {
+            writer.write_all(&[self.coord.c.unwrap_or(0)])?;
+        }
+        writer.write_all(&[self.d])?;
+        writer.write_all(&self.t.to_le_bytes())
+    }
+
+    fn read_record(record: &NpyRecord) -> Option<Self> {
+        let (x, y, c, d, t) = if record.get("x").is_some() {
+            (
+                record.get("x")?,
+                record.get("y")?,
+                record.get("c"),
+                record.get("d")?,
+                record.get("t")?,
+            )
+        } else if record.field_count() == 5 {
+            (
+                record.get("0")?,
+                record.get("1")?,
+                record.get("2"),
+                record.get("3")?,
+                record.get("4")?,
+            )
+        } else {
+            (
+                record.get("0")?,
+                record.get("1")?,
+                None,
+                record.get("2")?,
+                record.get("3")?,
+            )
+        };
+        Some(Event {
+            coord: Coord {
+                x: x as u16,
+                y: y as u16,
+                c: c.map(|c| c as u8),
+            },
+            d: d as u8,
+            t: t as u32,
+        })
+    }
+}
+
+/// DVS events are stored as `t` (microseconds), `x`, `y` and `p`. A 2-dimensional array is read
+/// as columns in that order.
+impl NpyEvent for DvsEvent {
+    fn descr(_color: bool) -> String {
+        "[('t', '<i8'), ('x', '<u2'), ('y', '<u2'), ('p', '|u1')]".to_string()
+    }
+
+    fn write_record<W: Write>(&self, _color: bool, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&i64::from(self.t).to_le_bytes())?;
+        writer.write_all(&self.x.to_le_bytes())?;
+        writer.write_all(&self.y.to_le_bytes())?;
+        writer.write_all(&[self.p])
+    }
+
+    fn read_record(record: &NpyRecord) -> Option<Self> {
+        read_dvs_record(record, |t| Ok(t.round() as u32))
+            .ok()
+            .flatten()
+    }
+}
+
+/// Read a DVS event from a record, converting its timestamp to microseconds with `to_micros`,
+/// or `None` if the record lacks a required field. The fields may also be named `ts`/`timestamp`
+/// and `pol`/`polarity`, and any polarity greater than 0 is an increase in intensity.
+pub(crate) fn read_dvs_record(
+    record: &NpyRecord,
+    to_micros: impl FnOnce(f64) -> io::Result<u32>,
+) -> io::Result<Option<DvsEvent>> {
+    let (Some(t), Some(x), Some(y), Some(p)) = (
+        record.get_any(&["t", "ts", "timestamp", "0"]),
+        record.get_any(&["x", "1"]),
+        record.get_any(&["y", "2"]),
+        record.get_any(&["p", "polarity", "pol", "3"]),
+    ) else {
+        return Ok(None);
+    };
+    Ok(Some(DvsEvent {
+        t: to_micros(t)?,
+        x: x as u16,
+        y: y as u16,
+        p: u8::from(p > 0.0),
+    }))
+}
+
+/// Writes events to a `.npy` array, one record at a time. The array length is written to the
+/// header when the writer is finished.
+pub struct NpyEventWriter<W: Write + Seek, E: NpyEvent> {
+    writer: W,
+    color: bool,
+    start: u64,
+    len: u64,
+    _phantom: std::marker::PhantomData<E>,
+}
+
+impl<W: Write + Seek, E: NpyEvent> NpyEventWriter<W, E> {
+    /// Start writing an array. `color` is whether ADΔER events have a channel field.
+    pub fn new(mut writer: W, color: bool) -> io::Result<Self> {
+        let start = writer.stream_position()?;
+        writer.write_all(&npy_header(&E::descr(color), 0))?;
+        Ok(Self {
+            writer,
+            color,
+            start,
+            len: 0,
+            _phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Append an event to the array
+    pub fn write_event(&mut self, event: &E) -> io::Result<()> {
+        event.write_record(self.color, &mut self.writer)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Get the number of events written so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether no events have been written
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Write the final array length to the header, and return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        self.writer
+            .write_all(&npy_header(&E::descr(self.color), self.len))?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Build a version 1.0 `.npy` header of [`WRITE_HEADER_LEN`] bytes for a 1-dimensional array
+fn npy_header(descr: &str, len: u64) -> Vec<u8> {
+    let mut dict = format!("{{'descr': {descr}, 'fortran_order': False, 'shape': ({len},), }}");
+    let dict_len = WRITE_HEADER_LEN - NPY_MAGIC.len() - 4;
+    while dict.len() < dict_len - 1 {
+        dict.push(' ');
+    }
+    dict.push('\n');
+
+    let mut header = Vec::with_capacity(WRITE_HEADER_LEN);
+    header.extend_from_slice(NPY_MAGIC);
+    header.extend_from_slice(&[1, 0]);
+    header.extend_from_slice(&(dict_len as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+/// Reads the records of a `.npy` array, either a 1-dimensional structured array or a
+/// 2-dimensional array of one numeric type
+pub struct NpyReader<R: Read> {
+    reader: R,
+    fields: Vec<NpyField>,
+    record: Vec<u8>,
+    len: u64,
+    remaining: u64,
+}
+
+impl<R: Read> NpyReader<R> {
+    /// Parse the header of an array
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic[..6] != NPY_MAGIC {
+            return Err(invalid_data("Not a .npy file"));
+        }
+        let header_len = if magic[6] == 1 {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        } else {
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        };
+        let mut header = vec![0; header_len];
+        reader.read_exact(&mut header)?;
+        let header = String::from_utf8_lossy(&header);
+
+        let shape: Vec<u64> = dict_value(&header, "shape")
+            .and_then(|shape| {
+                let shape = &shape[shape.find('(')? + 1..shape.find(')')?];
+                shape
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|dim| !dim.is_empty())
+                    .map(|dim| dim.parse().ok())
+                    .collect()
+            })
+            .ok_or_else(|| invalid_data("Invalid array shape"))?;
+        // A 1-dimensional array is laid out the same in either order
+        if shape.len() > 1
+            && dict_value(&header, "fortran_order").is_some_and(|order| order.starts_with("True"))
+        {
+            return Err(invalid_data("Fortran-ordered arrays are not supported"));
+        }
+        let descr = dict_value(&header, "descr").ok_or_else(|| invalid_data("Missing dtype"))?;
+
+        let fields = if descr.starts_with('[') {
+            if shape.len() != 1 {
+                return Err(invalid_data("Structured arrays must be 1-dimensional"));
+            }
+            let strings = quoted_strings(&descr[..descr.find(']').unwrap_or(descr.len())]);
+            let mut offset = 0;
+            let mut fields = Vec::new();
+            for pair in strings.chunks_exact(2) {
+                let field = NpyField::parse(pair[0].clone(), &pair[1], offset)?;
+                offset += field.size;
+                fields.push(field);
+            }
+            fields
+        } else {
+            let type_str = quoted_strings(descr)
+                .into_iter()
+                .next()
+                .ok_or_else(|| invalid_data("Missing dtype"))?;
+            let columns = match shape.len() {
+                1 => 1,
+                2 => shape[1] as usize,
+                _ => return Err(invalid_data("Arrays must be 1- or 2-dimensional")),
+            };
+            let mut fields = Vec::new();
+            for column in 0..columns {
+                let field = NpyField::parse(column.to_string(), &type_str, 0)?;
+                fields.push(NpyField {
+                    offset: column * field.size,
+                    ..field
+                });
+            }
+            fields
+        };
+
+        let record_size = fields.iter().map(|field| field.size).sum();
+        let len = shape.first().copied().unwrap_or(0);
+        Ok(Self {
+            reader,
+            fields,
+            record: vec![0; record_size],
+            len,
+            remaining: len,
+        })
+    }
+
+    /// Get the number of records in the array
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the array has no records
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read the next record, or `None` at the end of the array
+    pub fn next_record(&mut self) -> io::Result<Option<NpyRecord>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut self.record)?;
+        self.remaining -= 1;
+        Ok(Some(NpyRecord {
+            fields: &self.fields,
+            bytes: &self.record,
+        }))
+    }
+
+    /// Read the next event, or `None` at the end of the array
+    pub fn next_event<E: NpyEvent>(&mut self) -> io::Result<Option<E>> {
+        match self.next_record()? {
+            None => Ok(None),
+            Some(record) => E::read_record(&record)
+                .map(Some)
+                .ok_or_else(|| invalid_data("The array is missing event fields")),
+        }
+    }
+}
+
+/// Get the text of a value in a Python dict literal, up to the end of the dict
+fn dict_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let key_start = header
+        .find(&format!("'{key}'"))
+        .or_else(|| header.find(&format!("\"{key}\"")))?;
+    let rest = &header[key_start + key.len() + 2..];
+    Some(rest[rest.find(':')? + 1..].trim_start())
+}
+
+/// Get the contents of the quoted strings in some text
+fn quoted_strings(text: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut current: Option<(char, String)> = None;
+    for ch in text.chars() {
+        match &mut current {
+            Some((quote, string)) => {
+                if ch == *quote {
+                    strings.push(std::mem::take(string));
+                    current = None;
+                } else {
+                    string.push(ch);
+                }
+            }
+            None if ch == '\'' || ch == '"' => current = Some((ch, String::new())),
+            None => {}
+        }
+    }
+    strings
+}
+
+/// Write events to a `.npz` archive as the compressed array `{name}.npy`, like
+/// `numpy.savez_compressed`
+pub fn write_events_npz<E: NpyEvent, W: Write + Seek>(
+    writer: W,
+    name: &str,
+    events: impl IntoIterator<Item = E>,
+    color: bool,
+) -> io::Result<W> {
+    let mut npy = NpyEventWriter::<_, E>::new(Cursor::new(Vec::new()), color)?;
+    for event in events {
+        npy.write_event(&event)?;
+    }
+    let npy = npy.finish()?.into_inner();
+
+    let mut zip = ZipWriter::new(writer);
+    zip.start_file(
+        format!("{name}.npy"),
+        FileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    zip.write_all(&npy)?;
+    let mut writer = zip.finish()?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// Read the bytes of an array of a `.npz` archive: `{name}.npy` if a name is given, or else the
+/// first array
+pub fn read_npz_array<R: Read + Seek>(reader: R, name: Option<&str>) -> io::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(reader)?;
+    let file_name = match name {
+        Some(name) => format!("{name}.npy"),
+        None => archive
+            .file_names()
+            .filter(|file_name| file_name.ends_with(".npy"))
+            .min()
+            .ok_or_else(|| invalid_data("The archive has no arrays"))?
+            .to_string(),
+    };
+    let mut file = archive.by_name(&file_name)?;
+    let mut bytes = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Open an array of a `.npz` archive: `{name}.npy` if a name is given, or else the first array
+pub fn open_npz_array<R: Read + Seek>(
+    reader: R,
+    name: Option<&str>,
+) -> io::Result<NpyReader<Cursor<Vec<u8>>>> {
+    NpyReader::new(Cursor::new(read_npz_array(reader, name)?))
+}
+
+/// Read all the events of a `.npy` array
+pub fn read_events_npy<E: NpyEvent, R: Read>(reader: R) -> io::Result<Vec<E>> {
+    let mut npy = NpyReader::new(reader)?;
+    let mut events = Vec::with_capacity(npy.len() as usize);
+    while let Some(event) = npy.next_event()? {
+        events.push(event);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adder_round_trip() -> io::Result<()> {
+        let events = vec![
+            Event {
+                coord: Coord {
+                    x: 3,
+                    y: 4,
+                    c: Some(2),
+                },
+                d: 7,
+                t: 255,
+            },
+            Event {
+                coord: Coord {
+                    x: 300,
+                    y: 1,
+                    c: Some(0),
+                },
+                d: 128,
+                t: 70000,
+            },
+        ];
+
+        let mut writer = NpyEventWriter::new(Cursor::new(Vec::new()), true)?;
+        for event in &events {
+            writer.write_event(event)?;
+        }
+        let bytes = writer.finish()?.into_inner();
+        assert_eq!(bytes.len(), WRITE_HEADER_LEN + 2 * 10);
+        assert_eq!(read_events_npy::<Event, _>(Cursor::new(&bytes))?, events);
+
+        let npz = write_events_npz(Cursor::new(Vec::new()), "events", events.clone(), true)?;
+        let mut reader = open_npz_array(Cursor::new(npz.into_inner()), None)?;
+        assert_eq!(reader.len(), 2);
+        assert_eq!(reader.next_event::<Event>()?, Some(events[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn read_numpy_arrays() -> io::Result<()> {
+        // A structured array as written by NumPy: float seconds, big-endian coordinates, and a
+        // bool polarity
+        let dict = "{'descr': [('ts', '<f8'), ('x', '>u2'), ('y', '>u2'), ('p', '|b1')], \
+                    'fortran_order': False, 'shape': (1,), }\n";
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(dict.as_bytes());
+        bytes.extend_from_slice(&1500.0_f64.to_le_bytes());
+        bytes.extend_from_slice(&7_u16.to_be_bytes());
+        bytes.extend_from_slice(&9_u16.to_be_bytes());
+        bytes.push(1);
+        assert_eq!(
+            read_events_npy::<DvsEvent, _>(Cursor::new(&bytes))?,
+            vec![DvsEvent {
+                t: 1500,
+                x: 7,
+                y: 9,
+                p: 1
+            }]
+        );
+
+        // A 2-dimensional array of signed integers, with columns t, x, y, p
+        let dict = "{'descr': '<i4', 'fortran_order': False, 'shape': (2, 4), }\n";
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(dict.as_bytes());
+        for value in [10_i32, 1, 2, 1, 20, 3, 4, -1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let events = read_events_npy::<DvsEvent, _>(Cursor::new(&bytes))?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].t, 20);
+        assert_eq!(events[1].p, 0);
+        Ok(())
+    }
+}
@@ -83,6 +83,19 @@ pub struct SimulProcArgs {
 
     #[clap(long, default_value = "")]
     pub integration_mode: String,
+
+    /// Use the container's presentation timestamps to set the span of each frame, for variable
+    /// frame rate videos
+    #[clap(long, action)]
+    #[serde(default)]
+    pub use_timestamps: bool,
+
+    /// Transcode only these regions of the (scaled) input frames, given as `x,y,width,height`
+    /// rectangles separated by `;`. The output is sized to the bounding box of the regions.
+    /// Empty to transcode the whole frame.
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub crop: String,
 }
 
 /// A struct for simultaneously transcoding a video source to ADΔER and reconstructing a framed
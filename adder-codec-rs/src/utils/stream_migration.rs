@@ -126,6 +126,8 @@ mod tests {
                 event_size: 0,
                 source_camera: FramedU8,
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
@@ -177,6 +179,8 @@ mod tests {
                 event_size: 0,
                 source_camera: FramedU8,
                 adu_interval: 1,
+                lossless: false,
+                entropy_coder: Default::default(),
             },
             bufwriter,
         );
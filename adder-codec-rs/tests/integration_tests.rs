@@ -262,6 +262,8 @@ fn setup_raw_writer_v0(rand_num: u32) -> Encoder<BufWriter<File>> {
             event_size: 0,
             source_camera: Default::default(),
             adu_interval: 1,
+            lossless: false,
+            entropy_coder: Default::default(),
         },
         bufwriter,
     );
@@ -288,6 +290,8 @@ fn setup_raw_writer_v1(rand_num: u32) -> Encoder<BufWriter<File>> {
             event_size: 0,
             source_camera: FramedU8,
             adu_interval: 1,
+            lossless: false,
+            entropy_coder: Default::default(),
         },
         bufwriter,
     );
@@ -314,6 +318,8 @@ fn setup_raw_writer_v2(rand_num: u32) -> Encoder<BufWriter<File>> {
             event_size: 0,
             source_camera: FramedU8,
             adu_interval: 1,
+            lossless: false,
+            entropy_coder: Default::default(),
         },
         bufwriter,
     );
@@ -31,8 +31,12 @@ use adder_codec_rs::adder_codec_core::SourceCamera::{DavisU8, Dvs, FramedU8};
 use adder_codec_rs::adder_codec_core::{Event, PlaneError};
 #[cfg(feature = "open-cv")]
 use adder_codec_rs::davis_edi_rs::util::reconstructor::ReconstructorError;
+#[cfg(not(feature = "open-cv"))]
+use adder_codec_rs::transcoder::source::aedat4::Aedat4;
+#[cfg(not(feature = "open-cv"))]
+use adder_codec_rs::transcoder::source::dvs::DvsParams;
 use adder_codec_rs::transcoder::source::prophesee::Prophesee;
-use adder_codec_rs::transcoder::source::video::SourceError::{NoData, VideoError};
+use adder_codec_rs::transcoder::source::video::SourceError::{EndOfStream, NoData, VideoError};
 use adder_codec_rs::transcoder::source::video::{Source, SourceError, VideoBuilder};
 use adder_codec_rs::transcoder::source::AdderSource;
 use adder_codec_rs::utils::cv::{calculate_quality_metrics, QualityMetrics};
@@ -161,7 +165,8 @@ impl AdderTranscoder {
                     AdderTranscoderError::SourceError(VideoError(
                         video_rs_adder_dep::Error::ReadExhausted,
                     ))
-                    | AdderTranscoderError::SourceError(NoData) => {
+                    | AdderTranscoderError::SourceError(NoData)
+                    | AdderTranscoderError::SourceError(EndOfStream) => {
                         let mut state = self.transcoder_state.clone();
                         self.source
                             .as_mut()
@@ -407,8 +412,13 @@ impl AdderTranscoder {
                         let ext = ext.to_os_string();
                         self.create_davis(transcoder_state, ext).await
                     }
-                    "dat" => {
-                        // Prophesee video
+                    #[cfg(not(feature = "open-cv"))]
+                    "aedat4" => {
+                        // DVS events only, without OpenCV
+                        self.create_aedat4(transcoder_state).await
+                    }
+                    "dat" | "raw" => {
+                        // Prophesee video (legacy .dat, or EVT 2.0/3.0 .raw)
                         self.create_prophesee(transcoder_state).await
                     }
                     _ => Err(InvalidFileType),
@@ -690,4 +700,66 @@ impl AdderTranscoder {
 
         Ok(())
     }
+
+    #[cfg(not(feature = "open-cv"))]
+    async fn create_aedat4(
+        &mut self,
+        transcoder_state: TranscoderState,
+    ) -> Result<(), AdderTranscoderError> {
+        self.update_params(transcoder_state);
+
+        let core_params = &mut self.transcoder_state.core_params;
+        let adaptive_params = &self.transcoder_state.adaptive_params;
+
+        let output_string = core_params
+            .output_path
+            .clone()
+            .map(|output_path| output_path.to_str().expect("Bad path").to_string());
+
+        let mut aedat4_source: Aedat4<BufWriter<File>> = Aedat4::new(
+            core_params.delta_t_ref as u32,
+            core_params
+                .input_path_buf_0
+                .clone()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string(),
+            DvsParams::default(),
+        )?
+        .crf(
+            adaptive_params
+                .encoder_options
+                .crf
+                .get_quality()
+                .unwrap_or(DEFAULT_CRF_QUALITY),
+        );
+        let adu_interval = (aedat4_source.get_video_ref().state.tps as f32
+            / core_params.delta_t_ref as f32) as usize;
+
+        if let Some(output_string) = output_string {
+            let writer = BufWriter::new(File::create(output_string)?);
+            aedat4_source = *aedat4_source.write_out(
+                Dvs,
+                core_params.time_mode,
+                core_params.integration_mode_radio_state,
+                Some(adu_interval),
+                core_params.encoder_type,
+                adaptive_params.encoder_options,
+                writer,
+            )?;
+        }
+
+        core_params.delta_t_max_mult = aedat4_source.get_video_ref().get_delta_t_max()
+            / aedat4_source.get_video_ref().state.params.ref_time as u32;
+
+        self.source = Some(AdderSource::Aedat4(aedat4_source));
+
+        self.adaptive_state_update()?;
+        self.last_consume_time = std::time::Instant::now();
+
+        eprintln!("AEDAT4 source created!");
+
+        Ok(())
+    }
 }
@@ -1,6 +1,8 @@
 use crate::utils::PlotY;
 use adder_codec_rs::adder_codec_core::codec::rate_controller::{Crf, DEFAULT_CRF_QUALITY};
-use adder_codec_rs::adder_codec_core::codec::{EncoderOptions, EncoderType};
+use adder_codec_rs::adder_codec_core::codec::{
+    EncoderOptions, EncoderType, DEFAULT_MAX_QUEUED_EVENTS,
+};
 use adder_codec_rs::adder_codec_core::{PixelMultiMode, TimeMode};
 #[cfg(feature = "open-cv")]
 use adder_codec_rs::transcoder::source::davis::TranscoderMode;
@@ -80,6 +82,10 @@ impl Default for AdaptiveParams {
                 event_drop: Default::default(),
                 event_order: Default::default(),
                 crf: Crf::new(None, Default::default()),
+                channel_decorrelation: false,
+                lossless: false,
+                entropy_coder: Default::default(),
+                max_queued_events: DEFAULT_MAX_QUEUED_EVENTS,
             },
             thread_count: 1,
             show_original: false,
@@ -370,7 +370,7 @@ impl TranscoderUi {
                 if let Some(path) = rfd::FileDialog::new()
                     .add_filter("framed video", &["mp4", "mkv", "avi", "mov"])
                     .add_filter("DVS/DAVIS video", &["aedat4"])
-                    .add_filter("Prophesee video", &["dat"])
+                    .add_filter("Prophesee video", &["dat", "raw"])
                     .pick_file()
                 {
                     eprintln!("Updating input path: {:?}", path);
@@ -897,6 +897,16 @@ impl TranscoderUi {
         });
         ui.end_row();
 
+        ui.label("Color channel decorrelation:");
+        ui.add_enabled(
+            core_params.encoder_type == EncoderType::Compressed,
+            egui::Checkbox::new(
+                &mut adaptive_params.encoder_options.channel_decorrelation,
+                "Predict channels 1 and 2 from channel 0",
+            ),
+        );
+        ui.end_row();
+
         ui.label("Bandwidth limiting:");
         ui.add_enabled_ui(true, |ui| {
             ui.horizontal(|ui| {